@@ -0,0 +1,11 @@
+insert into lms_metadata (key, value) values ('loan_period', '30'); -- days
+
+alter table lms_instance add column status integer not null default 0;
+
+alter table lms_occupation add column due_date text default null;
+
+update lms_occupation
+    set due_date = date(date, '+30 days')
+    where kind = 0;
+
+create index lms_borrow_due_date on lms_occupation (due_date);
@@ -0,0 +1,3 @@
+insert into lms_metadata (key, value) values ('renewal_limit', '2');
+
+alter table lms_occupation add column renewals integer not null default 0;
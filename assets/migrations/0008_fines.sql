@@ -0,0 +1,22 @@
+insert into lms_metadata (key, value) values ('fine_daily_rate', '10'); -- cents per day late
+insert into lms_metadata (key, value) values ('fine_replacement', '2000'); -- cents per lost instance
+insert into lms_metadata (key, value) values ('fine_borrow_limit', '1000'); -- cents unpaid before borrowing is blocked
+
+create table lms_fine (
+    fid integer primary key autoincrement,
+    uid integer not null,
+    iid integer default null,
+    kind integer not null,
+    amount integer not null,
+    date text not null,
+    status integer not null default 0,
+    settle_date text default null,
+    note text not null default '',
+    foreign key (uid) references lms_user (uid),
+    foreign key (iid) references lms_instance (iid),
+    check (kind in (0, 1, 2)), -- 0: overdue, 1: lost, 2: damaged
+    check (status in (0, 1, 2)) -- 0: unpaid, 1: paid, 2: waived
+);
+
+create index lms_fine_uid on lms_fine (uid);
+create index lms_fine_status on lms_fine (status);
@@ -0,0 +1,24 @@
+insert into lms_metadata (key, value) values ('hold_shelf_days', '7'); -- days a held copy waits for pickup
+
+create table lms_hold (
+    hid integer primary key autoincrement,
+    uid integer not null,
+    bid integer not null,
+    iid integer default null,
+    date text not null,
+    expiry_date text default null,
+    status integer not null default 0,
+    foreign key (uid) references lms_user (uid),
+    foreign key (bid) references lms_book (bid),
+    foreign key (iid) references lms_instance (iid),
+    check (status in (0, 1, 2, 3, 4)) -- 0: waiting, 1: ready, 2: fulfilled, 3: cancelled, 4: expired
+);
+
+create index lms_hold_uid on lms_hold (uid);
+create index lms_hold_bid_status on lms_hold (bid, status);
+
+-- Copy reservations (occupation kind 1) become ready holds waiting on the hold shelf.
+insert into lms_hold (uid, bid, iid, date, expiry_date, status)
+    select o.uid, i.bid, o.iid, o.date, date('now', '+7 days'), 1
+    from lms_occupation o join lms_instance i on i.iid = o.iid
+    where o.kind = 1 and o.uid is not null;
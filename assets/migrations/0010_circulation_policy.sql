@@ -0,0 +1,49 @@
+insert into lms_metadata (key, value) values ('max_loans', '10');
+insert into lms_metadata (key, value) values ('max_holds', '5');
+
+create table lms_category (
+    cid integer primary key autoincrement,
+    name text not null unique,
+    info text not null
+);
+
+insert into lms_category (cid, name, info) values (1, 'student', '');
+insert into lms_category (cid, name, info) values (2, 'staff', '');
+insert into lms_category (cid, name, info) values (3, 'guest', '');
+
+create table lms_item_type (
+    tid integer primary key autoincrement,
+    name text not null unique,
+    info text not null
+);
+
+insert into lms_item_type (tid, name, info) values (1, 'book', '');
+insert into lms_item_type (tid, name, info) values (2, 'reference', 'in-library use only');
+insert into lms_item_type (tid, name, info) values (3, 'media', '');
+
+create table lms_policy (
+    cid integer not null,
+    tid integer not null,
+    max_loans integer not null,
+    loan_period integer not null, -- days
+    renewal_limit integer not null,
+    max_holds integer not null,
+    fine_daily_rate integer not null, -- cents per day late
+    primary key (cid, tid),
+    foreign key (cid) references lms_category (cid),
+    foreign key (tid) references lms_item_type (tid)
+);
+
+insert into lms_policy values (1, 1, 10, 30, 2, 5, 10);
+insert into lms_policy values (1, 2, 0, 0, 0, 0, 0);
+insert into lms_policy values (1, 3, 3, 7, 1, 2, 50);
+insert into lms_policy values (2, 1, 30, 90, 5, 10, 10);
+insert into lms_policy values (2, 2, 5, 7, 1, 2, 10);
+insert into lms_policy values (2, 3, 10, 14, 2, 5, 50);
+insert into lms_policy values (3, 1, 2, 14, 0, 1, 20);
+insert into lms_policy values (3, 2, 0, 0, 0, 0, 0);
+insert into lms_policy values (3, 3, 0, 0, 0, 0, 0);
+
+alter table lms_user add column cid integer not null default 1 references lms_category (cid);
+
+alter table lms_book add column tid integer not null default 1 references lms_item_type (tid);
@@ -0,0 +1,18 @@
+insert into lms_metadata (key, value) values ('session_hours', '24');
+
+create table lms_credential (
+    uid integer primary key,
+    hash text not null, -- argon2 PHC string, salt included
+    foreign key (uid) references lms_user (uid)
+);
+
+create table lms_session (
+    token text primary key,
+    uid integer not null,
+    created text not null,
+    expires text not null,
+    foreign key (uid) references lms_user (uid)
+);
+
+create index lms_session_uid on lms_session (uid);
+create index lms_session_expires on lms_session (expires);
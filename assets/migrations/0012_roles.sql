@@ -0,0 +1,2 @@
+-- 0 patron, 1 circulation, 2 librarian, 3 administrator
+alter table lms_user add column role integer not null default 0;
@@ -0,0 +1,33 @@
+create virtual table lms_book_fts using fts5 (
+    title,
+    author,
+    info,
+    content = 'lms_book',
+    content_rowid = 'bid',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+insert into lms_book_fts (lms_book_fts) values ('rebuild');
+
+create trigger lms_book_fts_insert
+    after insert on lms_book
+    begin
+        insert into lms_book_fts (rowid, title, author, info)
+        values (new.bid, new.title, new.author, new.info);
+    end;
+
+create trigger lms_book_fts_delete
+    after delete on lms_book
+    begin
+        insert into lms_book_fts (lms_book_fts, rowid, title, author, info)
+        values ('delete', old.bid, old.title, old.author, old.info);
+    end;
+
+create trigger lms_book_fts_update
+    after update of title, author, info on lms_book
+    begin
+        insert into lms_book_fts (lms_book_fts, rowid, title, author, info)
+        values ('delete', old.bid, old.title, old.author, old.info);
+        insert into lms_book_fts (rowid, title, author, info)
+        values (new.bid, new.title, new.author, new.info);
+    end;
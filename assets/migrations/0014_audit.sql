@@ -0,0 +1,25 @@
+create table lms_audit (
+    aid integer primary key autoincrement,
+    actor integer default null, -- uid of the caller, null for anonymous requests
+    endpoint text not null,
+    entity text not null,
+    target integer default null,
+    before text default null, -- JSON snapshot of the target, null if it did not exist
+    after text default null,
+    date text not null
+);
+
+create index lms_audit_actor on lms_audit (actor);
+create index lms_audit_entity on lms_audit (entity, target);
+create index lms_audit_date on lms_audit (date);
+
+-- The log is append-only.
+create trigger lms_audit_no_update before update on lms_audit
+begin
+    select raise(abort, 'lms_audit is append-only');
+end;
+
+create trigger lms_audit_no_delete before delete on lms_audit
+begin
+    select raise(abort, 'lms_audit is append-only');
+end;
@@ -0,0 +1,2 @@
+-- Inactive (suspended) users keep their records and may return items, but cannot borrow or reserve.
+alter table lms_user add column active integer not null default 1 check (active in (0, 1));
@@ -0,0 +1,24 @@
+-- Withdrawn instances stay on record for their history but never circulate again.
+alter table lms_instance add column withdrawn_date text default null;
+
+create table lms_instance_event (
+    eid integer primary key autoincrement,
+    iid integer not null,
+    kind integer not null,
+    date text not null,
+    due_date text default null, -- expected back from repair
+    uid integer default null, -- borrower billed for or credited with the instance
+    fid integer default null,
+    note text not null default '',
+    foreign key (iid) references lms_instance (iid),
+    foreign key (uid) references lms_user (uid),
+    foreign key (fid) references lms_fine (fid),
+    check (kind in (0, 1, 2, 3, 4, 5)) -- 0: sent to repair, 1: repaired, 2: beyond repair, 3: lost, 4: found, 5: withdrawn
+);
+
+create index lms_instance_event_iid on lms_instance_event (iid);
+
+-- Copies already in maintenance or lost start their history here.
+insert into lms_instance_event (iid, kind, date, uid)
+    select iid, case kind when 2 then 0 else 3 end, date, uid
+    from lms_occupation where kind in (2, 3);
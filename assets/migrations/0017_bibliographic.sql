@@ -0,0 +1,16 @@
+-- ISBNs are stored as normalized ISBN-13 digits; duplicates are allowed but warned about.
+alter table lms_book add column isbn text default null;
+alter table lms_book add column publisher text not null default '';
+alter table lms_book add column year integer not null default 0; -- 0: unknown
+alter table lms_book add column edition text not null default '';
+alter table lms_book add column language text not null default '';
+alter table lms_book add column pages integer not null default 0; -- 0: unknown
+
+create index lms_book_isbn on lms_book (isbn);
+
+create table lms_book_subject (
+    bid integer not null,
+    subject text not null,
+    primary key (bid, subject),
+    foreign key (bid) references lms_book (bid) on delete cascade
+);
@@ -0,0 +1,25 @@
+create table lms_author (
+    aid integer primary key autoincrement,
+    name text not null unique collate nocase
+);
+
+create table lms_book_author (
+    bid integer not null,
+    aid integer not null,
+    role integer not null default 0,
+    position integer not null default 0, -- order of the contributors on the title page
+    primary key (bid, aid, role),
+    foreign key (bid) references lms_book (bid) on delete cascade,
+    foreign key (aid) references lms_author (aid),
+    check (role in (0, 1, 2, 3)) -- 0: author, 1: editor, 2: translator, 3: illustrator
+);
+
+create index lms_book_author_aid on lms_book_author (aid);
+
+-- Each distinct author string becomes one author of its books.
+insert or ignore into lms_author (name)
+    select trim(author) from lms_book where trim(author) != '' order by bid;
+
+insert into lms_book_author (bid, aid, role, position)
+    select b.bid, a.aid, 0, 0
+    from lms_book b join lms_author a on a.name = trim(b.author);
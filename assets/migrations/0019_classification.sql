@@ -0,0 +1,16 @@
+-- Call numbers sort as text, so a classification range is a plain string range.
+alter table lms_book add column call_number text not null default '';
+
+create index lms_book_call_number on lms_book (call_number);
+
+create index lms_book_subject_subject on lms_book_subject (subject collate nocase);
+
+-- Tags are free, lowercase labels; subjects stay as catalogued headings.
+create table lms_book_tag (
+    bid integer not null,
+    tag text not null,
+    primary key (bid, tag),
+    foreign key (bid) references lms_book (bid) on delete cascade
+);
+
+create index lms_book_tag_tag on lms_book_tag (tag);
@@ -0,0 +1,16 @@
+create table lms_series (
+    sid integer primary key autoincrement,
+    title text not null unique collate nocase,
+    info text not null default ''
+);
+
+-- A book is a volume of at most one series.
+create table lms_book_series (
+    bid integer primary key,
+    sid integer not null,
+    volume integer not null,
+    unique (sid, volume),
+    foreign key (bid) references lms_book (bid) on delete cascade,
+    foreign key (sid) references lms_series (sid),
+    check (volume > 0)
+);
@@ -0,0 +1,8 @@
+-- Locations form a tree: branches hold floors, floors hold rooms, rooms hold
+-- shelves. Levels may be skipped, but a location always sits below its parent.
+alter table lms_location add column parent integer default null references lms_location (lid);
+alter table lms_location add column kind integer not null default 0
+    check (kind in (0, 1, 2, 3)); -- 0: branch, 1: floor, 2: room, 3: shelf
+
+create index lms_location_parent on lms_location (parent);
+create index lms_instance_lid on lms_instance (lid);
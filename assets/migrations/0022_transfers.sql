@@ -0,0 +1,57 @@
+-- Copies being moved between locations are occupied as in transit (kind 4),
+-- so they cannot circulate until received. The check constraint can only
+-- change by rebuilding the table.
+create table lms_occupation_new (
+    uid integer default null,
+    iid integer not null unique,
+    date text not null,
+    kind integer not null,
+    due_date text default null,
+    renewals integer not null default 0,
+    foreign key (uid) references lms_user (uid),
+    foreign key (iid) references lms_instance (iid),
+    check (kind in (0, 1, 2, 3, 4)) -- 0: borrowed, 1: reserved, 2: maintenance, 3: lost, 4: in transit
+);
+
+insert into lms_occupation_new (uid, iid, date, kind, due_date, renewals)
+    select uid, iid, date, kind, due_date, renewals from lms_occupation;
+
+drop table lms_occupation;
+alter table lms_occupation_new rename to lms_occupation;
+
+create index lms_borrow_uid on lms_occupation (uid);
+create index lms_borrow_iid on lms_occupation (iid);
+create index lms_borrow_due_date on lms_occupation (due_date);
+
+create trigger lms_occupation_remove
+    after delete on lms_occupation
+    when old.kind = 0
+    begin
+        insert into lms_history (uid, iid, date, return_date)
+        values (old.uid, old.iid, old.date, date('now'));
+    end;
+
+create table lms_transfer (
+    xid integer primary key autoincrement,
+    iid integer not null,
+    origin integer not null,
+    destination integer not null,
+    hid integer default null, -- hold the copy is sent to be picked up for
+    status integer not null default 0,
+    request_date text not null,
+    dispatch_date text default null,
+    receive_date text default null,
+    note text not null default '',
+    foreign key (iid) references lms_instance (iid),
+    foreign key (origin) references lms_location (lid),
+    foreign key (destination) references lms_location (lid),
+    foreign key (hid) references lms_hold (hid),
+    check (status in (0, 1, 2, 3)) -- 0: requested, 1: dispatched, 2: received, 3: cancelled
+);
+
+create index lms_transfer_iid on lms_transfer (iid);
+create index lms_transfer_status on lms_transfer (status);
+create unique index lms_transfer_open on lms_transfer (iid) where status in (0, 1);
+
+-- Branch a hold is picked up at; copies elsewhere are transferred there.
+alter table lms_hold add column pickup integer default null references lms_location (lid);
@@ -0,0 +1,43 @@
+-- A stocktake checks the copies recorded under location `lid` against the
+-- ones scanned on the shelves while it is open.
+create table lms_stocktake (
+    vid integer primary key autoincrement,
+    lid integer not null,
+    status integer not null default 0,
+    open_date text not null,
+    close_date text default null,
+    note text not null default '',
+    foreign key (lid) references lms_location (lid),
+    check (status in (0, 1)) -- 0: open, 1: closed
+);
+create index lms_stocktake_lid on lms_stocktake (lid);
+
+-- A copy scanned again is recorded where it was last scanned.
+create table lms_stocktake_scan (
+    vid integer not null,
+    iid integer not null,
+    lid integer not null,
+    date text not null,
+    primary key (vid, iid),
+    foreign key (vid) references lms_stocktake (vid),
+    foreign key (iid) references lms_instance (iid),
+    foreign key (lid) references lms_location (lid)
+);
+
+-- The report of a closed stocktake. `lid` is where the copy was recorded
+-- and `found` where it was scanned, null for missing copies.
+create table lms_stocktake_finding (
+    vid integer not null,
+    iid integer not null,
+    kind integer not null,
+    lid integer not null,
+    found integer default null,
+    lost integer not null default 0,
+    primary key (vid, kind, iid),
+    foreign key (vid) references lms_stocktake (vid),
+    foreign key (iid) references lms_instance (iid),
+    foreign key (lid) references lms_location (lid),
+    foreign key (found) references lms_location (lid),
+    check (kind in (0, 1, 2)) -- 0: missing, 1: misplaced, 2: borrowed
+);
+create index lms_stocktake_finding_iid on lms_stocktake_finding (iid);
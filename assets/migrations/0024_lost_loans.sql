@@ -0,0 +1,8 @@
+-- A loan whose copy is reported lost ends there, so it is closed into the
+-- history with `lost` set rather than living on in the lost occupation.
+alter table lms_history add column lost integer not null default 0;
+
+-- Copies lost while borrowed so far kept only the date they were reported
+-- lost, which stands in for both ends of the loan.
+insert into lms_history (uid, iid, date, return_date, lost)
+    select uid, iid, date, date, 1 from lms_occupation where kind = 3 and uid is not null;
@@ -0,0 +1,2 @@
+-- Staff member who recorded the event, null for events from before this.
+alter table lms_instance_event add column actor integer default null;
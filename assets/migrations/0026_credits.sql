@@ -0,0 +1,16 @@
+-- Money owed back to a patron, such as a paid replacement charge for a copy
+-- that turned up again. `fid` is the charge being given back.
+create table lms_credit (
+    rid integer primary key autoincrement,
+    uid integer not null,
+    fid integer not null unique,
+    amount integer not null,
+    date text not null,
+    status integer not null default 0,
+    settle_date text default null,
+    foreign key (uid) references lms_user (uid),
+    foreign key (fid) references lms_fine (fid),
+    check (status in (0, 1)) -- 0: owed, 1: refunded
+);
+
+create index lms_credit_uid on lms_credit (uid);
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 use std::io::Write;
-use crate::client::{Client, read_string};
+use crate::client::{Client, ClientError, read_string};
 use crate::model::*;
 use crate::utils::*;
 
@@ -20,10 +20,22 @@ macro_rules! read_arg {
 
 macro_rules! read_u64 {
     ($name:ident) => {
+        read_parsed!($name, u64);
+    };
+}
+
+macro_rules! read_bool {
+    ($name:ident) => {
+        read_parsed!($name, bool);
+    };
+}
+
+macro_rules! read_parsed {
+    ($name:ident, $ty:ty) => {
         println!(stringify!($name));
         std::io::stdout().flush().unwrap();
         let $name = match read_string() {
-            Some($name) => match $name.parse::<u64>() {
+            Some($name) => match $name.parse::<$ty>() {
                 Ok($name) => $name,
                 Err(_) => {
                     verdict_err(&format!("Failed to parse argument: {}", stringify!($name)));
@@ -38,6 +50,45 @@ macro_rules! read_u64 {
     };
 }
 
+/// Reads the bibliographic fields shared by `admin add` and `admin alter`.
+macro_rules! read_details {
+    ($name:ident) => {
+        read_arg!(isbn);
+        read_arg!(publisher);
+        read_u64!(year);
+        read_arg!(edition);
+        read_arg!(language);
+        read_u64!(pages);
+        read_arg!(subjects);
+        read_arg!(tags);
+        read_arg!(call_number);
+        let $name = BookDetails {
+            isbn,
+            publisher,
+            year,
+            edition,
+            language,
+            pages,
+            subjects: subjects.split(',').map(|subject| subject.to_string()).collect(),
+            tags: tags.split(',').map(|tag| tag.to_string()).collect(),
+            call_number,
+        };
+    };
+}
+
+#[inline]
+fn details_values(details: BookDetails) {
+    value("isbn", details.isbn);
+    value("publisher", details.publisher);
+    value("year", details.year);
+    value("edition", details.edition);
+    value("language", details.language);
+    value("pages", details.pages);
+    value("subjects", details.subjects.join(","));
+    value("tags", details.tags.join(","));
+    value("call_number", details.call_number);
+}
+
 #[inline]
 fn verdict_ok() {
     println!("OK");
@@ -57,6 +108,88 @@ fn value(key: &str, val: impl Display) {
     std::io::stdout().flush().unwrap();
 }
 
+#[inline]
+fn verdict_error(err: ClientError) {
+    match err {
+        ClientError::Api { code, message, violation, .. } => {
+            verdict_err(&message);
+            value("code", code.as_str());
+            if let Some(violation) = violation {
+                value("violation", format!(
+                    "{},{},{}", violation.rule, violation.limit, violation.current));
+            }
+        }
+        err => verdict_err(&format!("{}", err)),
+    }
+}
+
+#[inline]
+pub async fn auth_login(client: &Client) {
+    read_arg!(username);
+    read_arg!(password);
+    let response = match client.login(username, password).await {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("uid", response.uid);
+        value("role", response.role);
+        value("expires", response.expires);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn auth_logout(client: &Client) {
+    let response = client.post("auth/logout", RequestAuthLogout {}).await;
+    client.logout();
+    let response: ResponseAuthLogout = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn auth_password(client: &Client) {
+    read_arg!(current_password);
+    read_arg!(password);
+    if !is_password_legit(&password) {
+        verdict_err("Password is not legit");
+        return;
+    }
+    let request = RequestAuthPassword {
+        current_password,
+        password: password.clone(),
+    };
+    let response = client.post("auth/password", request).await;
+    let response: ResponseAuthPassword = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        client.remember_password(password);
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
 #[inline]
 pub async fn user_register(client: &Client) {
     read_arg!(username);
@@ -70,16 +203,22 @@ pub async fn user_register(client: &Client) {
         return;
     }
     read_arg!(info);
+    read_arg!(password);
+    if !is_password_legit(&password) {
+        verdict_err("Password is not legit");
+        return;
+    }
     let request = RequestUserRegister {
         username,
         email,
         info,
+        password,
     };
     let response = client.post("user/register", request).await;
     let response: ResponseUserRegister = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -98,9 +237,9 @@ pub async fn user_lookup(client: &Client) {
         ("phrase", &phrase),
     ]).await;
     let response: ResponseUserLookup = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -134,9 +273,9 @@ pub async fn user_alter(client: &Client) {
     };
     let response = client.post("user/alter", request).await;
     let response: ResponseUserAlter = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -150,47 +289,176 @@ pub async fn user_alter(client: &Client) {
 #[inline]
 pub async fn user_borrowed(client: &Client) {
     read_u64!(uid);
+    let mut loans = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("v2/user/borrowed", [
+            ("uid", &uid.to_string()),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseUserBorrowedV2 = match response {
+            Ok(response) => response,
+            Err(err) if cursor == 0 && err.is_missing_endpoint() => return user_borrowed_v1(client, uid).await,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        loans.extend(response.loans);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", loans.len());
+    for loan in loans {
+        value("loan", format!(
+            "{},{},{},{},{}",
+            loan.iid, loan.book.bid, loan.date, loan.due_date, loan.renewals));
+        value("title", loan.book.title);
+        value("author", loan.book.author);
+    }
+}
+
+// Servers with `features.v2` off only list loans by id.
+async fn user_borrowed_v1(client: &Client, uid: u64) {
     let response = client.get("user/borrowed", [
         ("uid", &uid.to_string()),
     ]).await;
     let response: ResponseUserBorrowed = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
-        let iid_list = response.iid_list;
-        value("iid_list", iid_list.len());
+        value("iid_list", response.iid_list);
+        value("due_list", response.due_list);
     } else {
         verdict_err(&response.message);
     }
 }
 
+#[inline]
+pub async fn user_history(client: &Client) {
+    read_u64!(uid);
+    read_arg!(since);
+    read_arg!(until);
+    let mut loans = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("user/history", [
+            ("uid", &uid.to_string()),
+            ("since", &since),
+            ("until", &until),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseUserHistory = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        loans.extend(response.loans);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", loans.len());
+    for loan in loans {
+        value("loan", format!(
+            "{},{},{},{},{}",
+            loan.iid, loan.book.bid, loan.date, loan.return_date, loan.lost));
+        value("title", loan.book.title);
+        value("author", loan.book.author);
+    }
+}
+
 #[inline]
 pub async fn user_reserved(client: &Client) {
     read_u64!(uid);
+    let mut holds = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("v2/user/reserved", [
+            ("uid", &uid.to_string()),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseUserReservedV2 = match response {
+            Ok(response) => response,
+            Err(err) if cursor == 0 && err.is_missing_endpoint() => return user_reserved_v1(client, uid).await,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        holds.extend(response.holds);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", holds.len());
+    for record in holds {
+        let hold = record.hold;
+        value("hold", format!(
+            "{},{},{},{},{},{},{},{},{}",
+            hold.hid, hold.uid, hold.bid, hold.iid, hold.status,
+            hold.position, hold.date, hold.expiry_date, hold.pickup));
+        value("title", record.book.title);
+        value("author", record.book.author);
+    }
+}
+
+async fn user_reserved_v1(client: &Client, uid: u64) {
     let response = client.get("user/reserved", [
         ("uid", &uid.to_string()),
     ]).await;
     let response: ResponseUserReserved = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
-        let iid_list = response.iid_list;
-        value("iid_list", iid_list.len());
+        hold_values(&response.holds);
     } else {
         verdict_err(&response.message);
     }
 }
 
+#[inline]
+fn hold_values(holds: &[Hold]) {
+    value("count", holds.len());
+    for hold in holds {
+        value("hold", format!(
+            "{},{},{},{},{},{},{},{},{}",
+            hold.hid, hold.uid, hold.bid, hold.iid, hold.status,
+            hold.position, hold.date, hold.expiry_date, hold.pickup));
+    }
+}
+
 #[inline]
 pub async fn user_unregister(client: &Client) {
     read_u64!(uid);
@@ -199,9 +467,9 @@ pub async fn user_unregister(client: &Client) {
     };
     let response = client.post("user/unregister", request).await;
     let response: ResponseUserUnregister = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -222,14 +490,15 @@ pub async fn user_borrow(client: &Client) {
     };
     let response = client.post("user/borrow", request).await;
     let response: ResponseBookBorrow = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
+        value("due_date", response.due_date);
     } else {
         verdict_err(&response.message);
     }
@@ -238,37 +507,91 @@ pub async fn user_borrow(client: &Client) {
 #[inline]
 pub async fn user_return(client: &Client) {
     read_u64!(iid);
+    read_u64!(uid);
     let request = RequestBookReturn {
         iid,
+        uid,
     };
     let response = client.post("user/return", request).await;
     let response: ResponseBookReturn = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
+        value("fine", response.fine);
+        value("held_for", response.held_for);
     } else {
         verdict_err(&response.message);
     }
 }
 
 #[inline]
-pub async fn user_reserve(client: &Client) {
+pub async fn user_renew(client: &Client) {
     read_u64!(uid);
     read_u64!(iid);
-    let request = RequestBookReserve {
+    let request = RequestBookRenew {
         uid,
         iid,
     };
+    let response = client.post("user/renew", request).await;
+    let response: ResponseBookRenew = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("due_date", response.due_date);
+        value("renewals", response.renewals);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn user_reserve(client: &Client) {
+    read_u64!(uid);
+    read_u64!(bid);
+    read_u64!(pickup);
+    let request = RequestBookReserve {
+        uid,
+        bid,
+        pickup,
+    };
     let response = client.post("user/reserve", request).await;
     let response: ResponseBookReserve = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("hid", response.hid);
+        value("position", response.position);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn user_cancel_hold(client: &Client) {
+    read_u64!(hid);
+    let request = RequestHoldCancel {
+        hid,
+    };
+    let response = client.post("user/cancel_hold", request).await;
+    let response: ResponseHoldCancel = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -279,6 +602,91 @@ pub async fn user_reserve(client: &Client) {
     }
 }
 
+#[inline]
+pub async fn user_overdue(client: &Client) {
+    read_u64!(uid);
+    let response = client.get("user/overdue", [
+        ("uid", &uid.to_string()),
+    ]).await;
+    let response: ResponseUserOverdue = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    overdue_values(&response.overdue);
+}
+
+#[inline]
+fn overdue_values(overdue: &[OverdueLoan]) {
+    value("count", overdue.len());
+    for loan in overdue {
+        value("overdue", format!(
+            "{},{},{},{},{},{}",
+            loan.uid, loan.iid, loan.bid, loan.date, loan.due_date, loan.days_late));
+    }
+}
+
+#[inline]
+pub async fn user_fines(client: &Client) {
+    read_u64!(uid);
+    let response = client.get("user/fines", [
+        ("uid", &uid.to_string()),
+    ]).await;
+    let response: ResponseUserFines = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    value("balance", response.balance);
+    value("credit", response.credit);
+    for fine in response.fines {
+        value("fine", format!(
+            "{},{},{},{},{},{},{}",
+            fine.fid, fine.iid, fine.kind, fine.amount, fine.date, fine.status, fine.note));
+    }
+}
+
+#[inline]
+pub async fn user_policy(client: &Client) {
+    read_u64!(uid);
+    read_u64!(bid);
+    let response = client.get("user/policy", [
+        ("uid", &uid.to_string()),
+        ("bid", &bid.to_string()),
+    ]).await;
+    let response: ResponseUserPolicy = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    value("max_loans", response.max_loans);
+    value("loan_period", response.loan_period);
+    value("renewal_limit", response.renewal_limit);
+    value("max_holds", response.max_holds);
+    value("fine_daily_rate", response.fine_daily_rate);
+}
+
 #[inline]
 pub async fn user_info(client: &Client) {
     read_u64!(uid);
@@ -286,9 +694,9 @@ pub async fn user_info(client: &Client) {
         ("uid", &uid.to_string()),
     ]).await;
     let response: ResponseUserInfo = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -300,6 +708,9 @@ pub async fn user_info(client: &Client) {
     value("username", response.username);
     value("email", response.email);
     value("info", response.info);
+    value("cid", response.cid);
+    value("role", response.role);
+    value("active", response.active);
 }
 
 #[inline]
@@ -307,22 +718,29 @@ pub async fn admin_add(client: &Client) {
     read_arg!(title);
     read_arg!(author);
     read_arg!(info);
+    read_u64!(tid);
+    read_details!(details);
     let request = RequestBookAdd {
         title,
         author,
         info,
+        tid,
+        details,
     };
     let response = client.post("admin/add", request).await;
     let response: ResponseBookAdd = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
         value("bid", response.bid);
+        for warning in response.warnings {
+            value("warning", warning);
+        }
     } else {
         verdict_err(&response.message);
     }
@@ -336,9 +754,9 @@ pub async fn admin_remove(client: &Client) {
     };
     let response = client.post("admin/remove", request).await;
     let response: ResponseBookRemove = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -355,17 +773,21 @@ pub async fn admin_alter(client: &Client) {
     read_arg!(title);
     read_arg!(author);
     read_arg!(info);
+    read_u64!(tid);
+    read_details!(details);
     let request = RequestBookAlter {
         bid,
         title,
         author,
         info,
+        tid,
+        details,
     };
     let response = client.post("admin/alter", request).await;
     let response: ResponseBookAlter = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -377,44 +799,40 @@ pub async fn admin_alter(client: &Client) {
 }
 
 #[inline]
-pub async fn admin_add_instance(client: &Client) {
-    read_u64!(bid);
-    read_u64!(lid);
-    read_u64!(status);
-    let request = RequestBookAddInstance {
-        bid,
-        lid,
-        status,
+pub async fn admin_add_author(client: &Client) {
+    read_arg!(name);
+    let request = RequestAuthorAdd {
+        name,
     };
-    let response = client
-        .post("admin/add_instance", request).await;
-    let response: ResponseBookAddInstance = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+    let response = client.post("admin/add_author", request).await;
+    let response: ResponseAuthorAdd = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
-        value("iid", response.iid);
+        value("aid", response.aid);
     } else {
         verdict_err(&response.message);
     }
 }
 
 #[inline]
-pub async fn admin_remove_instance(client: &Client) {
-    read_u64!(iid);
-    let request = RequestBookRemoveInstance {
-        iid,
+pub async fn admin_rename_author(client: &Client) {
+    read_u64!(aid);
+    read_arg!(name);
+    let request = RequestAuthorRename {
+        aid,
+        name,
     };
-    let response = client
-        .post("admin/remove_instance", request).await;
-    let response: ResponseBookRemoveInstance = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+    let response = client.post("admin/rename_author", request).await;
+    let response: ResponseAuthorRename = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -426,23 +844,18 @@ pub async fn admin_remove_instance(client: &Client) {
 }
 
 #[inline]
-pub async fn admin_occupy_instance(client: &Client) {
-    read_u64!(iid);
-    read_u64!(status);
-    if status != 2 || status != 3 {
-        verdict_err("Invalid status");
-        return;
-    }
-    let request = RequestInstanceOccupy {
-        iid,
-        status,
+pub async fn admin_merge_authors(client: &Client) {
+    read_u64!(aid);
+    read_u64!(from);
+    let request = RequestAuthorMerge {
+        aid,
+        from,
     };
-    let response = client
-        .post("admin/occupy_instance", request).await;
-    let response: ResponseInstanceOccupy = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+    let response = client.post("admin/merge_authors", request).await;
+    let response: ResponseAuthorMerge = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -453,18 +866,38 @@ pub async fn admin_occupy_instance(client: &Client) {
     }
 }
 
+/// Contributors are read as `aid:role` pairs separated by commas.
 #[inline]
-pub async fn admin_release_instance(client: &Client) {
-    read_u64!(iid);
-    let request = RequestInstanceRelease {
-        iid,
-    };
-    let response = client
-        .post("admin/release_instance", request).await;
-    let response: ResponseInstanceRelease = match response {
-        Some(response) => response,
+pub async fn admin_set_contributors(client: &Client) {
+    read_u64!(bid);
+    read_arg!(contributors);
+    let contributors = contributors.split(',')
+        .filter(|contributor| !contributor.is_empty())
+        .map(|contributor| {
+            let (aid, role) = contributor.split_once(':')?;
+            Some(Contributor {
+                aid: aid.parse().ok()?,
+                name: String::new(),
+                role: role.parse().ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>();
+    let contributors = match contributors {
+        Some(contributors) => contributors,
         None => {
-            verdict_err("Failed to receive response");
+            verdict_err("Failed to parse argument: contributors");
+            return;
+        }
+    };
+    let request = RequestBookContributors {
+        bid,
+        contributors,
+    };
+    let response = client.post("admin/set_contributors", request).await;
+    let response: ResponseBookContributors = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -476,68 +909,1025 @@ pub async fn admin_release_instance(client: &Client) {
 }
 
 #[inline]
-pub async fn admin_add_location(client: &Client) {
-    read_arg!(name);
+pub async fn admin_add_series(client: &Client) {
+    read_arg!(title);
     read_arg!(info);
-    let request = RequestLocationAdd {
-        name,
+    let request = RequestSeriesAdd {
+        title,
         info,
     };
-    let response = client
+    let response = client.post("admin/add_series", request).await;
+    let response: ResponseSeriesAdd = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("sid", response.sid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_set_series(client: &Client) {
+    read_u64!(bid);
+    read_u64!(sid);
+    read_u64!(volume);
+    let request = RequestBookSeries {
+        bid,
+        sid,
+        volume,
+    };
+    let response = client.post("admin/set_series", request).await;
+    let response: ResponseBookSeries = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_add_instance(client: &Client) {
+    read_u64!(bid);
+    read_u64!(lid);
+    read_u64!(status);
+    let request = RequestBookAddInstance {
+        bid,
+        lid,
+        status,
+    };
+    let response = client
+        .post("admin/add_instance", request).await;
+    let response: ResponseBookAddInstance = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("iid", response.iid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_remove_instance(client: &Client) {
+    read_u64!(iid);
+    let request = RequestBookRemoveInstance {
+        iid,
+    };
+    let response = client
+        .post("admin/remove_instance", request).await;
+    let response: ResponseBookRemoveInstance = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_occupy_instance(client: &Client) {
+    read_u64!(iid);
+    read_u64!(status);
+    if status != 2 && status != 3 {
+        verdict_err("Invalid status");
+        return;
+    }
+    let request = RequestInstanceOccupy {
+        iid,
+        status,
+    };
+    let response = client
+        .post("admin/occupy_instance", request).await;
+    let response: ResponseInstanceOccupy = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_release_instance(client: &Client) {
+    read_u64!(iid);
+    let request = RequestInstanceRelease {
+        iid,
+    };
+    let response = client
+        .post("admin/release_instance", request).await;
+    let response: ResponseInstanceRelease = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("held_for", response.held_for);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_add_category(client: &Client) {
+    read_arg!(name);
+    read_arg!(info);
+    let request = RequestCategoryAdd {
+        name,
+        info,
+    };
+    let response = client
+        .post("admin/add_category", request).await;
+    let response: ResponseCategoryAdd = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("cid", response.cid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_add_item_type(client: &Client) {
+    read_arg!(name);
+    read_arg!(info);
+    let request = RequestItemTypeAdd {
+        name,
+        info,
+    };
+    let response = client
+        .post("admin/add_item_type", request).await;
+    let response: ResponseItemTypeAdd = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("tid", response.tid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_set_category(client: &Client) {
+    read_u64!(uid);
+    read_u64!(cid);
+    let request = RequestUserCategory {
+        uid,
+        cid,
+    };
+    let response = client
+        .post("admin/set_category", request).await;
+    let response: ResponseUserCategory = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_set_active(client: &Client) {
+    read_u64!(uid);
+    read_bool!(active);
+    let request = RequestUserActive {
+        uid,
+        active,
+    };
+    let response = client
+        .post("admin/set_active", request).await;
+    let response: ResponseUserActive = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_grant_role(client: &Client) {
+    read_u64!(uid);
+    read_u64!(role);
+    let request = RequestRoleGrant {
+        uid,
+        role,
+    };
+    let response = client
+        .post("admin/grant_role", request).await;
+    let response: ResponseRoleGrant = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_set_password(client: &Client) {
+    read_u64!(uid);
+    read_arg!(password);
+    let request = RequestUserPassword {
+        uid,
+        password,
+    };
+    let response = client.post("admin/set_password", request).await;
+    let response: ResponseUserPassword = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_set_policy(client: &Client) {
+    read_u64!(cid);
+    read_u64!(tid);
+    read_u64!(max_loans);
+    read_u64!(loan_period);
+    read_u64!(renewal_limit);
+    read_u64!(max_holds);
+    read_u64!(fine_daily_rate);
+    let request = RequestPolicySet {
+        cid,
+        tid,
+        max_loans,
+        loan_period,
+        renewal_limit,
+        max_holds,
+        fine_daily_rate,
+    };
+    let response = client
+        .post("admin/set_policy", request).await;
+    let response: ResponsePolicySet = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_send_repair(client: &Client) {
+    read_u64!(iid);
+    read_arg!(note);
+    read_arg!(expected_date);
+    let request = RequestInstanceRepair {
+        iid,
+        note,
+        expected_date,
+    };
+    let response = client
+        .post("admin/send_repair", request).await;
+    let response: ResponseInstanceRepair = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_finish_repair(client: &Client) {
+    read_u64!(iid);
+    read_bool!(repaired);
+    read_arg!(note);
+    let request = RequestInstanceRepaired {
+        iid,
+        repaired,
+        note,
+    };
+    let response = client
+        .post("admin/finish_repair", request).await;
+    let response: ResponseInstanceRepaired = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("held_for", response.held_for);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_mark_lost(client: &Client) {
+    read_u64!(iid);
+    read_bool!(bill);
+    read_arg!(note);
+    let request = RequestInstanceLost {
+        iid,
+        bill,
+        note,
+    };
+    let response = client
+        .post("admin/mark_lost", request).await;
+    let response: ResponseInstanceLost = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("billed", response.billed);
+        value("fid", response.fid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_mark_found(client: &Client) {
+    read_u64!(iid);
+    read_arg!(note);
+    let request = RequestInstanceFound {
+        iid,
+        note,
+    };
+    let response = client
+        .post("admin/mark_found", request).await;
+    let response: ResponseInstanceFound = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("reversed", response.reversed);
+        value("refunded", response.refunded);
+        value("held_for", response.held_for);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_withdraw_instance(client: &Client) {
+    read_u64!(iid);
+    read_arg!(note);
+    let request = RequestInstanceWithdraw {
+        iid,
+        note,
+    };
+    let response = client
+        .post("admin/withdraw_instance", request).await;
+    let response: ResponseInstanceWithdraw = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_add_location(client: &Client) {
+    read_arg!(name);
+    read_arg!(info);
+    read_u64!(parent);
+    read_u64!(kind);
+    let request = RequestLocationAdd {
+        name,
+        info,
+        parent,
+        kind,
+    };
+    let response = client
         .post("admin/add_location", request).await;
     let response: ResponseLocationAdd = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("lid", response.lid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_remove_location(client: &Client) {
+    read_u64!(lid);
+    read_u64!(target);
+    let request = RequestLocationRemove {
+        lid,
+        target,
+    };
+    let response = client
+        .post("admin/remove_location", request).await;
+    let response: ResponseLocationRemove = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn location_tree(client: &Client) {
+    read_u64!(lid);
+    let response = client.get("location/tree", [
+        ("lid", &lid.to_string()),
+    ]).await;
+    let response: ResponseLocationTree = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    value("count", response.locations.len());
+    for location in response.locations {
+        value("location", format!(
+            "{},{},{},{},{}",
+            location.lid, location.parent, location.kind, location.depth, location.instances));
+        value("name", location.name);
+    }
+}
+
+#[inline]
+pub async fn location_contents(client: &Client) {
+    read_u64!(lid);
+    let mut instances = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("location/contents", [
+            ("lid", &lid.to_string()),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseLocationContents = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        instances.extend(response.instances);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", instances.len());
+    for instance in instances {
+        let kind = match instance.occupation {
+            Some(occupation) => occupation.kind.to_string(),
+            None => String::new(),
+        };
+        value("instance", format!(
+            "{},{},{},{},{}",
+            instance.iid, instance.bid, instance.lid, instance.status, kind));
+        value("location", instance.location);
+    }
+}
+
+#[inline]
+pub async fn admin_alter_location(client: &Client) {
+    read_u64!(lid);
+    read_arg!(name);
+    read_arg!(info);
+    let request = RequestLocationAlter {
+        lid,
+        name,
+        info,
+    };
+    let response = client
+        .post("admin/alter_location", request).await;
+    let response: ResponseLocationAlter = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_overdue(client: &Client) {
+    let response = client.get("admin/overdue", []).await;
+    let response: ResponseAdminOverdue = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    overdue_values(&response.overdue);
+}
+
+#[inline]
+pub async fn admin_request_transfer(client: &Client) {
+    read_u64!(iid);
+    read_u64!(destination);
+    read_arg!(note);
+    let request = RequestTransferRequest {
+        iid,
+        destination,
+        note,
+    };
+    let response = client.post("admin/request_transfer", request).await;
+    let response: ResponseTransferRequest = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("xid", response.xid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_dispatch_transfer(client: &Client) {
+    read_u64!(xid);
+    let request = RequestTransferDispatch {
+        xid,
+    };
+    let response = client.post("admin/dispatch_transfer", request).await;
+    let response: ResponseTransferDispatch = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_receive_transfer(client: &Client) {
+    read_u64!(xid);
+    let request = RequestTransferReceive {
+        xid,
+    };
+    let response = client.post("admin/receive_transfer", request).await;
+    let response: ResponseTransferReceive = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("held_for", response.held_for);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_cancel_transfer(client: &Client) {
+    read_u64!(xid);
+    let request = RequestTransferCancel {
+        xid,
+    };
+    let response = client.post("admin/cancel_transfer", request).await;
+    let response: ResponseTransferCancel = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_transfers(client: &Client) {
+    read_u64!(lid);
+    read_bool!(open);
+    let mut transfers = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("admin/transfers", [
+            ("lid", &lid.to_string()),
+            ("open", &open.to_string()),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseTransferList = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        transfers.extend(response.transfers);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", transfers.len());
+    for transfer in transfers {
+        value("transfer", format!(
+            "{},{},{},{},{},{}",
+            transfer.xid, transfer.iid, transfer.origin, transfer.destination, transfer.hid, transfer.status));
+        value("dates", format!(
+            "{},{},{}",
+            transfer.request_date, transfer.dispatch_date, transfer.receive_date));
+        value("note", transfer.note);
+    }
+}
+
+#[inline]
+pub async fn admin_open_stocktake(client: &Client) {
+    read_u64!(lid);
+    read_arg!(note);
+    let request = RequestStocktakeOpen {
+        lid,
+        note,
+    };
+    let response = client.post("admin/open_stocktake", request).await;
+    let response: ResponseStocktakeOpen = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
-        value("lid", response.lid);
+        value("vid", response.vid);
     } else {
         verdict_err(&response.message);
     }
 }
 
+/// Scanned iids are read separated by commas.
 #[inline]
-pub async fn admin_remove_location(client: &Client) {
+pub async fn admin_scan_stocktake(client: &Client) {
+    read_u64!(vid);
     read_u64!(lid);
-    let request = RequestLocationRemove {
+    read_arg!(iids);
+    let iids = iids.split(',')
+        .filter(|iid| !iid.is_empty())
+        .map(|iid| iid.trim().parse().ok())
+        .collect::<Option<Vec<u64>>>();
+    let iids = match iids {
+        Some(iids) => iids,
+        None => {
+            verdict_err("Failed to parse argument: iids");
+            return;
+        }
+    };
+    let request = RequestStocktakeScan {
+        vid,
         lid,
+        iids,
     };
-    let response = client
-        .post("admin/remove_location", request).await;
-    let response: ResponseLocationRemove = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+    let response = client.post("admin/scan_stocktake", request).await;
+    let response: ResponseStocktakeScan = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
     if response.success {
         verdict_ok();
+        value("scanned", response.scanned);
+        let unknown = response.unknown.iter().map(|iid| iid.to_string()).collect::<Vec<_>>();
+        value("unknown", unknown.join(","));
     } else {
         verdict_err(&response.message);
     }
 }
 
 #[inline]
-pub async fn admin_alter_location(client: &Client) {
+pub async fn admin_close_stocktake(client: &Client) {
+    read_u64!(vid);
+    read_bool!(mark_lost);
+    let request = RequestStocktakeClose {
+        vid,
+        mark_lost,
+    };
+    let response = client.post("admin/close_stocktake", request).await;
+    let response: ResponseStocktakeClose = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        report_values(response.report);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_stocktake(client: &Client) {
+    read_u64!(vid);
+    let response = client.get("admin/stocktake", [
+        ("vid", &vid.to_string()),
+    ]).await;
+    let response: ResponseStocktakeReport = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        stocktake_values(response.stocktake);
+        report_values(response.report);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_stocktakes(client: &Client) {
     read_u64!(lid);
-    read_arg!(name);
-    read_arg!(info);
-    let request = RequestLocationAlter {
-        lid,
-        name,
-        info,
+    read_bool!(open);
+    let mut stocktakes = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("admin/stocktakes", [
+            ("lid", &lid.to_string()),
+            ("open", &open.to_string()),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseStocktakeList = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        stocktakes.extend(response.stocktakes);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", stocktakes.len());
+    for stocktake in stocktakes {
+        stocktake_values(stocktake);
+    }
+}
+
+fn stocktake_values(stocktake: Stocktake) {
+    value("stocktake", format!(
+        "{},{},{},{}",
+        stocktake.vid, stocktake.lid, stocktake.status, stocktake.scanned));
+    value("dates", format!("{},{}", stocktake.open_date, stocktake.close_date));
+    value("note", stocktake.note);
+}
+
+fn report_values(report: StocktakeReport) {
+    for (name, findings) in [("missing", report.missing), ("misplaced", report.misplaced), ("borrowed", report.borrowed)] {
+        value(name, findings.len());
+        for finding in findings {
+            value("finding", format!(
+                "{},{},{},{},{}",
+                finding.iid, finding.bid, finding.lid, finding.found, finding.lost));
+        }
+    }
+}
+
+#[inline]
+pub async fn admin_audit(client: &Client) {
+    read_u64!(actor);
+    read_arg!(entity);
+    read_u64!(target);
+    read_arg!(since);
+    read_arg!(until);
+    let mut entries = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("admin/audit", [
+            ("actor", &actor.to_string()),
+            ("entity", &entity),
+            ("target", &target.to_string()),
+            ("since", &since),
+            ("until", &until),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseAdminAudit = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        entries.extend(response.entries);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", entries.len());
+    for entry in entries {
+        value("entry", format!(
+            "{},{},{},{},{},{}",
+            entry.aid, entry.date, entry.actor, entry.endpoint, entry.entity, entry.target));
+        value("before", entry.before);
+        value("after", entry.after);
+    }
+}
+
+#[inline]
+pub async fn admin_add_fine(client: &Client) {
+    read_u64!(uid);
+    read_u64!(iid);
+    read_u64!(kind);
+    read_u64!(amount);
+    read_arg!(note);
+    let request = RequestFineAdd {
+        uid,
+        iid,
+        kind,
+        amount,
+        note,
     };
     let response = client
-        .post("admin/alter_location", request).await;
-    let response: ResponseLocationAlter = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        .post("admin/add_fine", request).await;
+    let response: ResponseFineAdd = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if response.success {
+        verdict_ok();
+        value("fid", response.fid);
+    } else {
+        verdict_err(&response.message);
+    }
+}
+
+#[inline]
+pub async fn admin_pay_fine(client: &Client) {
+    admin_settle_fine(client, "admin/pay_fine").await;
+}
+
+#[inline]
+pub async fn admin_waive_fine(client: &Client) {
+    admin_settle_fine(client, "admin/waive_fine").await;
+}
+
+#[inline]
+async fn admin_settle_fine(client: &Client, path: &str) {
+    read_u64!(fid);
+    let request = RequestFineSettle {
+        fid,
+    };
+    let response = client.post(path, request).await;
+    let response: ResponseFineSettle = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -551,13 +1941,61 @@ pub async fn admin_alter_location(client: &Client) {
 #[inline]
 pub async fn book_search(client: &Client) {
     read_arg!(phrase);
-    let response = client.get("book/search", [
-        ("phrase", &phrase),
-    ]).await;
+    read_arg!(subject);
+    read_arg!(tag);
+    read_arg!(class_from);
+    read_arg!(class_to);
+    let mut results = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("v2/book/search", [
+            ("phrase", &phrase),
+            ("subject", &subject),
+            ("tag", &tag),
+            ("class_from", &class_from),
+            ("class_to", &class_to),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseBookSearchV2 = match response {
+            Ok(response) => response,
+            Err(err) if cursor == 0 && err.is_missing_endpoint() => return book_search_v1(client, [
+                ("phrase", &phrase),
+                ("subject", &subject),
+                ("tag", &tag),
+                ("class_from", &class_from),
+                ("class_to", &class_to),
+            ]).await,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        results.extend(response.results);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", results.len());
+    for result in results {
+        value("result", format!("{},{:.3}", result.bid, result.score));
+        value("title", result.title);
+        value("author", result.author);
+        value("snippet", result.snippet);
+    }
+}
+
+async fn book_search_v1(client: &Client, query: [(&str, &str); 5]) {
+    let response = client.get("book/search", query).await;
     let response: ResponseBookSearch = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -567,6 +2005,12 @@ pub async fn book_search(client: &Client) {
     }
     verdict_ok();
     value("bid_list", response.bid_list);
+    for result in response.results {
+        value("result", format!("{},{:.3}", result.bid, result.score));
+        value("title", result.title);
+        value("author", result.author);
+        value("snippet", result.snippet);
+    }
 }
 
 #[inline]
@@ -576,9 +2020,9 @@ pub async fn book_info(client: &Client) {
         ("bid", &bid.to_string()),
     ]).await;
     let response: ResponseBookInfo = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -590,18 +2034,265 @@ pub async fn book_info(client: &Client) {
     value("title", response.title);
     value("author", response.author);
     value("info", response.info);
+    value("tid", response.tid);
+    details_values(response.details);
+    for contributor in response.contributors {
+        value("contributor", format!("{},{}", contributor.aid, contributor.role));
+        value("name", contributor.name);
+    }
+    if let Some(series) = response.series {
+        value("series", format!("{},{}", series.sid, series.volume));
+        value("series_title", series.title);
+    }
+}
+
+#[inline]
+fn volume_values(volume: SeriesVolume) {
+    value("volume", format!("{},{},{}", volume.volume, volume.book.bid, volume.available));
+    value("title", volume.book.title);
+}
+
+#[inline]
+pub async fn book_series(client: &Client) {
+    read_u64!(sid);
+    let response = client.get("book/series", [
+        ("sid", &sid.to_string()),
+    ]).await;
+    let response: ResponseSeriesVolumes = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    value("title", response.title);
+    value("info", response.info);
+    value("count", response.volumes.len());
+    for volume in response.volumes {
+        volume_values(volume);
+    }
+}
+
+#[inline]
+pub async fn book_next_volume(client: &Client) {
+    read_u64!(bid);
+    let response = client.get("book/next_volume", [
+        ("bid", &bid.to_string()),
+    ]).await;
+    let response: ResponseBookNextVolume = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    if let Some(volume) = response.next {
+        volume_values(volume);
+    }
+}
+
+#[inline]
+pub async fn book_by_author(client: &Client) {
+    read_u64!(aid);
+    let mut books = Vec::new();
+    let mut cursor = 0;
+    let mut name;
+    loop {
+        let response = client.get("book/by_author", [
+            ("aid", &aid.to_string()),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseBookByAuthor = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        name = response.name;
+        books.extend(response.books);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("name", name);
+    value("count", books.len());
+    for book in books {
+        let roles = book.roles.iter().map(|role| role.to_string()).collect::<Vec<_>>();
+        value("book", format!("{},{},{}", book.book.bid, book.book.tid, roles.join(":")));
+        value("title", book.book.title);
+    }
+}
+
+#[inline]
+pub async fn book_by_subject(client: &Client) {
+    read_arg!(subject);
+    let mut books = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("book/by_subject", [
+            ("subject", &subject),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseBookBySubject = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        books.extend(response.books);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", books.len());
+    for book in books {
+        value("book", format!("{},{}", book.bid, book.tid));
+        value("title", book.title);
+        value("author", book.author);
+    }
+}
+
+#[inline]
+pub async fn book_by_class(client: &Client) {
+    read_arg!(from);
+    read_arg!(to);
+    let mut books = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("book/by_class", [
+            ("from", &from),
+            ("to", &to),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseBookByClass = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        books.extend(response.books);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", books.len());
+    for book in books {
+        value("book", format!("{},{}", book.book.bid, book.book.tid));
+        value("call_number", book.call_number);
+        value("title", book.book.title);
+    }
+}
+
+#[inline]
+pub async fn book_by_isbn(client: &Client) {
+    read_arg!(isbn);
+    let response = client.get("book/by_isbn", [
+        ("isbn", &isbn),
+    ]).await;
+    let response: ResponseBookByIsbn = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    value("count", response.books.len());
+    for book in response.books {
+        value("book", format!("{},{}", book.bid, book.tid));
+        value("title", book.title);
+        value("author", book.author);
+    }
 }
 
 #[inline]
 pub async fn book_instance(client: &Client) {
     read_u64!(bid);
+    let mut instances = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("v2/book/instance", [
+            ("bid", &bid.to_string()),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseBookInstanceV2 = match response {
+            Ok(response) => response,
+            Err(err) if cursor == 0 && err.is_missing_endpoint() => return book_instance_v1(client, bid).await,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        instances.extend(response.instances);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", instances.len());
+    for instance in instances {
+        let (kind, due_date) = match instance.occupation {
+            Some(occupation) => (occupation.kind.to_string(), occupation.due_date),
+            None => (String::new(), String::new()),
+        };
+        value("instance", format!(
+            "{},{},{},{},{}",
+            instance.iid, instance.lid, instance.status, kind, due_date));
+        value("location", instance.location);
+        if !instance.withdrawn_date.is_empty() {
+            value("withdrawn", instance.withdrawn_date);
+        }
+    }
+}
+
+async fn book_instance_v1(client: &Client, bid: u64) {
     let response = client.get("book/instance", [
         ("bid", &bid.to_string()),
     ]).await;
     let response: ResponseBookInstance = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -613,6 +2304,74 @@ pub async fn book_instance(client: &Client) {
     value("iid_list", response.iid_list);
 }
 
+#[inline]
+pub async fn book_history(client: &Client) {
+    read_u64!(iid);
+    read_arg!(since);
+    read_arg!(until);
+    let mut entries = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let response = client.get("book/instance_history", [
+            ("iid", &iid.to_string()),
+            ("since", &since),
+            ("until", &until),
+            ("cursor", &cursor.to_string()),
+        ]).await;
+        let response: ResponseBookInstanceHistory = match response {
+            Ok(response) => response,
+            Err(err) => {
+                verdict_error(err);
+                return;
+            }
+        };
+        if !response.success {
+            verdict_err(&response.message);
+            return;
+        }
+        entries.extend(response.entries);
+        match response.next_cursor {
+            0 => break,
+            next_cursor => cursor = next_cursor,
+        }
+    }
+    verdict_ok();
+    value("count", entries.len());
+    for entry in entries {
+        match entry {
+            InstanceHistoryEntry::Loan(loan) => value("loan", format!(
+                "{},{},{},{}", loan.uid, loan.date, loan.return_date, loan.lost)),
+            InstanceHistoryEntry::Event(event) => {
+                value("event", format!(
+                    "{},{},{},{},{},{},{}",
+                    event.eid, event.kind, event.date, event.due_date, event.uid, event.fid, event.actor));
+                value("note", event.note);
+            }
+        }
+    }
+}
+
+#[inline]
+pub async fn book_holds(client: &Client) {
+    read_u64!(bid);
+    let response = client.get("book/holds", [
+        ("bid", &bid.to_string()),
+    ]).await;
+    let response: ResponseBookHolds = match response {
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
+            return;
+        }
+    };
+    if !response.success {
+        verdict_err(&response.message);
+        return;
+    }
+    verdict_ok();
+    hold_values(&response.holds);
+}
+
 #[inline]
 pub async fn book_instance_info(client: &Client) {
     read_u64!(iid);
@@ -620,9 +2379,9 @@ pub async fn book_instance_info(client: &Client) {
         ("iid", &iid.to_string()),
     ]).await;
     let response: ResponseBookInstanceInfo = match response {
-        Some(response) => response,
-        None => {
-            verdict_err("Failed to receive response");
+        Ok(response) => response,
+        Err(err) => {
+            verdict_error(err);
             return;
         }
     };
@@ -634,4 +2393,5 @@ pub async fn book_instance_info(client: &Client) {
     value("bid", response.bid);
     value("lid", response.lid);
     value("status", response.status);
+    value("due_date", response.due_date);
 }
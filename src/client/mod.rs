@@ -1,13 +1,77 @@
 mod api;
 
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use reqwest::{RequestBuilder, Response, StatusCode};
 use serde::{Serialize};
 use serde::de::DeserializeOwned;
 use crate::client::api::*;
+use crate::model::{ErrorCode, PolicyViolation, RequestAuthLogin, ResponseAuthLogin, ResponseError};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Transport(String),
+    Api {
+        status: StatusCode,
+        code: ErrorCode,
+        message: String,
+        violation: Option<PolicyViolation>,
+    },
+    Decode(String),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Transport(err) => write!(f, "Failed to receive response: {}", err),
+            ClientError::Api { status, code, message, .. } =>
+                write!(f, "{} ({}): {}", code.as_str(), status.as_u16(), message),
+            ClientError::Decode(err) => write!(f, "Failed to decode response: {}", err),
+        }
+    }
+}
+
+impl ClientError {
+    /// The server has no such route, e.g. a v2 endpoint while `features.v2`
+    /// is off. Other not-found errors come back the same from v1, so callers
+    /// can retry there without telling the two apart.
+    pub fn is_missing_endpoint(&self) -> bool {
+        matches!(self, ClientError::Api { status: StatusCode::NOT_FOUND, code: ErrorCode::NotFound, .. })
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            ClientError::Decode(format!("{}", err))
+        } else {
+            ClientError::Transport(format!("{}", err))
+        }
+    }
+}
+
+// Failed requests carry a `ResponseError` body alongside a 4xx or 5xx status.
+async fn decode<ResTy: DeserializeOwned>(response: Response) -> Result<ResTy, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json::<ResTy>().await?);
+    }
+    let err = response.json::<ResponseError>().await
+        .map_err(|_| ClientError::Decode(format!("unexpected status {}", status)))?;
+    Err(ClientError::Api {
+        status,
+        code: err.code,
+        message: err.message,
+        violation: err.violation,
+    })
+}
 
 pub struct Client {
     host: String,
     port: String,
     client: reqwest::Client,
+    token: Mutex<Option<String>>,
+    credentials: Mutex<Option<(String, String)>>,
 }
 
 impl Client {
@@ -16,6 +80,52 @@ impl Client {
             host,
             port,
             client: reqwest::Client::new(),
+            token: Mutex::new(None),
+            credentials: Mutex::new(None),
+        }
+    }
+
+    fn authorize(&self, builder: RequestBuilder) -> RequestBuilder {
+        match self.token.lock().unwrap().as_ref() {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    async fn login(&self, username: String, password: String) -> Result<ResponseAuthLogin, ClientError> {
+        let url = format!("http://{}:{}/auth/login", self.host, self.port);
+        let request = RequestAuthLogin {
+            username: username.clone(),
+            password: password.clone(),
+        };
+        let response = self.client
+            .post(&url)
+            .json(&request)
+            .send().await?;
+        let response = decode::<ResponseAuthLogin>(response).await?;
+        *self.token.lock().unwrap() = Some(response.token.clone());
+        *self.credentials.lock().unwrap() = Some((username, password));
+        Ok(response)
+    }
+
+    fn logout(&self) {
+        *self.token.lock().unwrap() = None;
+        *self.credentials.lock().unwrap() = None;
+    }
+
+    // Other sessions end when the password changes, but this one lives on.
+    fn remember_password(&self, password: String) {
+        if let Some((_, remembered)) = self.credentials.lock().unwrap().as_mut() {
+            *remembered = password;
+        }
+    }
+
+    // Sessions expire on the server; log in again with the remembered credentials.
+    async fn relogin(&self) -> bool {
+        let credentials = self.credentials.lock().unwrap().clone();
+        match credentials {
+            Some((username, password)) => self.login(username, password).await.is_ok(),
+            None => false,
         }
     }
 
@@ -23,28 +133,36 @@ impl Client {
         &self,
         path: &str,
         query: [(&str, &str); N],
-    ) -> Option<ResTy> {
+    ) -> Result<ResTy, ClientError> {
         let url = format!("http://{}:{}/{}", self.host, self.port, path);
         let client = &self.client;
-        let response = client
-            .get(&url)
+        let mut response = self.authorize(client.get(&url))
             .query(query.as_slice())
-            .send().await.ok()?;
-        response.json::<ResTy>().await.ok()
+            .send().await?;
+        if response.status() == StatusCode::UNAUTHORIZED && self.relogin().await {
+            response = self.authorize(client.get(&url))
+                .query(query.as_slice())
+                .send().await?;
+        }
+        decode(response).await
     }
 
-    async fn post<'a, ReqTy: Serialize, ResTy: DeserializeOwned>(
+    async fn post<ReqTy: Serialize, ResTy: DeserializeOwned>(
         &self,
         path: &str,
         req: ReqTy
-    ) -> Option<ResTy> {
+    ) -> Result<ResTy, ClientError> {
         let url = format!("http://{}:{}/{}", self.host, self.port, path);
         let client = &self.client;
-        let response = client
-            .post(&url)
+        let mut response = self.authorize(client.post(&url))
             .json(&req)
-            .send().await.ok()?;
-        response.json::<ResTy>().await.ok()
+            .send().await?;
+        if response.status() == StatusCode::UNAUTHORIZED && self.relogin().await {
+            response = self.authorize(client.post(&url))
+                .json(&req)
+                .send().await?;
+        }
+        decode(response).await
     }
 }
 
@@ -71,21 +189,44 @@ fn read_command() -> Option<(String, String)> {
     Some((iter.next()?.to_string(), iter.next()?.to_string()))
 }
 
-pub async fn main_client(host: String, port: String) {
+pub async fn main_client(
+    host: String,
+    port: String,
+    username: Option<String>,
+    password: Option<String>,
+) {
     let client = Client::new(host, port);
+    if let (Some(username), Some(password)) = (username, password) {
+        match client.login(username, password).await {
+            Ok(_) => {}
+            Err(err) => println!("login failed: {}", err),
+        }
+    }
     while let Some((category, function)) = read_command() {
         match category.as_str() {
+            "auth" => match function.as_str() {
+                "login" => auth_login(&client).await,
+                "logout" => auth_logout(&client).await,
+                "password" => auth_password(&client).await,
+                _ => println!("unknown function: {}", function),
+            },
             "user" => match function.as_str() {
                 "register" => user_register(&client).await,
                 "lookup" => user_lookup(&client).await,
                 "alter" => user_alter(&client).await,
                 "borrowed" => user_borrowed(&client).await,
                 "reserved" => user_reserved(&client).await,
+                "cancel_hold" => user_cancel_hold(&client).await,
                 "unregister" => user_unregister(&client).await,
                 "borrow" => user_borrow(&client).await,
                 "reserve" => user_reserve(&client).await,
                 "return" => user_return(&client).await,
+                "renew" => user_renew(&client).await,
                 "info" => user_info(&client).await,
+                "overdue" => user_overdue(&client).await,
+                "fines" => user_fines(&client).await,
+                "policy" => user_policy(&client).await,
+                "history" => user_history(&client).await,
                 _ => println!("unknown function: {}", function),
             },
             "book" => match function.as_str() {
@@ -93,19 +234,65 @@ pub async fn main_client(host: String, port: String) {
                 "info" => book_info(&client).await,
                 "instance" => book_instance(&client).await,
                 "instance_info" => book_instance_info(&client).await,
+                "holds" => book_holds(&client).await,
+                "history" => book_history(&client).await,
+                "by_isbn" => book_by_isbn(&client).await,
+                "by_author" => book_by_author(&client).await,
+                "by_subject" => book_by_subject(&client).await,
+                "by_class" => book_by_class(&client).await,
+                "series" => book_series(&client).await,
+                "next_volume" => book_next_volume(&client).await,
+                _ => println!("unknown function: {}", function),
+            },
+            "location" => match function.as_str() {
+                "tree" => location_tree(&client).await,
+                "contents" => location_contents(&client).await,
                 _ => println!("unknown function: {}", function),
             },
             "admin" => match function.as_str() {
                 "add" => admin_add(&client).await,
                 "remove" => admin_remove(&client).await,
                 "alter" => admin_alter(&client).await,
+                "add_author" => admin_add_author(&client).await,
+                "rename_author" => admin_rename_author(&client).await,
+                "merge_authors" => admin_merge_authors(&client).await,
+                "set_contributors" => admin_set_contributors(&client).await,
+                "add_series" => admin_add_series(&client).await,
+                "set_series" => admin_set_series(&client).await,
                 "add_instance" => admin_add_instance(&client).await,
                 "remove_instance" => admin_remove_instance(&client).await,
                 "occupy_instance" => admin_occupy_instance(&client).await,
                 "release_instance" => admin_release_instance(&client).await,
+                "send_repair" => admin_send_repair(&client).await,
+                "finish_repair" => admin_finish_repair(&client).await,
+                "mark_lost" => admin_mark_lost(&client).await,
+                "mark_found" => admin_mark_found(&client).await,
+                "withdraw_instance" => admin_withdraw_instance(&client).await,
                 "add_location" => admin_add_location(&client).await,
                 "remove_location" => admin_remove_location(&client).await,
                 "alter_location" => admin_alter_location(&client).await,
+                "overdue" => admin_overdue(&client).await,
+                "add_fine" => admin_add_fine(&client).await,
+                "pay_fine" => admin_pay_fine(&client).await,
+                "waive_fine" => admin_waive_fine(&client).await,
+                "add_category" => admin_add_category(&client).await,
+                "add_item_type" => admin_add_item_type(&client).await,
+                "set_category" => admin_set_category(&client).await,
+                "set_active" => admin_set_active(&client).await,
+                "set_policy" => admin_set_policy(&client).await,
+                "grant_role" => admin_grant_role(&client).await,
+                "set_password" => admin_set_password(&client).await,
+                "audit" => admin_audit(&client).await,
+                "request_transfer" => admin_request_transfer(&client).await,
+                "dispatch_transfer" => admin_dispatch_transfer(&client).await,
+                "receive_transfer" => admin_receive_transfer(&client).await,
+                "cancel_transfer" => admin_cancel_transfer(&client).await,
+                "transfers" => admin_transfers(&client).await,
+                "open_stocktake" => admin_open_stocktake(&client).await,
+                "scan_stocktake" => admin_scan_stocktake(&client).await,
+                "close_stocktake" => admin_close_stocktake(&client).await,
+                "stocktake" => admin_stocktake(&client).await,
+                "stocktakes" => admin_stocktakes(&client).await,
                 _ => println!("unknown function: {}", function),
             }
             _ => println!("unknown category: {}", category),
@@ -1,25 +1,57 @@
 use log::{info, warn};
+use crate::migrate::migrate;
+use crate::server::set_password;
+use crate::settings::settings;
+use crate::utils::*;
 
-pub async fn main_config(lms_config_overwrite: bool) {
+pub async fn main_config(
+    lms_config_overwrite: bool,
+    username: Option<String>,
+    password: Option<String>,
+) {
     let ow = lms_config_overwrite;
-    env_logger::init();
+    settings().init_logger();
     info!("Running 1st time server configuration");
     if ow {
         warn!("Overwriting existing configuration if any");
     }
     config_database(ow);
+    match (username, password) {
+        (Some(username), Some(password)) => config_administrator(&username, &password),
+        _ => warn!("No administrator account created. Set lms_username and lms_password to create one"),
+    }
     info!("Configuration finished. It's safe to run the server now");
 }
 
-const QUERY_DB_CREATE: &str = include_str!("../assets/table_init.sql");
-
 fn config_database(ow: bool) {
-    if ow && std::path::Path::new("rdb_exp3.db").exists() {
+    let path = &settings().database.path;
+    if ow && std::path::Path::new(path).exists() {
         info!("Removing existing database");
-        std::fs::remove_file("rdb_exp3.db").unwrap();
+        std::fs::remove_file(path).unwrap();
+        // A stale write-ahead log would otherwise be replayed into the new file.
+        for suffix in ["-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path, suffix));
+        }
     }
     info!("Configuring database");
-    let db = rusqlite::Connection::open("rdb_exp3.db").unwrap();
-    db.execute_batch(QUERY_DB_CREATE).unwrap();
+    let mut db = rusqlite::Connection::open(path).unwrap();
+    migrate(&mut db).unwrap();
     db.close().unwrap();
-}
\ No newline at end of file
+}
+
+fn config_administrator(username: &str, password: &str) {
+    if !is_username_legit(username) || !is_password_legit(password) {
+        panic!("Administrator username or password is not legit");
+    }
+    info!("Creating administrator account `{}`", username);
+    let mut db = rusqlite::Connection::open(&settings().database.path).unwrap();
+    let tx = db.transaction().unwrap();
+    tx.execute(
+        "INSERT INTO lms_user (username, email, info, role) VALUES (?1, '', '', 3)",
+        [username],
+    ).unwrap();
+    let uid = tx.last_insert_rowid() as u64;
+    set_password(&tx, uid, password).unwrap();
+    tx.commit().unwrap();
+    db.close().unwrap();
+}
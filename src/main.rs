@@ -2,24 +2,68 @@ mod model;
 mod server;
 mod client;
 mod config;
+mod migrate;
+mod settings;
 mod utils;
 
+use settings::Settings;
+
+/// Splits `--config <path>` (or `--config=<path>`) from the positional arguments.
+fn parse_args() -> (Option<String>, Vec<String>) {
+    let mut config = None;
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            config = Some(args.next().expect("--config needs a path"));
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            config = Some(path.to_string());
+        } else {
+            positional.push(arg);
+        }
+    }
+    (config, positional)
+}
+
 #[tokio::main]
 async fn main() {
-    let lms_launch_type = std::env::var("lms_launch_type")
-        .unwrap_or_else(|_| "client".to_string());
+    let (config_path, positional) = parse_args();
+    let config_path = config_path.or_else(|| std::env::var("lms_config").ok());
+    let lms_launch_type = positional.first().cloned()
+        .or_else(|| std::env::var("lms_launch_type").ok())
+        .unwrap_or_else(|| "client".to_string());
+    let check = lms_launch_type == "config" && positional.get(1).map(String::as_str) == Some("check");
+    let settings = match Settings::load(config_path.as_deref()) {
+        Ok(settings) => settings,
+        Err(err) if check => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        Err(err) => panic!("Failed to load configuration: {}", err),
+    };
     let lms_host = std::env::var("lms_host")
         .unwrap_or_else(|_| "localhost".to_string());
-    let lms_port = std::env::var("lms_port")
-        .unwrap_or_else(|_| "9998".to_string());
+    let lms_port = settings.server.port.to_string();
+    let lms_username = std::env::var("lms_username").ok();
+    let lms_password = std::env::var("lms_password").ok();
     let lms_config_overwrite = std::env::var("lms_config_overwrite")
         .unwrap_or_else(|_| "false".to_string())
         .parse::<bool>()
         .expect("lms_config_overwrite must be a boolean");
+    if check {
+        settings::main_check(&settings);
+        return;
+    }
+    let problems = settings.problems();
+    if !problems.is_empty() {
+        panic!("Invalid configuration: {}. Run `config check` for details", problems.join("; "));
+    }
+    settings::init(settings);
     match lms_launch_type.as_str() {
-        "server" => server::main_server(lms_port).await,
-        "client" => client::main_client(lms_host, lms_port).await,
-        "config" => config::main_config(lms_config_overwrite).await,
+        "server" => server::main_server().await,
+        "client" => client::main_client(lms_host, lms_port, lms_username, lms_password).await,
+        "migrate" => migrate::main_migrate().await,
+        "config" => config::main_config(lms_config_overwrite, lms_username, lms_password).await,
         _ => panic!("Unknown launch type: {}", lms_launch_type),
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,160 @@
+use log::{info, warn};
+use rusqlite::{Connection, OptionalExtension};
+use crate::settings::settings;
+
+/// Schema steps in order. Each brings the database to `version` and runs in a
+/// transaction together with the `dbv` bump; the baseline creates `lms_metadata`.
+const MIGRATIONS: &[(u64, &str)] = &[
+    (5, include_str!("../assets/table_init.sql")),
+    (6, include_str!("../assets/migrations/0006_loan_due_dates.sql")),
+    (7, include_str!("../assets/migrations/0007_loan_renewals.sql")),
+    (8, include_str!("../assets/migrations/0008_fines.sql")),
+    (9, include_str!("../assets/migrations/0009_hold_queue.sql")),
+    (10, include_str!("../assets/migrations/0010_circulation_policy.sql")),
+    (11, include_str!("../assets/migrations/0011_credentials.sql")),
+    (12, include_str!("../assets/migrations/0012_roles.sql")),
+    (13, include_str!("../assets/migrations/0013_book_search.sql")),
+    (14, include_str!("../assets/migrations/0014_audit.sql")),
+    (15, include_str!("../assets/migrations/0015_user_active.sql")),
+    (16, include_str!("../assets/migrations/0016_instance_events.sql")),
+    (17, include_str!("../assets/migrations/0017_bibliographic.sql")),
+    (18, include_str!("../assets/migrations/0018_contributors.sql")),
+    (19, include_str!("../assets/migrations/0019_classification.sql")),
+    (20, include_str!("../assets/migrations/0020_series.sql")),
+    (21, include_str!("../assets/migrations/0021_location_tree.sql")),
+    (22, include_str!("../assets/migrations/0022_transfers.sql")),
+    (23, include_str!("../assets/migrations/0023_stocktake.sql")),
+    (24, include_str!("../assets/migrations/0024_lost_loans.sql")),
+    (25, include_str!("../assets/migrations/0025_event_actor.sql")),
+    (26, include_str!("../assets/migrations/0026_credits.sql")),
+];
+
+pub fn latest_version() -> u64 {
+    MIGRATIONS.last().map(|(version, _)| *version).unwrap_or(0)
+}
+
+/// Version recorded in `lms_metadata.dbv`, or 0 for an unconfigured database.
+pub fn schema_version(db: &Connection) -> rusqlite::Result<u64> {
+    let configured = db.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'lms_metadata'",
+        [],
+        |row| row.get::<_, i64>(0),
+    )? == 1;
+    if !configured {
+        return Ok(0);
+    }
+    let version = db.query_row(
+        "SELECT value FROM lms_metadata WHERE key = 'dbv'",
+        [],
+        |row| row.get::<_, String>(0),
+    ).optional()?;
+    Ok(version.and_then(|version| version.parse::<u64>().ok()).unwrap_or(0))
+}
+
+/// Applies every pending step, each in its own transaction, and returns the
+/// resulting version. Refuses schemas newer than this build knows about.
+pub fn migrate(db: &mut Connection) -> Result<u64, String> {
+    let current = schema_version(db).map_err(|err| format!("{}", err))?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(format!(
+            "database schema version {} is newer than the latest known version {}",
+            current, latest));
+    }
+    // Steps may rebuild or alter referenced tables, so foreign keys are only
+    // verified once a step is complete, right before it commits.
+    db.execute_batch("PRAGMA foreign_keys = OFF").map_err(|err| format!("{}", err))?;
+    let res = MIGRATIONS.iter()
+        .filter(|(version, _)| *version > current)
+        .try_for_each(|(version, script)| migrate_step(db, *version, script));
+    db.execute_batch("PRAGMA foreign_keys = ON").map_err(|err| format!("{}", err))?;
+    res?;
+    Ok(latest)
+}
+
+fn migrate_step(db: &mut Connection, version: u64, script: &str) -> Result<(), String> {
+    info!("Migrating database to version {}", version);
+    let tx = db.transaction().map_err(|err| format!("{}", err))?;
+    tx.execute_batch(script)
+        .and_then(|_| tx.execute(
+            "UPDATE lms_metadata SET value = ?1 WHERE key = 'dbv'",
+            [version.to_string()],
+        ))
+        .map_err(|err| format!("migration to version {} failed: {}", version, err))?;
+    let violations = tx.query_row(
+        "SELECT COUNT(*) FROM pragma_foreign_key_check",
+        [],
+        |row| row.get::<_, i64>(0),
+    ).map_err(|err| format!("{}", err))?;
+    if violations > 0 {
+        return Err(format!("migration to version {} failed: {} foreign key violations",
+            version, violations));
+    }
+    tx.commit().map_err(|err| format!("migration to version {} failed: {}", version, err))
+}
+
+/// A fresh in-memory database at the latest version, for tests.
+#[cfg(test)]
+pub fn test_database() -> Connection {
+    crate::settings::init_for_tests();
+    let mut db = Connection::open_in_memory().unwrap();
+    migrate(&mut db).unwrap();
+    db
+}
+
+pub async fn main_migrate() {
+    settings().init_logger();
+    info!("Migrating database");
+    let mut db = rusqlite::Connection::open(&settings().database.path).unwrap();
+    let current = schema_version(&db).unwrap();
+    if current == 0 {
+        warn!("Database is not configured yet; creating it from scratch");
+    }
+    match migrate(&mut db) {
+        Ok(version) if version == current => info!("Database is up to date at version {}", version),
+        Ok(version) => info!("Database migrated from version {} to {}", current, version),
+        Err(err) => panic!("Failed to migrate database: {}", err),
+    }
+    db.close().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dbv5_databases_migrate_to_the_latest_version() {
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute_batch(MIGRATIONS[0].1).unwrap();
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', ''); \
+            INSERT INTO lms_user (username, email, info) VALUES ('bob', 'bob@example.com', ''); \
+            INSERT INTO lms_book (title, author, info) VALUES ('Dune', 'Frank Herbert', ''); \
+            INSERT INTO lms_location (name, info) VALUES ('main', ''); \
+            INSERT INTO lms_instance (bid, lid) VALUES (1, 1); \
+            INSERT INTO lms_instance (bid, lid) VALUES (1, 1); \
+            INSERT INTO lms_instance (bid, lid) VALUES (1, 1); \
+            INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (1, 1, '2020-01-01', 0); \
+            INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (2, 2, '2020-02-01', 3); \
+            INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (NULL, 3, '2020-03-01', 2); \
+            INSERT INTO lms_history (uid, iid, date, return_date) VALUES (2, 1, '2019-01-01', '2019-01-15');",
+        ).unwrap();
+        assert_eq!(schema_version(&db).unwrap(), 5);
+        assert_eq!(migrate(&mut db).unwrap(), latest_version());
+        assert_eq!(schema_version(&db).unwrap(), latest_version());
+        let violations = db.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get::<_, i64>(0))
+            .unwrap();
+        assert_eq!(violations, 0);
+        let due_date = db.query_row("SELECT due_date FROM lms_occupation WHERE iid = 1", [], |row| row.get::<_, String>(0))
+            .unwrap();
+        assert_eq!(due_date, "2020-01-31");
+        let mut history = db.prepare("SELECT uid, iid, lost FROM lms_history ORDER BY date").unwrap();
+        let history = history.query_map([], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?, row.get::<_, bool>(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(history, [(2, 1, false), (2, 2, true)]);
+        let login = db.query_row("SELECT COUNT(*) FROM lms_credential", [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!(login, 0);
+    }
+}
@@ -1,10 +1,90 @@
+use std::fmt;
 use serde::{Serialize, Deserialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PolicyViolation {
+    pub rule: String,
+    pub limit: u64,
+    pub current: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    Conflict,
+    Validation,
+    Unauthorized,
+    Forbidden,
+    Internal,
+    UserNotFound,
+    UserInactive,
+    BookNotFound,
+    InstanceNotFound,
+    InstanceOccupied,
+    InstanceOnHold,
+    InstanceUnavailable,
+    InstanceNotBorrowed,
+    InstanceNotOccupied,
+    InstanceNotInRepair,
+    InstanceNotLost,
+    NotBorrower,
+    AlreadyHeld,
+    InstanceInTransit,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::Validation => "validation",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::Internal => "internal",
+            ErrorCode::UserNotFound => "user_not_found",
+            ErrorCode::UserInactive => "user_inactive",
+            ErrorCode::BookNotFound => "book_not_found",
+            ErrorCode::InstanceNotFound => "instance_not_found",
+            ErrorCode::InstanceOccupied => "instance_occupied",
+            ErrorCode::InstanceOnHold => "instance_on_hold",
+            ErrorCode::InstanceUnavailable => "instance_unavailable",
+            ErrorCode::InstanceNotBorrowed => "instance_not_borrowed",
+            ErrorCode::InstanceNotOccupied => "instance_not_occupied",
+            ErrorCode::InstanceNotInRepair => "instance_not_in_repair",
+            ErrorCode::InstanceNotLost => "instance_not_lost",
+            ErrorCode::NotBorrower => "not_borrower",
+            ErrorCode::AlreadyHeld => "already_held",
+            ErrorCode::InstanceInTransit => "instance_in_transit",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseError {
+    pub success: bool,
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub violation: Option<PolicyViolation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct RequestUserRegister {
     pub username: String,
     pub email: String,
     pub info: String,
+    pub password: String,
+}
+
+impl fmt::Debug for RequestUserRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestUserRegister")
+            .field("username", &self.username)
+            .field("email", &self.email)
+            .field("info", &self.info)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -38,6 +118,10 @@ pub struct ResponseUserInfo {
     pub username: String,
     pub email: String,
     pub info: String,
+    pub cid: u64,
+    pub role: u64,
+    #[serde(default)]
+    pub active: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -64,6 +148,7 @@ pub struct ResponseUserBorrowed {
     pub success: bool,
     pub message: String,
     pub iid_list: String,
+    pub due_list: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -75,7 +160,39 @@ pub struct RequestUserReserved {
 pub struct ResponseUserReserved {
     pub success: bool,
     pub message: String,
-    pub iid_list: String,
+    pub holds: Vec<Hold>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OverdueLoan {
+    pub uid: u64,
+    pub iid: u64,
+    pub bid: u64,
+    pub date: String,
+    pub due_date: String,
+    pub days_late: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserOverdue {
+    pub uid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserOverdue {
+    pub success: bool,
+    pub message: String,
+    pub overdue: Vec<OverdueLoan>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestAdminOverdue {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAdminOverdue {
+    pub success: bool,
+    pub message: String,
+    pub overdue: Vec<OverdueLoan>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -92,6 +209,26 @@ pub struct ResponseUserUnregister {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RequestBookSearch {
     pub phrase: String,
+    /// Only books with this subject heading.
+    #[serde(default)]
+    pub subject: String,
+    /// Only books with this tag.
+    #[serde(default)]
+    pub tag: String,
+    /// Only books whose call number is in `[class_from, class_to)`.
+    #[serde(default)]
+    pub class_from: String,
+    #[serde(default)]
+    pub class_to: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub bid: u64,
+    pub score: f64,
+    pub title: String,
+    pub author: String,
+    pub snippet: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -99,6 +236,7 @@ pub struct ResponseBookSearch {
     pub success: bool,
     pub message: String,
     pub bid_list: String,
+    pub results: Vec<SearchResult>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -113,6 +251,25 @@ pub struct ResponseBookInfo {
     pub title: String,
     pub author: String,
     pub info: String,
+    pub tid: u64,
+    #[serde(flatten)]
+    pub details: BookDetails,
+    #[serde(default)]
+    pub contributors: Vec<Contributor>,
+    #[serde(default)]
+    pub series: Option<SeriesMembership>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookByIsbn {
+    pub isbn: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookByIsbn {
+    pub success: bool,
+    pub message: String,
+    pub books: Vec<BookRecord>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -137,29 +294,92 @@ pub struct RequestBookBorrow {
 pub struct ResponseBookBorrow {
     pub success: bool,
     pub message: String,
+    pub due_date: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct RequestBookReserve {
+pub struct Hold {
+    pub hid: u64,
     pub uid: u64,
+    pub bid: u64,
     pub iid: u64,
+    pub status: u64,
+    pub position: u64,
+    pub date: String,
+    pub expiry_date: String,
+    /// Branch the hold is picked up at, 0 for wherever a copy is.
+    #[serde(default)]
+    pub pickup: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookReserve {
+    pub uid: u64,
+    pub bid: u64,
+    /// Branch to pick the copy up at. Copies elsewhere are transferred there.
+    #[serde(default)]
+    pub pickup: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ResponseBookReserve {
     pub success: bool,
     pub message: String,
+    pub hid: u64,
+    pub position: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestHoldCancel {
+    pub hid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseHoldCancel {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookHolds {
+    pub bid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookHolds {
+    pub success: bool,
+    pub message: String,
+    pub holds: Vec<Hold>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookRenew {
+    pub uid: u64,
+    pub iid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookRenew {
+    pub success: bool,
+    pub message: String,
+    pub due_date: String,
+    pub renewals: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RequestBookReturn {
     pub iid: u64,
+    /// When set, the return fails unless this user has the instance on loan.
+    #[serde(default)]
+    pub uid: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ResponseBookReturn {
     pub success: bool,
     pub message: String,
+    pub fine: u64,
+    pub held_for: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -167,6 +387,9 @@ pub struct RequestBookAdd {
     pub title: String,
     pub author: String,
     pub info: String,
+    pub tid: u64,
+    #[serde(flatten)]
+    pub details: BookDetails,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -174,6 +397,9 @@ pub struct ResponseBookAdd {
     pub success: bool,
     pub message: String,
     pub bid: u64,
+    /// Possible duplicates of the new book; it is added regardless.
+    #[serde(default)]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -193,6 +419,9 @@ pub struct RequestBookAlter {
     pub title: String,
     pub author: String,
     pub info: String,
+    pub tid: u64,
+    #[serde(flatten)]
+    pub details: BookDetails,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -238,6 +467,7 @@ pub struct ResponseBookInstanceInfo {
     pub bid: u64,
     pub lid: u64,
     pub status: u64,
+    pub due_date: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -261,12 +491,242 @@ pub struct RequestInstanceRelease {
 pub struct ResponseInstanceRelease {
     pub success: bool,
     pub message: String,
+    pub held_for: u64,
+}
+
+/// `expected_date` is when the instance should be back, or empty if unknown.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestInstanceRepair {
+    pub iid: u64,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub expected_date: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseInstanceRepair {
+    pub success: bool,
+    pub message: String,
+}
+
+/// An instance beyond repair is withdrawn instead of going back on the shelf.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestInstanceRepaired {
+    pub iid: u64,
+    pub repaired: bool,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseInstanceRepaired {
+    pub success: bool,
+    pub message: String,
+    pub held_for: u64,
+}
+
+/// With `bill`, the borrower, or the last one if the instance is not on
+/// loan, is charged the replacement fee.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestInstanceLost {
+    pub iid: u64,
+    #[serde(default)]
+    pub bill: bool,
+    #[serde(default)]
+    pub note: String,
+}
+
+/// `billed` and `fid` are 0 when nobody was charged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseInstanceLost {
+    pub success: bool,
+    pub message: String,
+    pub billed: u64,
+    pub fid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestInstanceFound {
+    pub iid: u64,
+    #[serde(default)]
+    pub note: String,
+}
+
+/// `reversed` is the total of unpaid replacement charges waived, `refunded`
+/// the total of paid ones now owed back to the patron.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseInstanceFound {
+    pub success: bool,
+    pub message: String,
+    pub reversed: u64,
+    #[serde(default)]
+    pub refunded: u64,
+    pub held_for: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestInstanceWithdraw {
+    pub iid: u64,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseInstanceWithdraw {
+    pub success: bool,
+    pub message: String,
+}
+
+/// `kind` is 0: sent to repair, 1: repaired, 2: beyond repair, 3: lost,
+/// 4: found, 5: withdrawn. `uid` and `fid` name the borrower billed or
+/// credited and the fine involved, or are 0.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstanceEvent {
+    pub eid: u64,
+    pub iid: u64,
+    pub kind: u64,
+    pub date: String,
+    pub due_date: String,
+    pub uid: u64,
+    pub fid: u64,
+    pub note: String,
+    /// Staff member who recorded the event, 0 when unknown.
+    #[serde(default)]
+    pub actor: u64,
+}
+
+/// One loan; `return_date` is empty while it is still out. A loan ended by
+/// the copy being reported lost has `lost` set and that date as its return.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryRecord {
+    pub uid: u64,
+    pub iid: u64,
+    pub date: String,
+    pub return_date: String,
+    pub book: BookRecord,
+    #[serde(default)]
+    pub lost: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InstanceHistoryEntry {
+    Loan(HistoryRecord),
+    Event(InstanceEvent),
+}
+
+/// `since` and `until` bound the loan or event date; `until` is exclusive.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookInstanceHistory {
+    pub iid: u64,
+    #[serde(default)]
+    pub since: String,
+    #[serde(default)]
+    pub until: String,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+/// Loans and maintenance events in date order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookInstanceHistory {
+    pub success: bool,
+    pub message: String,
+    pub entries: Vec<InstanceHistoryEntry>,
+    pub next_cursor: u64,
+}
+
+/// `since` and `until` bound the borrow date; `until` is exclusive.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserHistory {
+    pub uid: u64,
+    #[serde(default)]
+    pub since: String,
+    #[serde(default)]
+    pub until: String,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+/// Loans in borrow date order, including the ones still out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserHistory {
+    pub success: bool,
+    pub message: String,
+    pub loans: Vec<HistoryRecord>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fine {
+    pub fid: u64,
+    pub uid: u64,
+    pub iid: u64,
+    pub kind: u64,
+    pub amount: u64,
+    pub date: String,
+    pub status: u64,
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserFines {
+    pub uid: u64,
+}
+
+/// `balance` is what the user owes, `credit` what is owed back to them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserFines {
+    pub success: bool,
+    pub message: String,
+    pub balance: u64,
+    #[serde(default)]
+    pub credit: u64,
+    pub fines: Vec<Fine>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestFineAdd {
+    pub uid: u64,
+    pub iid: u64,
+    pub kind: u64,
+    pub amount: u64,
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseFineAdd {
+    pub success: bool,
+    pub message: String,
+    pub fid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestFineSettle {
+    pub fid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseFineSettle {
+    pub success: bool,
+    pub message: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RequestLocationAdd {
     pub name: String,
     pub info: String,
+    /// Location this one is nested in, or 0 for a new branch.
+    #[serde(default)]
+    pub parent: u64,
+    /// 0 for a branch, 1 for a floor, 2 for a room and 3 for a shelf.
+    #[serde(default)]
+    pub kind: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -279,6 +739,10 @@ pub struct ResponseLocationAdd {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RequestLocationRemove {
     pub lid: u64,
+    /// Location that takes over the instances still at `lid`. With 0, a
+    /// location that holds instances is not removed.
+    #[serde(default)]
+    pub target: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -298,4 +762,807 @@ pub struct RequestLocationAlter {
 pub struct ResponseLocationAlter {
     pub success: bool,
     pub message: String,
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestCategoryAdd {
+    pub name: String,
+    pub info: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseCategoryAdd {
+    pub success: bool,
+    pub message: String,
+    pub cid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestItemTypeAdd {
+    pub name: String,
+    pub info: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseItemTypeAdd {
+    pub success: bool,
+    pub message: String,
+    pub tid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserCategory {
+    pub uid: u64,
+    pub cid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserCategory {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserActive {
+    pub uid: u64,
+    pub active: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserActive {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestPolicySet {
+    pub cid: u64,
+    pub tid: u64,
+    pub max_loans: u64,
+    pub loan_period: u64,
+    pub renewal_limit: u64,
+    pub max_holds: u64,
+    pub fine_daily_rate: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponsePolicySet {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserPolicy {
+    pub uid: u64,
+    pub bid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserPolicy {
+    pub success: bool,
+    pub message: String,
+    pub max_loans: u64,
+    pub loan_period: u64,
+    pub renewal_limit: u64,
+    pub max_holds: u64,
+    pub fine_daily_rate: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestRoleGrant {
+    pub uid: u64,
+    pub role: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseRoleGrant {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Sets `uid`'s password without knowing the old one. Users carried over
+/// from databases older than passwords have none and cannot sign in until
+/// an administrator sets one here.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct RequestUserPassword {
+    pub uid: u64,
+    pub password: String,
+}
+
+impl fmt::Debug for RequestUserPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestUserPassword").field("uid", &self.uid).finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserPassword {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct RequestAuthLogin {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for RequestAuthLogin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestAuthLogin")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAuthLogin {
+    pub success: bool,
+    pub message: String,
+    pub uid: u64,
+    pub role: u64,
+    pub token: String,
+    pub expires: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestAuthLogout {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAuthLogout {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct RequestAuthPassword {
+    pub current_password: String,
+    pub password: String,
+}
+
+impl fmt::Debug for RequestAuthPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestAuthPassword").finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAuthPassword {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Bibliographic fields beyond title and author. Empty strings and zeros mean unknown.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct BookDetails {
+    /// ISBN-10 or ISBN-13 on input, always ISBN-13 on output.
+    pub isbn: String,
+    pub publisher: String,
+    pub year: u64,
+    pub edition: String,
+    pub language: String,
+    pub pages: u64,
+    pub subjects: Vec<String>,
+    /// Free labels, stored in lowercase.
+    pub tags: Vec<String>,
+    /// Dewey or CLC style classification with shelf mark, e.g. `512.5 KNU`.
+    pub call_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BookRecord {
+    pub bid: u64,
+    pub title: String,
+    pub author: String,
+    pub info: String,
+    pub tid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OccupationRecord {
+    pub kind: u64,
+    pub date: String,
+    pub due_date: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstanceRecord {
+    pub iid: u64,
+    pub bid: u64,
+    pub lid: u64,
+    pub location: String,
+    pub status: u64,
+    pub occupation: Option<OccupationRecord>,
+    #[serde(default)]
+    pub withdrawn_date: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoanRecord {
+    pub iid: u64,
+    pub date: String,
+    pub due_date: String,
+    pub renewals: u64,
+    pub book: BookRecord,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HoldRecord {
+    pub hold: Hold,
+    pub book: BookRecord,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookSearchV2 {
+    pub phrase: String,
+    /// Only books with this subject heading.
+    #[serde(default)]
+    pub subject: String,
+    /// Only books with this tag.
+    #[serde(default)]
+    pub tag: String,
+    /// Only books whose call number is in `[class_from, class_to)`.
+    #[serde(default)]
+    pub class_from: String,
+    #[serde(default)]
+    pub class_to: String,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookSearchV2 {
+    pub success: bool,
+    pub message: String,
+    pub results: Vec<SearchResult>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookInstanceV2 {
+    pub bid: u64,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookInstanceV2 {
+    pub success: bool,
+    pub message: String,
+    pub instances: Vec<InstanceRecord>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserBorrowedV2 {
+    pub uid: u64,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserBorrowedV2 {
+    pub success: bool,
+    pub message: String,
+    pub loans: Vec<LoanRecord>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestUserReservedV2 {
+    pub uid: u64,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseUserReservedV2 {
+    pub success: bool,
+    pub message: String,
+    pub holds: Vec<HoldRecord>,
+    pub next_cursor: u64,
+}
+
+/// Every filter is optional: 0 or an empty string matches anything. `since`
+/// and `until` take a date or date and time; `until` is exclusive.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestAdminAudit {
+    #[serde(default)]
+    pub actor: u64,
+    #[serde(default)]
+    pub entity: String,
+    #[serde(default)]
+    pub target: u64,
+    #[serde(default)]
+    pub since: String,
+    #[serde(default)]
+    pub until: String,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+/// `actor` is 0 for anonymous requests, `target` 0 when nothing was touched,
+/// and `before`/`after` are null when the record did not exist.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub aid: u64,
+    pub actor: u64,
+    pub endpoint: String,
+    pub entity: String,
+    pub target: u64,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+    pub date: String,
+}
+
+/// Newest entries first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAdminAudit {
+    pub success: bool,
+    pub message: String,
+    pub entries: Vec<AuditEntry>,
+    pub next_cursor: u64,
+}
+
+/// A person credited on a book. `role` is 0 for author, 1 for editor,
+/// 2 for translator and 3 for illustrator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Contributor {
+    pub aid: u64,
+    #[serde(default)]
+    pub name: String,
+    pub role: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestAuthorAdd {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAuthorAdd {
+    pub success: bool,
+    pub message: String,
+    pub aid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestAuthorRename {
+    pub aid: u64,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAuthorRename {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Moves every credit of author `from` to author `aid` and removes `from`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestAuthorMerge {
+    pub aid: u64,
+    pub from: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseAuthorMerge {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Replaces the contributors of a book, in title page order. Names are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookContributors {
+    pub bid: u64,
+    pub contributors: Vec<Contributor>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookContributors {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookByAuthor {
+    pub aid: u64,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuthorBook {
+    pub book: BookRecord,
+    pub roles: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookByAuthor {
+    pub success: bool,
+    pub message: String,
+    pub name: String,
+    pub books: Vec<AuthorBook>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookBySubject {
+    pub subject: String,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookBySubject {
+    pub success: bool,
+    pub message: String,
+    pub books: Vec<BookRecord>,
+    pub next_cursor: u64,
+}
+
+/// Books with a call number in `[from, to)`, in shelf order. An empty `to`
+/// leaves the range open.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookByClass {
+    pub from: String,
+    #[serde(default)]
+    pub to: String,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClassifiedBook {
+    pub book: BookRecord,
+    pub call_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookByClass {
+    pub success: bool,
+    pub message: String,
+    pub books: Vec<ClassifiedBook>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeriesMembership {
+    pub sid: u64,
+    pub title: String,
+    pub volume: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestSeriesAdd {
+    pub title: String,
+    #[serde(default)]
+    pub info: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseSeriesAdd {
+    pub success: bool,
+    pub message: String,
+    pub sid: u64,
+}
+
+/// Makes book `bid` volume `volume` of series `sid`. A `sid` of 0 takes the
+/// book out of its series.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookSeries {
+    pub bid: u64,
+    pub sid: u64,
+    #[serde(default)]
+    pub volume: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookSeries {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestSeriesVolumes {
+    pub sid: u64,
+}
+
+/// A volume of a series; `available` counts its copies on the shelf.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeriesVolume {
+    pub volume: u64,
+    pub book: BookRecord,
+    pub available: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseSeriesVolumes {
+    pub success: bool,
+    pub message: String,
+    pub title: String,
+    pub info: String,
+    pub volumes: Vec<SeriesVolume>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestBookNextVolume {
+    pub bid: u64,
+}
+
+/// `next` is `None` for the last volume of a series.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseBookNextVolume {
+    pub success: bool,
+    pub message: String,
+    pub next: Option<SeriesVolume>,
+}
+
+/// The subtree under `lid`, or every branch when `lid` is 0.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestLocationTree {
+    #[serde(default)]
+    pub lid: u64,
+}
+
+/// A location in depth-first order. `parent` is 0 for a branch and
+/// `instances` counts the copies kept directly at this location.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LocationNode {
+    pub lid: u64,
+    pub parent: u64,
+    pub kind: u64,
+    pub depth: u64,
+    pub name: String,
+    pub info: String,
+    pub instances: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseLocationTree {
+    pub success: bool,
+    pub message: String,
+    pub locations: Vec<LocationNode>,
+}
+
+/// Instances kept anywhere under location `lid`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestLocationContents {
+    pub lid: u64,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseLocationContents {
+    pub success: bool,
+    pub message: String,
+    pub instances: Vec<InstanceRecord>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestTransferRequest {
+    pub iid: u64,
+    pub destination: u64,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseTransferRequest {
+    pub success: bool,
+    pub message: String,
+    pub xid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestTransferDispatch {
+    pub xid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseTransferDispatch {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestTransferReceive {
+    pub xid: u64,
+}
+
+/// `held_for` is the patron the received copy went to the hold shelf for, or 0.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseTransferReceive {
+    pub success: bool,
+    pub message: String,
+    pub held_for: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestTransferCancel {
+    pub xid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseTransferCancel {
+    pub success: bool,
+    pub message: String,
+}
+
+/// Transfers from or to anywhere under location `lid` (0 for any), only
+/// those still requested or dispatched when `open` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestTransferList {
+    #[serde(default)]
+    pub lid: u64,
+    #[serde(default)]
+    pub open: bool,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+/// `status` is 0 for requested, 1 for dispatched, 2 for received and 3 for
+/// cancelled. `hid` is 0 for transfers not made for a hold.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub xid: u64,
+    pub iid: u64,
+    pub origin: u64,
+    pub destination: u64,
+    pub hid: u64,
+    pub status: u64,
+    pub request_date: String,
+    pub dispatch_date: String,
+    pub receive_date: String,
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseTransferList {
+    pub success: bool,
+    pub message: String,
+    pub transfers: Vec<Transfer>,
+    pub next_cursor: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestStocktakeOpen {
+    pub lid: u64,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseStocktakeOpen {
+    pub success: bool,
+    pub message: String,
+    pub vid: u64,
+}
+
+/// Copies scanned at location `lid`, which defaults to the stocktake's own.
+/// A copy scanned at a location containing the one it is recorded at, such
+/// as its branch, counts as in place.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestStocktakeScan {
+    pub vid: u64,
+    #[serde(default)]
+    pub lid: u64,
+    pub iids: Vec<u64>,
+}
+
+/// `unknown` lists the scanned iids that name no instance; the rest are recorded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseStocktakeScan {
+    pub success: bool,
+    pub message: String,
+    pub scanned: u64,
+    pub unknown: Vec<u64>,
+}
+
+/// With `mark_lost`, missing copies are marked lost as the stocktake closes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestStocktakeClose {
+    pub vid: u64,
+    #[serde(default)]
+    pub mark_lost: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseStocktakeClose {
+    pub success: bool,
+    pub message: String,
+    pub report: StocktakeReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestStocktakeReport {
+    pub vid: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseStocktakeReport {
+    pub success: bool,
+    pub message: String,
+    pub stocktake: Stocktake,
+    pub report: StocktakeReport,
+}
+
+/// Stocktakes of anywhere under location `lid` (0 for any), only those
+/// still open when `open` is set.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestStocktakeList {
+    #[serde(default)]
+    pub lid: u64,
+    #[serde(default)]
+    pub open: bool,
+    #[serde(default)]
+    pub limit: u64,
+    #[serde(default)]
+    pub cursor: u64,
+}
+
+/// `status` is 0 for open and 1 for closed. `scanned` counts the copies
+/// scanned so far.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Stocktake {
+    pub vid: u64,
+    pub lid: u64,
+    pub status: u64,
+    pub open_date: String,
+    pub close_date: String,
+    pub note: String,
+    pub scanned: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseStocktakeList {
+    pub success: bool,
+    pub message: String,
+    pub stocktakes: Vec<Stocktake>,
+    pub next_cursor: u64,
+}
+
+/// What a stocktake found: copies expected under its location but not
+/// scanned, copies scanned outside the part of the tree they are recorded
+/// in, and copies scanned while recorded as borrowed. An open stocktake
+/// reports what closing it now would.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StocktakeReport {
+    pub missing: Vec<StocktakeFinding>,
+    pub misplaced: Vec<StocktakeFinding>,
+    pub borrowed: Vec<StocktakeFinding>,
+}
+
+/// `lid` is where the copy is recorded and `found` where it was scanned, 0
+/// for missing copies. `lost` is set when closing marked the copy lost.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StocktakeFinding {
+    pub iid: u64,
+    pub bid: u64,
+    pub lid: u64,
+    pub found: u64,
+    pub lost: bool,
+}
@@ -1,728 +1,3237 @@
 use log::{info};
+use rusqlite::{Connection, OptionalExtension};
 use crate::model::*;
-use crate::server::database;
+use crate::server::auth::set_password;
+use crate::server::audit::Entity;
+use crate::server::error::{ApiError, ApiResult};
+use crate::server::policy::*;
+use crate::server::role::Role;
 use crate::utils::*;
 
+const DEFAULT_FINE_REPLACEMENT: u64 = 2000;
+const DEFAULT_FINE_BORROW_LIMIT: u64 = 1000;
+const DEFAULT_HOLD_SHELF_DAYS: u64 = 7;
+const DEFAULT_PAGE_LIMIT: u64 = 20;
+const MAX_PAGE_LIMIT: u64 = 100;
+
+/// Fails with `message` when an UPDATE or DELETE matched no row.
+fn affected(rows: usize, message: &str) -> ApiResult<()> {
+    match rows {
+        0 => Err(ApiError::not_found(message)),
+        _ => Ok(()),
+    }
+}
+
+/// Rows that keep a user from being removed, with the reason given to the client.
+const USER_REFERENCES: [(&str, &str); 5] = [
+    ("lms_occupation", "user still has borrowed or lost instances"),
+    ("lms_hold", "user has holds on record; deactivate the user instead"),
+    ("lms_fine", "user has fines on record; deactivate the user instead"),
+    ("lms_history", "user has loan history; deactivate the user instead"),
+    ("lms_instance_event", "user is named in instance events; deactivate the user instead"),
+];
+
+/// Rows that keep a book from being removed, with the reason given to the client.
+const BOOK_REFERENCES: [(&str, &str); 2] = [
+    ("lms_instance", "book still has instances; withdraw them instead"),
+    ("lms_hold", "book has holds on record"),
+];
+
+/// Rows that keep an instance from being removed, with the reason given to the client.
+const INSTANCE_REFERENCES: [(&str, &str); 7] = [
+    ("lms_transfer", "instance has transfers on record; withdraw it instead"),
+    ("lms_hold", "instance has holds on record; withdraw it instead"),
+    ("lms_history", "instance has loan history; withdraw it instead"),
+    ("lms_fine", "instance has fines on record; withdraw it instead"),
+    ("lms_instance_event", "instance has maintenance events; withdraw it instead"),
+    ("lms_stocktake_scan", "instance was scanned in a stocktake; withdraw it instead"),
+    ("lms_stocktake_finding", "instance is in a stocktake report; withdraw it instead"),
+];
+
+/// Fails with `missing` when the record does not exist, or with the first
+/// reason in `references` whose table still points at it.
+fn refuse_referenced(
+    db: &Connection,
+    table: &str,
+    column: &str,
+    id: u64,
+    references: &[(&str, &str)],
+    missing: &str,
+) -> ApiResult<()> {
+    db.query_row(&format!("SELECT 1 FROM {table} WHERE {column} = ?1"), [id], |_| Ok(()))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found(missing))?;
+    for (dependent, reason) in references {
+        let rows = db.query_row(
+            &format!("SELECT COUNT(*) FROM {dependent} WHERE {column} = ?1"),
+            [id],
+            |row| row.get::<_, u64>(0),
+        )?;
+        if rows > 0 {
+            return Err(ApiError::conflict(reason));
+        }
+    }
+    Ok(())
+}
+
 #[inline]
-pub fn user_register(req: RequestUserRegister) -> ResponseUserRegister {
+pub fn user_register(db: &mut Connection, req: RequestUserRegister) -> ApiResult<ResponseUserRegister> {
     info!("user_register IN {:?}", req);
     if !is_username_legit(&req.username) {
-        info!("user_register ERR username is not legit");
-        return ResponseUserRegister {
-            success: false,
-            uid: 0,
-            message: "username is not legit".to_string(),
-        };
+        return Err(ApiError::validation("username is not legit"));
     }
     if !is_email_legit(&req.email) {
-        info!("user_register ERR email is not legit");
-        return ResponseUserRegister {
-            success: false,
-            uid: 0,
-            message: "email is not legit".to_string(),
-        };
+        return Err(ApiError::validation("email is not legit"));
+    }
+    if !is_password_legit(&req.password) {
+        return Err(ApiError::validation("password is not legit"));
     }
-    let db = database();
-    if let Err(err) = db.execute(
+    let tx = db.savepoint()?;
+    tx.execute(
         "INSERT INTO lms_user (username, email, info) VALUES (?1, ?2, ?3)",
         [&req.username, &req.email, &req.info],
-    ) {
-        info!("user_register ERR {:?}", err);
-        ResponseUserRegister {
-            success: false,
-            uid: 0,
-            message: format!("{}", err),
-        }
-    } else {
-        let uid = db.last_insert_rowid() as u64;
-        info!("user_register OUT {}", uid);
-        ResponseUserRegister {
-            success: true,
-            uid,
-            message: "success".to_string(),
-        }
-    }
+    )?;
+    let uid = tx.last_insert_rowid() as u64;
+    set_password(&tx, uid, &req.password)?;
+    tx.commit()?;
+    info!("user_register OUT {}", uid);
+    Ok(ResponseUserRegister {
+        success: true,
+        uid,
+        message: "success".to_string(),
+    })
 }
 
 #[inline]
-pub fn user_lookup(req: RequestUserLookup) -> ResponseUserLookup {
+pub fn user_lookup(db: &mut Connection, req: RequestUserLookup) -> ApiResult<ResponseUserLookup> {
     info!("user_lookup IN {:?}", req);
-    let db = database();
     let (phrase, query) = if req.phrase.starts_with(':') {
         (&req.phrase[1..], "SELECT uid FROM lms_user WHERE email = ?1")
     } else {
         (req.phrase.as_str(), "SELECT uid FROM lms_user WHERE username = ?1")
     };
-    let uid = db.query_row(query, [phrase], |row| row.get(0));
-    match uid {
-        Ok(uid) => {
-            info!("user_lookup OUT {:?}", uid);
-            ResponseUserLookup {
-                success: true,
-                uid,
-                message: "success".to_string(),
-            }
-        }
-        Err(err) => {
-            info!("user_lookup ERR {:?}", err);
-            ResponseUserLookup {
-                success: false,
-                uid: 0,
-                message: format!("{}", err),
-            }
-        }
-    }
+    let uid = db.query_row(query, [phrase], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("user does not exist"))?;
+    info!("user_lookup OUT {:?}", uid);
+    Ok(ResponseUserLookup {
+        success: true,
+        uid,
+        message: "success".to_string(),
+    })
 }
 
 #[inline]
-pub fn user_alter(req: RequestUserAlter) -> ResponseUserAlter {
+pub fn user_alter(db: &mut Connection, req: RequestUserAlter) -> ApiResult<ResponseUserAlter> {
     info!("user_alter IN {:?}", req);
     if !is_username_legit(&req.username) {
-        info!("user_alter ERR username is not legit");
-        return ResponseUserAlter {
-            success: false,
-            message: "username is not legit".to_string(),
-        };
+        return Err(ApiError::validation("username is not legit"));
     }
-    let res = database().execute(
+    let rows = db.execute(
         "UPDATE lms_user SET username = ?1, email = ?2, info = ?3 WHERE uid = ?4",
         [&req.username, &req.email, &req.info, &req.uid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("user_alter_name OUT {:?}", req);
-            ResponseUserAlter {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("user_alter_name ERR {:?}", err);
-            ResponseUserAlter {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
-    }
+    )?;
+    affected(rows, "user does not exist")?;
+    info!("user_alter_name OUT {:?}", req);
+    Ok(ResponseUserAlter {
+        success: true,
+        message: "success".to_string(),
+    })
 }
 
 #[inline]
-pub fn user_borrowed(req: RequestUserBorrowed) -> ResponseUserBorrowed {
+pub fn user_borrowed(db: &mut Connection, req: RequestUserBorrowed) -> ApiResult<ResponseUserBorrowed> {
     info!("user_borrowed IN {:?}", req);
-    let db = database();
     let mut stmt = db.prepare(
-        "SELECT iid FROM lms_occupation WHERE uid = ?1 AND kind = 0",
-    ).unwrap();
-    let iid_iter = stmt
-        .query_map([&req.uid.to_string()], |row| row.get(0));
-    let iid_iter = match iid_iter {
-        Ok(iid_iter) => iid_iter,
-        Err(err) => {
-            info!("user_borrowed ERR {:?}", err);
-            return ResponseUserBorrowed {
-                success: false,
-                message: format!("{}", err),
-                iid_list: String::new(),
-            };
-        }
-    };
-    let iid_list = iid_iter
-        .map(|iid: Result<u64, _>| iid.unwrap().to_string())
-        .collect::<Vec<_>>()
-        .join(",");
-    info!("user_borrowed OUT {:?}", iid_list);
-    ResponseUserBorrowed {
+        "SELECT iid, due_date FROM lms_occupation WHERE uid = ?1 AND kind = 0",
+    )?;
+    let loans = stmt
+        .query_map([&req.uid.to_string()], |row| {
+            Ok((row.get::<_, u64>(0)?, row.get::<_, Option<String>>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (iid_list, due_list): (Vec<_>, Vec<_>) = loans.into_iter()
+        .map(|(iid, due_date)| (iid.to_string(), due_date.unwrap_or_default()))
+        .unzip();
+    let iid_list = iid_list.join(",");
+    let due_list = due_list.join(",");
+    info!("user_borrowed OUT {:?} {:?}", iid_list, due_list);
+    Ok(ResponseUserBorrowed {
         success: true,
         message: "success".to_string(),
         iid_list,
-    }
+        due_list,
+    })
 }
 
 #[inline]
-pub fn user_reserved(req: RequestUserReserved) -> ResponseUserReserved {
-    info!("user_borrowed IN {:?}", req);
-    let db = database();
-    let mut stmt = db.prepare(
-        "SELECT iid FROM lms_occupation WHERE uid = ?1 AND kind = 1",
-    ).unwrap();
-    let iid_iter = stmt
-        .query_map([&req.uid.to_string()], |row| row.get(0));
-    let iid_iter = match iid_iter {
-        Ok(iid_iter) => iid_iter,
-        Err(err) => {
-            info!("user_borrowed ERR {:?}", err);
-            return ResponseUserReserved {
-                success: false,
-                message: format!("{}", err),
-                iid_list: String::new(),
-            };
-        }
-    };
-    let iid_list = iid_iter
-        .map(|iid: Result<u64, _>| iid.unwrap().to_string())
-        .collect::<Vec<_>>()
-        .join(",");
-    info!("user_borrowed OUT {:?}", iid_list);
-    ResponseUserReserved {
+pub fn user_reserved(db: &mut Connection, req: RequestUserReserved) -> ApiResult<ResponseUserReserved> {
+    info!("user_reserved IN {:?}", req);
+    let holds = holds_of(db, "h.uid = ?1", req.uid)?;
+    info!("user_reserved OUT {:?}", holds);
+    Ok(ResponseUserReserved {
         success: true,
         message: "success".to_string(),
-        iid_list,
-    }
+        holds,
+    })
 }
 
 #[inline]
-pub fn user_unregister(req: RequestUserUnregister) -> ResponseUserUnregister {
+pub fn user_unregister(db: &mut Connection, req: RequestUserUnregister) -> ApiResult<ResponseUserUnregister> {
     info!("user_unregister IN {:?}", req);
-    let res = database().execute(
-        "DELETE FROM lms_user WHERE uid = ?1",
-        [&req.uid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("user_unregister OUT {:?}", req);
-            ResponseUserUnregister {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("user_unregister ERR {:?}", err);
-            ResponseUserUnregister {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
-    }
+    let tx = db.savepoint()?;
+    refuse_referenced(&tx, "lms_user", "uid", req.uid, &USER_REFERENCES, "user does not exist")?;
+    tx.execute("DELETE FROM lms_session WHERE uid = ?1", [req.uid])?;
+    tx.execute("DELETE FROM lms_credential WHERE uid = ?1", [req.uid])?;
+    let rows = tx.execute("DELETE FROM lms_user WHERE uid = ?1", [req.uid])?;
+    affected(rows, "user does not exist")?;
+    tx.commit()?;
+    info!("user_unregister OUT {:?}", req);
+    Ok(ResponseUserUnregister {
+        success: true,
+        message: "success".to_string(),
+    })
 }
 
 #[inline]
-pub fn user_borrow(req: RequestBookBorrow) -> ResponseBookBorrow {
+pub fn user_borrow(db: &mut Connection, req: RequestBookBorrow) -> ApiResult<ResponseBookBorrow> {
     info!("user_borrow IN {:?}", req);
-    let res = database().execute(
-        "INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (?1, ?2, date('now'), 0)",
-        [&req.uid.to_string(), &req.iid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("user_borrow OUT {:?}", req);
-            ResponseBookBorrow {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("user_borrow ERR {:?}", err);
-            ResponseBookBorrow {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
-    }
+    let due_date = borrow_instance(db, req.uid, req.iid)?;
+    info!("user_borrow OUT {:?} {}", req, due_date);
+    Ok(ResponseBookBorrow {
+        success: true,
+        message: "success".to_string(),
+        due_date,
+    })
 }
 
-#[inline]
-pub fn user_return(req: RequestBookReturn) -> ResponseBookReturn {
-    info!("user_return IN {:?}", req);
-    let res = database().execute(
-        "DELETE FROM lms_occupation WHERE iid = ?2",
-        [&req.iid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("user_return OUT {:?}", req);
-            ResponseBookReturn {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("user_return ERR {:?}", err);
-            ResponseBookReturn {
-                success: false,
-                message: format!("{}", err),
+fn borrow_instance(db: &mut Connection, uid: u64, iid: u64) -> ApiResult<String> {
+    let tx = db.savepoint()?;
+    check_user_active(&tx, uid)?;
+    let bid = instance_book(&tx, iid)?;
+    let balance = fine_balance(&tx, uid)?;
+    let borrow_limit = policy_u64(&tx, "fine_borrow_limit", DEFAULT_FINE_BORROW_LIMIT);
+    if balance > borrow_limit {
+        return Err(ApiError::Policy(PolicyViolation {
+            rule: "fine_limit".to_string(),
+            limit: borrow_limit,
+            current: balance,
+        }));
+    }
+    let policy = Policy::for_book(&tx, uid, bid)?;
+    if let Some(violation) = policy.check_borrow(&tx, uid, bid)? {
+        return Err(ApiError::Policy(violation));
+    }
+    match occupation_of(&tx, iid)? {
+        None => {}
+        // Picking up a copy from the hold shelf fulfils the hold, but only the borrower's own.
+        Some((_, 1)) => {
+            let fulfilled = tx.execute(
+                "UPDATE lms_hold SET status = 2 WHERE uid = ?1 AND iid = ?2 AND status = 1",
+                [uid, iid],
+            )?;
+            if fulfilled == 0 {
+                return Err(ApiError::invariant(ErrorCode::InstanceOnHold, "instance is on hold for another user"));
             }
+            tx.execute("DELETE FROM lms_occupation WHERE iid = ?1", [iid])?;
         }
+        Some((_, kind)) => return Err(occupied(kind)),
     }
+    let due_date = tx.query_row(
+        "INSERT INTO lms_occupation (uid, iid, date, due_date, kind) \
+        VALUES (?1, ?2, date('now'), date('now', ?3), 0) \
+        RETURNING due_date",
+        [&uid.to_string(), &iid.to_string(), &format!("+{} days", policy.loan_period)],
+        |row| row.get(0),
+    )?;
+    tx.commit()?;
+    Ok(due_date)
 }
 
 #[inline]
-pub fn user_reserve(req: RequestBookReserve) -> ResponseBookReserve {
-    info!("user_reserve IN {:?}", req);
-    let db = database();
-    let res = db.execute(
-        "INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (?1, ?2, date('now'), ?3)",
-        [&req.uid.to_string(), &req.iid.to_string(), &1.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("user_reserve OUT {:?}", req);
-            ResponseBookReserve {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("user_reserve ERR {:?}", err);
-            ResponseBookReserve {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
+pub fn user_renew(db: &mut Connection, req: RequestBookRenew) -> ApiResult<ResponseBookRenew> {
+    info!("user_renew IN {:?}", req);
+    let (due_date, renewals) = renew_loan(db, req.uid, req.iid)?;
+    info!("user_renew OUT {:?} {} {}", req, due_date, renewals);
+    Ok(ResponseBookRenew {
+        success: true,
+        message: "success".to_string(),
+        due_date,
+        renewals,
+    })
+}
+
+fn renew_loan(db: &mut Connection, uid: u64, iid: u64) -> ApiResult<(String, u64)> {
+    let tx = db.savepoint()?;
+    check_user_active(&tx, uid)?;
+    let renewals = tx.query_row(
+        "SELECT renewals FROM lms_occupation WHERE uid = ?1 AND iid = ?2 AND kind = 0",
+        [uid, iid],
+        |row| row.get::<_, u64>(0),
+    ).optional()?.ok_or_else(|| ApiError::not_found("instance is not borrowed by this user"))?;
+    let policy = Policy::for_instance(&tx, uid, iid)?;
+    if let Some(violation) = policy.check_renew(renewals) {
+        return Err(ApiError::Policy(violation));
+    }
+    let reserved = tx.query_row(
+        "SELECT COUNT(*) FROM lms_hold \
+        WHERE status = 0 AND uid != ?1 \
+        AND bid = (SELECT bid FROM lms_instance WHERE iid = ?2)",
+        [uid, iid],
+        |row| row.get::<_, u64>(0),
+    )?;
+    if reserved > 0 {
+        return Err(ApiError::conflict("title is reserved by another user"));
     }
+    let renewed = tx.query_row(
+        "UPDATE lms_occupation \
+        SET due_date = date(max(ifnull(due_date, date('now')), date('now')), ?3), \
+        renewals = renewals + 1 \
+        WHERE uid = ?1 AND iid = ?2 AND kind = 0 \
+        RETURNING due_date, renewals",
+        [&uid.to_string(), &iid.to_string(), &format!("+{} days", policy.loan_period)],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    tx.commit()?;
+    Ok(renewed)
 }
 
 #[inline]
-pub fn user_info(req: RequestUserInfo) -> ResponseUserInfo {
-    info!("user_info IN {:?}", req);
-    let res = database().query_row(
-        "SELECT username, email, info FROM lms_user WHERE uid = ?1",
-        [&req.uid.to_string()],
-        |row| {
-            Ok((
-                row.get(0).unwrap(),
-                row.get(1).unwrap(),
-                row.get(2).unwrap(),
-            ))
-        }
-    );
-    let res = match res {
-        Ok(res) => res,
-        Err(err) => {
-            info!("user_info ERR {:?}", err);
-            return ResponseUserInfo {
-                success: false,
-                message: format!("{}", err),
-                username: String::new(),
-                email: String::new(),
-                info: String::new(),
-            };
-        }
-    };
-    info!("user_info OUT {:?}", req);
-    ResponseUserInfo {
+pub fn user_return(db: &mut Connection, req: RequestBookReturn) -> ApiResult<ResponseBookReturn> {
+    info!("user_return IN {:?}", req);
+    let borrower = if req.uid == 0 { None } else { Some(req.uid) };
+    let (fine, held_for) = return_instance(db, req.iid, borrower)?;
+    info!("user_return OUT {:?} {} {}", req, fine, held_for);
+    Ok(ResponseBookReturn {
         success: true,
         message: "success".to_string(),
-        username: res.0,
-        email: res.1,
-        info: res.2,
-    }
+        fine,
+        held_for,
+    })
 }
 
-#[inline]
-pub fn admin_add(req: RequestBookAdd) -> ResponseBookAdd {
-    info!("admin_add IN {:?}", req);
-    let db = database();
-    let res = db.execute(
-        "INSERT INTO lms_book (title, author, info) VALUES (?1, ?2, ?3)",
-        [&req.title, &req.author, &req.info],
-    );
-    match res {
-        Ok(_) => {
-            let bid = db.last_insert_rowid() as u64;
-            info!("admin_add OUT {:?}", req);
-            ResponseBookAdd {
-                success: true,
-                bid,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_add ERR {:?}", err);
-            ResponseBookAdd {
-                success: false,
-                bid: 0,
-                message: format!("{}", err),
-            }
-        }
+fn return_instance(db: &mut Connection, iid: u64, borrower: Option<u64>) -> ApiResult<(u64, u64)> {
+    let tx = db.savepoint()?;
+    instance_book(&tx, iid)?;
+    let (uid, days_late) = tx.query_row(
+        "SELECT uid, CAST(julianday(date('now')) - julianday(due_date) AS INTEGER) \
+        FROM lms_occupation WHERE iid = ?1 AND kind = 0",
+        [iid],
+        |row| Ok((row.get::<_, u64>(0)?, row.get::<_, Option<i64>>(1)?)),
+    ).optional()?.ok_or_else(|| ApiError::invariant(ErrorCode::InstanceNotBorrowed, "instance is not borrowed"))?;
+    if borrower.is_some_and(|borrower| borrower != uid) {
+        return Err(ApiError::invariant(ErrorCode::NotBorrower, "instance is borrowed by another user"));
     }
+    tx.execute("DELETE FROM lms_occupation WHERE iid = ?1", [iid])?;
+    let days_late = days_late.unwrap_or(0).max(0) as u64;
+    let fine = days_late * Policy::for_instance(&tx, uid, iid)?.fine_daily_rate;
+    if fine > 0 {
+        tx.execute(
+            "INSERT INTO lms_fine (uid, iid, kind, amount, date, note) \
+            VALUES (?1, ?2, 0, ?3, date('now'), ?4)",
+            rusqlite::params![uid, iid, fine, format!("{} days late", days_late)],
+        )?;
+    }
+    let held_for = assign_hold(&tx, iid)?;
+    tx.commit()?;
+    Ok((fine, held_for.unwrap_or(0)))
 }
 
 #[inline]
-pub fn admin_remove(req: RequestBookRemove) -> ResponseBookRemove {
-    info!("admin_remove IN {:?}", req);
-    let res = database().execute(
-        "DELETE FROM lms_book WHERE bid = ?1",
-        [&req.bid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("admin_remove OUT {:?}", req);
-            ResponseBookRemove {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_remove ERR {:?}", err);
-            ResponseBookRemove {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
-    }
+pub fn user_reserve(db: &mut Connection, req: RequestBookReserve) -> ApiResult<ResponseBookReserve> {
+    info!("user_reserve IN {:?}", req);
+    let pickup = if req.pickup == 0 { None } else { Some(req.pickup) };
+    let (hid, position) = place_hold(db, req.uid, req.bid, pickup)?;
+    info!("user_reserve OUT {:?} {} {}", req, hid, position);
+    Ok(ResponseBookReserve {
+        success: true,
+        message: "success".to_string(),
+        hid,
+        position,
+    })
 }
 
-#[inline]
-pub fn admin_alter(req: RequestBookAlter) -> ResponseBookAlter {
-    info!("admin_alter IN {:?}", req);
-    let res = database().execute(
-        "UPDATE lms_book SET title = ?1, author = ?2, info = ?3 WHERE bid = ?5",
-        [&req.title, &req.author, &req.info, &req.bid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("admin_alter OUT {:?}", req);
-            ResponseBookAlter {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_alter ERR {:?}", err);
-            ResponseBookAlter {
-                success: false,
-                message: format!("{}", err),
+fn place_hold(db: &mut Connection, uid: u64, bid: u64, pickup: Option<u64>) -> ApiResult<(u64, u64)> {
+    let tx = db.savepoint()?;
+    check_user_active(&tx, uid)?;
+    if let Some(pickup) = pickup {
+        if location_kind(&tx, pickup)? != 0 {
+            return Err(ApiError::validation("pickup location is not a branch"));
+        }
+    }
+    let book = tx.query_row("SELECT 1 FROM lms_book WHERE bid = ?1", [bid], |_| Ok(())).optional()?;
+    if book.is_none() {
+        return Err(ApiError::invariant(ErrorCode::BookNotFound, "book does not exist"));
+    }
+    let existing = tx.query_row(
+        "SELECT COUNT(*) FROM lms_hold WHERE uid = ?1 AND bid = ?2 AND status IN (0, 1)",
+        [uid, bid],
+        |row| row.get::<_, u64>(0),
+    )?;
+    if existing > 0 {
+        return Err(ApiError::invariant(ErrorCode::AlreadyHeld, "user already holds this title"));
+    }
+    let policy = Policy::for_book(&tx, uid, bid)?;
+    if let Some(violation) = policy.check_hold(&tx, uid, bid)? {
+        return Err(ApiError::Policy(violation));
+    }
+    tx.execute(
+        "INSERT INTO lms_hold (uid, bid, date, pickup) VALUES (?1, ?2, datetime('now'), ?3)",
+        rusqlite::params![uid, bid, pickup],
+    )?;
+    let hid = tx.last_insert_rowid() as u64;
+    // A copy that is already on the shelf goes straight to the new hold,
+    // preferably one that needs no transfer to the pickup branch.
+    let free = tx.prepare(
+        "SELECT i.iid, i.lid FROM lms_instance i \
+        LEFT JOIN lms_occupation o ON o.iid = i.iid \
+        WHERE i.bid = ?1 AND o.iid IS NULL AND i.withdrawn_date IS NULL \
+        ORDER BY i.iid",
+    )?.query_map([bid], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut chosen = free.first().map(|(iid, _)| *iid);
+    if pickup.is_some() {
+        for (iid, lid) in &free {
+            if branch_of(&tx, *lid)? == pickup {
+                chosen = Some(*iid);
+                break;
             }
         }
     }
+    if let Some(iid) = chosen {
+        assign_hold(&tx, iid)?;
+    }
+    let position = hold_position(&tx, hid)?;
+    tx.commit()?;
+    Ok((hid, position))
 }
 
 #[inline]
-pub fn admin_add_instance(req: RequestBookAddInstance) -> ResponseBookAddInstance {
-    info!("admin_add_instance IN {:?}", req);
-    let db = database();
-    let res = db.execute(
-        "INSERT INTO lms_instance (bid, status, lid) VALUES (?1, ?2, ?3)",
-        [&req.bid.to_string(), &req.status.to_string(), &req.lid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            let iid = db.last_insert_rowid() as u64;
-            info!("admin_add_instance OUT {iid}");
-            ResponseBookAddInstance {
-                success: true,
-                message: "success".to_string(),
-                iid,
-            }
-        },
-        Err(err) => {
-            info!("admin_add_instance ERR {:?}", err);
-            ResponseBookAddInstance {
-                success: false,
-                message: format!("{}", err),
-                iid: 0,
+pub fn user_cancel_hold(db: &mut Connection, req: RequestHoldCancel) -> ApiResult<ResponseHoldCancel> {
+    info!("user_cancel_hold IN {:?}", req);
+    cancel_hold(db, req.hid)?;
+    info!("user_cancel_hold OUT {:?}", req);
+    Ok(ResponseHoldCancel {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+fn cancel_hold(db: &mut Connection, hid: u64) -> ApiResult<()> {
+    let tx = db.savepoint()?;
+    let (iid, status) = tx.query_row(
+        "SELECT iid, status FROM lms_hold WHERE hid = ?1 AND status IN (0, 1)",
+        [hid],
+        |row| Ok((row.get::<_, Option<u64>>(0)?, row.get::<_, u64>(1)?)),
+    ).optional()?.ok_or_else(|| ApiError::not_found("hold does not exist or is no longer active"))?;
+    tx.execute("UPDATE lms_hold SET status = 3 WHERE hid = ?1", [hid])?;
+    match (iid, status) {
+        (Some(iid), 1) => {
+            tx.execute("DELETE FROM lms_occupation WHERE iid = ?1 AND kind = 1", [iid])?;
+            assign_hold(&tx, iid)?;
+        }
+        // A copy bound for the pickup branch is passed on unless it already left;
+        // one on the road goes to the next hold when it is received.
+        (Some(iid), _) => {
+            let stopped = tx.execute("UPDATE lms_transfer SET status = 3 WHERE hid = ?1 AND status = 0", [hid])?;
+            if stopped > 0 {
+                tx.execute("DELETE FROM lms_occupation WHERE iid = ?1 AND kind = 4", [iid])?;
+                assign_hold(&tx, iid)?;
             }
         }
+        (None, _) => {}
     }
+    tx.commit()?;
+    Ok(())
 }
 
-#[inline]
-pub fn admin_remove_instance(req: RequestBookRemoveInstance) -> ResponseBookRemoveInstance {
-    info!("admin_remove_instance IN {:?}", req);
-    let res = database().execute(
-        "DELETE FROM lms_instance WHERE iid = ?1",
-        [&req.iid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("admin_remove_instance OUT {:?}", req);
-            ResponseBookRemoveInstance {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_remove_instance ERR {:?}", err);
-            ResponseBookRemoveInstance {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
+/// Fails unless `uid` names an active user.
+fn check_user_active(db: &Connection, uid: u64) -> ApiResult<()> {
+    let active = db.query_row(
+        "SELECT active FROM lms_user WHERE uid = ?1",
+        [uid],
+        |row| row.get::<_, bool>(0),
+    ).optional()?.ok_or_else(|| ApiError::invariant(ErrorCode::UserNotFound, "user does not exist"))?;
+    if !active {
+        return Err(ApiError::invariant(ErrorCode::UserInactive, "user is inactive"));
     }
+    Ok(())
 }
 
-#[inline]
-pub fn admin_occupy_instance(req: RequestInstanceOccupy) -> ResponseInstanceOccupy {
-    info!("admin_occupy_instance IN {:?}", req);
-    if req.status != 2 || req.status != 3 {
-        info!("admin_occupy_instance ERR status must be 2(maintenance) or 3(lost)");
-        return ResponseInstanceOccupy {
-            success: false,
-            message: "status must be 2(maintenance) or 3(lost)".to_string(),
-        };
+/// The title of instance `iid`, failing when the instance does not exist or was withdrawn.
+fn instance_book(db: &Connection, iid: u64) -> ApiResult<u64> {
+    let (bid, withdrawn) = db.query_row(
+        "SELECT bid, withdrawn_date IS NOT NULL FROM lms_instance WHERE iid = ?1",
+        [iid],
+        |row| Ok((row.get(0)?, row.get::<_, bool>(1)?)),
+    ).optional()?.ok_or_else(|| ApiError::invariant(ErrorCode::InstanceNotFound, "instance does not exist"))?;
+    if withdrawn {
+        return Err(ApiError::invariant(ErrorCode::InstanceUnavailable, "instance is withdrawn"));
     }
-    let res = database().execute(
-        "INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (null, ?1, date('now'), 0)",
-        [&req.iid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("admin_occupy_instance OUT {:?}", req);
-            ResponseInstanceOccupy {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_occupy_instance ERR {:?}", err);
-            ResponseInstanceOccupy {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
+    Ok(bid)
+}
+
+fn occupation_of(db: &Connection, iid: u64) -> rusqlite::Result<Option<(Option<u64>, u64)>> {
+    db.query_row(
+        "SELECT uid, kind FROM lms_occupation WHERE iid = ?1",
+        [iid],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()
+}
+
+/// Why an instance occupied as `kind` cannot be taken.
+fn occupied(kind: u64) -> ApiError {
+    match kind {
+        0 => ApiError::invariant(ErrorCode::InstanceOccupied, "instance is borrowed"),
+        1 => ApiError::invariant(ErrorCode::InstanceOnHold, "instance is on the hold shelf"),
+        2 => ApiError::invariant(ErrorCode::InstanceUnavailable, "instance is under maintenance"),
+        3 => ApiError::invariant(ErrorCode::InstanceUnavailable, "instance is lost"),
+        _ => ApiError::invariant(ErrorCode::InstanceInTransit, "instance is in transit"),
     }
 }
 
-#[inline]
-pub fn admin_release_instance(req: RequestInstanceRelease) -> ResponseInstanceRelease {
-    info!("admin_release_instance IN {:?}", req);
-    let res = database().execute(
-        "DELETE FROM lms_occupation WHERE iid = ?1",
-        [&req.iid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("admin_release_instance OUT {:?}", req);
-            ResponseInstanceRelease {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_release_instance ERR {:?}", err);
-            ResponseInstanceRelease {
-                success: false,
-                message: format!("{}", err),
-            }
+/// Gives a free instance to the first patron waiting on its title: onto the
+/// hold shelf, or into a transfer when the hold is picked up at another branch.
+fn assign_hold(db: &Connection, iid: u64) -> rusqlite::Result<Option<u64>> {
+    let hold = db.query_row(
+        "SELECT h.hid, h.uid, h.pickup, i.lid FROM lms_hold h \
+        JOIN lms_instance i ON i.bid = h.bid \
+        WHERE i.iid = ?1 AND h.status = 0 AND h.iid IS NULL \
+        ORDER BY h.hid LIMIT 1",
+        [iid],
+        |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?, row.get::<_, Option<u64>>(2)?, row.get::<_, u64>(3)?)),
+    ).optional()?;
+    let (hid, uid, pickup, lid) = match hold {
+        Some(hold) => hold,
+        None => return Ok(None),
+    };
+    match pickup {
+        Some(pickup) if branch_of(db, lid)? != Some(pickup) => {
+            // The hold keeps waiting, now with its copy on the way.
+            start_transfer(db, iid, pickup, Some(hid), "")?;
+            db.execute("UPDATE lms_hold SET iid = ?2 WHERE hid = ?1", [hid, iid])?;
         }
+        _ => shelve_hold(db, hid, uid, iid)?,
     }
+    Ok(Some(uid))
+}
+
+fn shelve_hold(db: &Connection, hid: u64, uid: u64, iid: u64) -> rusqlite::Result<()> {
+    let shelf_days = policy_u64(db, "hold_shelf_days", DEFAULT_HOLD_SHELF_DAYS);
+    let expiry = format!("+{shelf_days} days");
+    db.execute(
+        "INSERT INTO lms_occupation (uid, iid, date, due_date, kind) \
+        VALUES (?1, ?2, date('now'), date('now', ?3), 1)",
+        [&uid.to_string(), &iid.to_string(), &expiry],
+    )?;
+    db.execute(
+        "UPDATE lms_hold SET status = 1, iid = ?2, expiry_date = date('now', ?3) WHERE hid = ?1",
+        [&hid.to_string(), &iid.to_string(), &expiry],
+    )?;
+    Ok(())
 }
 
+/// Shelf holds whose pickup window has passed, for `expire_hold` to end.
+pub fn expired_holds(db: &Connection) -> rusqlite::Result<Vec<u64>> {
+    db.prepare("SELECT hid FROM lms_hold WHERE status = 1 AND expiry_date < date('now') ORDER BY hid")?
+        .query_map([], |row| row.get(0))?
+        .collect()
+}
+
+/// Ends a shelf hold that was not picked up in time and passes its copy on.
 #[inline]
-pub fn admin_add_location(req: RequestLocationAdd) -> ResponseLocationAdd {
-    info!("admin_add_location IN {:?}", req);
-    let res = database().execute(
-        "INSERT INTO lms_location (name, info) VALUES (?1, ?2)",
-        [&req.name, &req.info],
-    );
-    match res {
-        Ok(_) => {
-            let lid = database().last_insert_rowid() as u64;
-            info!("admin_add_location OUT {:?}", req);
-            ResponseLocationAdd {
-                success: true,
-                message: "success".to_string(),
-                lid,
-            }
-        },
-        Err(err) => {
-            info!("admin_add_location ERR {:?}", err);
-            ResponseLocationAdd {
-                success: false,
-                message: format!("{}", err),
-                lid: 0,
-            }
-        }
-    }
+pub fn expire_hold(db: &mut Connection, req: RequestHoldCancel) -> ApiResult<ResponseHoldCancel> {
+    info!("expire_hold IN {:?}", req);
+    let tx = db.savepoint()?;
+    let iid = tx.query_row(
+        "SELECT iid FROM lms_hold WHERE hid = ?1 AND status = 1 AND expiry_date < date('now')",
+        [req.hid],
+        |row| row.get::<_, u64>(0),
+    ).optional()?.ok_or_else(|| ApiError::not_found("hold is not waiting past its expiry date"))?;
+    tx.execute("UPDATE lms_hold SET status = 4 WHERE hid = ?1", [req.hid])?;
+    tx.execute("DELETE FROM lms_occupation WHERE iid = ?1 AND kind = 1", [iid])?;
+    assign_hold(&tx, iid)?;
+    tx.commit()?;
+    info!("expire_hold OUT {:?}", req);
+    Ok(ResponseHoldCancel {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+fn hold_position(db: &Connection, hid: u64) -> rusqlite::Result<u64> {
+    db.query_row(
+        "SELECT CASE h.status WHEN 0 THEN \
+        (SELECT COUNT(*) FROM lms_hold w WHERE w.bid = h.bid AND w.status = 0 AND w.hid <= h.hid) \
+        ELSE 0 END FROM lms_hold h WHERE h.hid = ?1",
+        [hid],
+        |row| row.get(0),
+    )
+}
+
+fn holds_of(db: &Connection, filter: &str, id: u64) -> rusqlite::Result<Vec<Hold>> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT h.hid, h.uid, h.bid, h.iid, h.status, \
+        CASE h.status WHEN 0 THEN \
+        (SELECT COUNT(*) FROM lms_hold w WHERE w.bid = h.bid AND w.status = 0 AND w.hid <= h.hid) \
+        ELSE 0 END, h.date, h.expiry_date, h.pickup \
+        FROM lms_hold h WHERE {filter} AND h.status IN (0, 1) ORDER BY h.hid",
+    ))?;
+    let holds = stmt.query_map([id], |row| {
+        Ok(Hold {
+            hid: row.get(0)?,
+            uid: row.get(1)?,
+            bid: row.get(2)?,
+            iid: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+            status: row.get(4)?,
+            position: row.get(5)?,
+            date: row.get(6)?,
+            expiry_date: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+            pickup: row.get::<_, Option<u64>>(8)?.unwrap_or(0),
+        })
+    })?;
+    holds.collect()
 }
 
 #[inline]
-pub fn admin_remove_location(req: RequestLocationRemove) -> ResponseLocationRemove {
-    info!("admin_remove_location IN {:?}", req);
-    let res = database().execute(
-        "DELETE FROM lms_location WHERE lid = ?1",
-        [&req.lid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("admin_remove_location OUT {:?}", req);
-            ResponseLocationRemove {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_remove_location ERR {:?}", err);
-            ResponseLocationRemove {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
-    }
+pub fn user_overdue(db: &mut Connection, req: RequestUserOverdue) -> ApiResult<ResponseUserOverdue> {
+    info!("user_overdue IN {:?}", req);
+    let overdue = overdue_loans(db, Some(req.uid))?;
+    info!("user_overdue OUT {:?}", overdue);
+    Ok(ResponseUserOverdue {
+        success: true,
+        message: "success".to_string(),
+        overdue,
+    })
 }
 
 #[inline]
-pub fn admin_alter_location(req: RequestLocationAlter) -> ResponseLocationAlter {
-    info!("admin_alters_location IN {:?}", req);
-    let res = database().execute(
-        "UPDATE lms_location SET name = ?1, info = ?2 WHERE lid = ?3",
-        [&req.name, &req.info, &req.lid.to_string()],
-    );
-    match res {
-        Ok(_) => {
-            info!("admin_alters_location OUT {:?}", req);
-            ResponseLocationAlter {
-                success: true,
-                message: "success".to_string(),
-            }
-        },
-        Err(err) => {
-            info!("admin_alters_location ERR {:?}", err);
-            ResponseLocationAlter {
-                success: false,
-                message: format!("{}", err),
-            }
-        }
-    }
+pub fn user_fines(db: &mut Connection, req: RequestUserFines) -> ApiResult<ResponseUserFines> {
+    info!("user_fines IN {:?}", req);
+    let balance = fine_balance(db, req.uid)?;
+    let mut stmt = db.prepare(
+        "SELECT fid, uid, iid, kind, amount, date, status, note \
+        FROM lms_fine WHERE uid = ?1 ORDER BY fid",
+    )?;
+    let fines = stmt.query_map([req.uid], |row| {
+        Ok(Fine {
+            fid: row.get(0)?,
+            uid: row.get(1)?,
+            iid: row.get::<_, Option<u64>>(2)?.unwrap_or(0),
+            kind: row.get(3)?,
+            amount: row.get(4)?,
+            date: row.get(5)?,
+            status: row.get(6)?,
+            note: row.get(7)?,
+        })
+    })?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let credit = db.query_row(
+        "SELECT ifnull(sum(amount), 0) FROM lms_credit WHERE uid = ?1 AND status = 0",
+        [req.uid],
+        |row| row.get(0),
+    )?;
+    info!("user_fines OUT {} {} {:?}", balance, credit, fines);
+    Ok(ResponseUserFines {
+        success: true,
+        message: "success".to_string(),
+        balance,
+        credit,
+        fines,
+    })
+}
+
+fn fine_balance(db: &Connection, uid: u64) -> rusqlite::Result<u64> {
+    db.query_row(
+        "SELECT ifnull(sum(amount), 0) FROM lms_fine WHERE uid = ?1 AND status = 0",
+        [uid],
+        |row| row.get(0),
+    )
 }
 
 #[inline]
-pub fn book_search(req: RequestBookSearch) -> ResponseBookSearch {
-    info!("book_search IN {:?}", req);
-    let db = database();
-    let phrase = req.phrase;
-    let mut stmt = db.prepare(&format!(
-        "SELECT bid FROM lms_book WHERE \
-        title LIKE '%{phrase}%' OR \
-        author LIKE '%{phrase}%' OR \
-        info LIKE '%{phrase}%'",
-    )).unwrap();
-    let bids = stmt.query_map(
-        [],
-        |row| {
-            Ok(row.get(0).unwrap())
-        }
-    );
-    let bids = match bids {
-        Ok(bids) => bids,
-        Err(err) => {
-            info!("book_search ERR {:?}", err);
-            return ResponseBookSearch {
-                success: false,
-                message: format!("{}", err),
-                bid_list: String::new(),
-            };
-        }
-    };
-    let bids = bids
-        .map(|bid| bid.unwrap())
-        .collect::<Vec<String>>();
-    let response = ResponseBookSearch {
+pub fn user_policy(db: &mut Connection, req: RequestUserPolicy) -> ApiResult<ResponseUserPolicy> {
+    info!("user_policy IN {:?}", req);
+    let policy = Policy::for_book(db, req.uid, req.bid).map_err(|err| match err {
+        rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("user or book does not exist"),
+        err => err.into(),
+    })?;
+    info!("user_policy OUT {:?}", policy);
+    Ok(ResponseUserPolicy {
         success: true,
         message: "success".to_string(),
-        bid_list: bids.join(","),
-    };
-    info!("book_search OUT {response:?}");
-    response
+        max_loans: policy.max_loans,
+        loan_period: policy.loan_period,
+        renewal_limit: policy.renewal_limit,
+        max_holds: policy.max_holds,
+        fine_daily_rate: policy.fine_daily_rate,
+    })
 }
 
 #[inline]
-pub fn book_info(req: RequestBookInfo) -> ResponseBookInfo {
-    info!("book_info IN {:?}", req);
-    let res = database().query_row(
-        "SELECT title, author, info FROM lms_book WHERE bid = ?1",
-        [&req.bid.to_string()],
+pub fn user_info(db: &mut Connection, req: RequestUserInfo) -> ApiResult<ResponseUserInfo> {
+    info!("user_info IN {:?}", req);
+    let res = db.query_row(
+        "SELECT username, email, info, cid, role, active FROM lms_user WHERE uid = ?1",
+        [&req.uid.to_string()],
         |row| {
             Ok((
-                row.get(0).unwrap(),
-                row.get(1).unwrap(),
-                row.get(2).unwrap(),
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
             ))
         }
-    );
-    let res = match res {
-        Ok(res) => res,
-        Err(err) => {
-            info!("book_info ERR {:?}", err);
-            return ResponseBookInfo {
-                success: false,
-                message: format!("{}", err),
-                title: String::new(),
-                author: String::new(),
-                info: String::new(),
-            };
-        }
-    };
-    let response = ResponseBookInfo {
+    ).optional()?.ok_or_else(|| ApiError::not_found("user does not exist"))?;
+    info!("user_info OUT {:?}", req);
+    Ok(ResponseUserInfo {
         success: true,
         message: "success".to_string(),
-        title: res.0,
-        author: res.1,
+        username: res.0,
+        email: res.1,
         info: res.2,
+        cid: res.3,
+        role: res.4,
+        active: res.5,
+    })
+}
+
+/// Validates `details`, normalizing the ISBN and tidying the subject and tag lists.
+fn clean_details(details: BookDetails) -> ApiResult<BookDetails> {
+    let isbn = match details.isbn.trim() {
+        "" => String::new(),
+        isbn => normalize_isbn(isbn).ok_or_else(|| ApiError::validation("isbn is not valid"))?,
     };
-    info!("book_info OUT {response:?}");
-    response
+    let mut subjects = details.subjects.iter()
+        .map(|subject| subject.trim().to_string())
+        .filter(|subject| !subject.is_empty())
+        .collect::<Vec<_>>();
+    subjects.sort();
+    subjects.dedup();
+    let mut tags = details.tags.iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect::<Vec<_>>();
+    tags.sort();
+    tags.dedup();
+    Ok(BookDetails {
+        isbn,
+        publisher: details.publisher.trim().to_string(),
+        edition: details.edition.trim().to_string(),
+        language: details.language.trim().to_lowercase(),
+        subjects,
+        tags,
+        call_number: details.call_number.trim().to_string(),
+        ..details
+    })
+}
+
+fn write_details(db: &Connection, bid: u64, details: &BookDetails) -> rusqlite::Result<()> {
+    let isbn = Some(&details.isbn).filter(|isbn| !isbn.is_empty());
+    db.execute(
+        "UPDATE lms_book SET isbn = ?1, publisher = ?2, year = ?3, edition = ?4, language = ?5, pages = ?6, \
+        call_number = ?7 WHERE bid = ?8",
+        rusqlite::params![isbn, details.publisher, details.year, details.edition, details.language,
+            details.pages, details.call_number, bid],
+    )?;
+    db.execute("DELETE FROM lms_book_subject WHERE bid = ?1", [bid])?;
+    for subject in &details.subjects {
+        db.execute(
+            "INSERT INTO lms_book_subject (bid, subject) VALUES (?1, ?2)",
+            rusqlite::params![bid, subject],
+        )?;
+    }
+    db.execute("DELETE FROM lms_book_tag WHERE bid = ?1", [bid])?;
+    for tag in &details.tags {
+        db.execute(
+            "INSERT INTO lms_book_tag (bid, tag) VALUES (?1, ?2)",
+            rusqlite::params![bid, tag],
+        )?;
+    }
+    Ok(())
+}
+
+fn read_details(db: &Connection, bid: u64) -> rusqlite::Result<BookDetails> {
+    let subjects = db.prepare("SELECT subject FROM lms_book_subject WHERE bid = ?1 ORDER BY subject")?
+        .query_map([bid], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    let tags = db.prepare("SELECT tag FROM lms_book_tag WHERE bid = ?1 ORDER BY tag")?
+        .query_map([bid], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    db.query_row(
+        "SELECT isbn, publisher, year, edition, language, pages, call_number FROM lms_book WHERE bid = ?1",
+        [bid],
+        |row| Ok(BookDetails {
+            isbn: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+            publisher: row.get(1)?,
+            year: row.get(2)?,
+            edition: row.get(3)?,
+            language: row.get(4)?,
+            pages: row.get(5)?,
+            subjects,
+            tags,
+            call_number: row.get(6)?,
+        }),
+    )
+}
+
+/// Existing books the new one may duplicate, by ISBN or by title, author and edition.
+fn duplicate_warnings(db: &Connection, bid: u64, req: &RequestBookAdd) -> rusqlite::Result<Vec<String>> {
+    let mut warnings = db.prepare("SELECT bid FROM lms_book WHERE isbn = ?1 AND bid != ?2 ORDER BY bid")?
+        .query_map(rusqlite::params![req.details.isbn, bid], |row| row.get::<_, u64>(0))?
+        .map(|other| other.map(|other| format!("isbn is already used by book {other}")))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let same = db.prepare(
+        "SELECT bid FROM lms_book WHERE title = ?1 COLLATE NOCASE AND author = ?2 COLLATE NOCASE \
+        AND edition = ?3 AND bid != ?4 ORDER BY bid",
+    )?
+        .query_map(rusqlite::params![req.title, req.author, req.details.edition, bid], |row| row.get::<_, u64>(0))?
+        .map(|other| other.map(|other| format!("book {other} has the same title, author and edition")))
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    warnings.extend(same);
+    Ok(warnings)
 }
 
 #[inline]
-pub fn book_instance(req: RequestBookInstance) -> ResponseBookInstance {
-    info!("book_instance IN {:?}", req);
-    let db = database();
-    let mut stmt = db
-        .prepare("SELECT iid FROM lms_instance WHERE bid = ?1")
-        .unwrap();
-    let iid_list = stmt.query_map(
+pub fn admin_add(db: &mut Connection, req: RequestBookAdd) -> ApiResult<ResponseBookAdd> {
+    info!("admin_add IN {:?}", req);
+    let req = RequestBookAdd { details: clean_details(req.details)?, ..req };
+    let tx = db.savepoint()?;
+    tx.execute(
+        "INSERT INTO lms_book (title, author, info, tid) VALUES (?1, ?2, ?3, ?4)",
+        [&req.title, &req.author, &req.info, &req.tid.to_string()],
+    )?;
+    let bid = tx.last_insert_rowid() as u64;
+    write_details(&tx, bid, &req.details)?;
+    let author = req.author.trim();
+    if !author.is_empty() {
+        let aid = author_named(&tx, author)?;
+        tx.execute("INSERT INTO lms_book_author (bid, aid) VALUES (?1, ?2)", [bid, aid])?;
+    }
+    let warnings = duplicate_warnings(&tx, bid, &req)?;
+    tx.commit()?;
+    info!("admin_add OUT {} {:?}", bid, warnings);
+    Ok(ResponseBookAdd {
+        success: true,
+        bid,
+        message: "success".to_string(),
+        warnings,
+    })
+}
+
+#[inline]
+pub fn admin_remove(db: &mut Connection, req: RequestBookRemove) -> ApiResult<ResponseBookRemove> {
+    info!("admin_remove IN {:?}", req);
+    let tx = db.savepoint()?;
+    refuse_referenced(&tx, "lms_book", "bid", req.bid, &BOOK_REFERENCES, "book does not exist")?;
+    let rows = tx.execute(
+        "DELETE FROM lms_book WHERE bid = ?1",
         [&req.bid.to_string()],
-        |row| {
-            Ok(row.get(0).unwrap())
-        });
-    let iid_list = match iid_list {
-        Ok(iid_list) => iid_list,
-        Err(err) => {
-            info!("book_instance ERR {:?}", err);
-            return ResponseBookInstance {
-                success: false,
-                message: format!("{}", err),
-                iid_list: String::new(),
-            };
-        }
-    };
-    let iid_list = iid_list
-        .map(|iid| iid.unwrap())
-        .collect::<Vec<String>>();
-    let response = ResponseBookInstance {
+    )?;
+    affected(rows, "book does not exist")?;
+    tx.commit()?;
+    info!("admin_remove OUT {:?}", req);
+    Ok(ResponseBookRemove {
         success: true,
         message: "success".to_string(),
-        iid_list: iid_list.join(","),
-    };
-    info!("book_instance OUT {response:?}");
-    response
+    })
 }
 
 #[inline]
-pub fn book_instance_info(req: RequestBookInstanceInfo) -> ResponseBookInstanceInfo {
-    info!("book_instance_info IN {:?}", req);
-    let res = database().query_row(
-        "SELECT bid, status, lid FROM lms_instance WHERE iid = ?1",
-        [&req.iid.to_string()],
-        |row| {
-            Ok((
-                row.get(0).unwrap(),
-                row.get(1).unwrap(),
-                row.get(2).unwrap(),
-            ))
-        }
-    );
-    let res = match res {
-        Ok(res) => res,
-        Err(err) => {
-            info!("book_instance_info ERR {:?}", err);
-            return ResponseBookInstanceInfo {
-                success: false,
-                message: format!("{}", err),
-                bid: 0,
-                lid: 0,
-                status: 0,
-            };
+pub fn admin_alter(db: &mut Connection, req: RequestBookAlter) -> ApiResult<ResponseBookAlter> {
+    info!("admin_alter IN {:?}", req);
+    let details = clean_details(req.details.clone())?;
+    let tx = db.savepoint()?;
+    let previous = tx.query_row("SELECT author FROM lms_book WHERE bid = ?1", [req.bid], |row| row.get::<_, String>(0))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("book does not exist"))?;
+    tx.execute(
+        "UPDATE lms_book SET title = ?1, author = ?2, info = ?3, tid = ?4 WHERE bid = ?5",
+        [&req.title, &req.author, &req.info, &req.tid.to_string(), &req.bid.to_string()],
+    )?;
+    write_details(&tx, req.bid, &details)?;
+    // The author field names the primary author, so its link follows the edit.
+    let author = req.author.trim();
+    if author != previous.trim() {
+        tx.execute(
+            "DELETE FROM lms_book_author WHERE bid = ?1 AND role = 0 \
+            AND aid IN (SELECT aid FROM lms_author WHERE name = ?2)",
+            rusqlite::params![req.bid, previous.trim()],
+        )?;
+        if !author.is_empty() {
+            let aid = author_named(&tx, author)?;
+            tx.execute("INSERT OR IGNORE INTO lms_book_author (bid, aid) VALUES (?1, ?2)", [req.bid, aid])?;
         }
-    };
-    let response = ResponseBookInstanceInfo {
+    }
+    tx.commit()?;
+    info!("admin_alter OUT {:?}", req);
+    Ok(ResponseBookAlter {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+/// The author called `name`, created if there is none yet.
+fn author_named(db: &Connection, name: &str) -> rusqlite::Result<u64> {
+    db.execute("INSERT OR IGNORE INTO lms_author (name) VALUES (?1)", [name])?;
+    db.query_row("SELECT aid FROM lms_author WHERE name = ?1", [name], |row| row.get(0))
+}
+
+fn check_author(db: &Connection, aid: u64) -> ApiResult<String> {
+    db.query_row("SELECT name FROM lms_author WHERE aid = ?1", [aid], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("author does not exist"))
+}
+
+fn author_name(name: &str) -> ApiResult<&str> {
+    match name.trim() {
+        "" => Err(ApiError::validation("author name is empty")),
+        name => Ok(name),
+    }
+}
+
+fn read_contributors(db: &Connection, bid: u64) -> rusqlite::Result<Vec<Contributor>> {
+    db.prepare(
+        "SELECT a.aid, a.name, ba.role FROM lms_book_author ba JOIN lms_author a ON a.aid = ba.aid \
+        WHERE ba.bid = ?1 ORDER BY ba.position, ba.role",
+    )?
+        .query_map([bid], |row| Ok(Contributor {
+            aid: row.get(0)?,
+            name: row.get(1)?,
+            role: row.get(2)?,
+        }))?
+        .collect()
+}
+
+#[inline]
+pub fn admin_add_author(db: &mut Connection, req: RequestAuthorAdd) -> ApiResult<ResponseAuthorAdd> {
+    info!("admin_add_author IN {:?}", req);
+    db.execute("INSERT INTO lms_author (name) VALUES (?1)", [author_name(&req.name)?])?;
+    let aid = db.last_insert_rowid() as u64;
+    info!("admin_add_author OUT {aid}");
+    Ok(ResponseAuthorAdd {
+        success: true,
+        message: "success".to_string(),
+        aid,
+    })
+}
+
+#[inline]
+pub fn admin_rename_author(db: &mut Connection, req: RequestAuthorRename) -> ApiResult<ResponseAuthorRename> {
+    info!("admin_rename_author IN {:?}", req);
+    let rows = db.execute(
+        "UPDATE lms_author SET name = ?1 WHERE aid = ?2",
+        rusqlite::params![author_name(&req.name)?, req.aid],
+    )?;
+    affected(rows, "author does not exist")?;
+    info!("admin_rename_author OUT {:?}", req);
+    Ok(ResponseAuthorRename {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_merge_authors(db: &mut Connection, req: RequestAuthorMerge) -> ApiResult<ResponseAuthorMerge> {
+    info!("admin_merge_authors IN {:?}", req);
+    if req.aid == req.from {
+        return Err(ApiError::validation("cannot merge an author into itself"));
+    }
+    let tx = db.savepoint()?;
+    check_author(&tx, req.aid)?;
+    check_author(&tx, req.from)?;
+    // A book crediting both authors in the same role keeps a single credit.
+    tx.execute("UPDATE OR IGNORE lms_book_author SET aid = ?1 WHERE aid = ?2", [req.aid, req.from])?;
+    tx.execute("DELETE FROM lms_book_author WHERE aid = ?1", [req.from])?;
+    tx.execute("DELETE FROM lms_author WHERE aid = ?1", [req.from])?;
+    tx.commit()?;
+    info!("admin_merge_authors OUT {:?}", req);
+    Ok(ResponseAuthorMerge {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_set_contributors(db: &mut Connection, req: RequestBookContributors) -> ApiResult<ResponseBookContributors> {
+    info!("admin_set_contributors IN {:?}", req);
+    if req.contributors.iter().any(|contributor| contributor.role > 3) {
+        return Err(ApiError::validation("contributor role is not valid"));
+    }
+    let tx = db.savepoint()?;
+    tx.query_row("SELECT 1 FROM lms_book WHERE bid = ?1", [req.bid], |_| Ok(()))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("book does not exist"))?;
+    tx.execute("DELETE FROM lms_book_author WHERE bid = ?1", [req.bid])?;
+    for (position, contributor) in req.contributors.iter().enumerate() {
+        check_author(&tx, contributor.aid)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO lms_book_author (bid, aid, role, position) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![req.bid, contributor.aid, contributor.role, position],
+        )?;
+    }
+    tx.commit()?;
+    info!("admin_set_contributors OUT {:?}", req);
+    Ok(ResponseBookContributors {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_add_series(db: &mut Connection, req: RequestSeriesAdd) -> ApiResult<ResponseSeriesAdd> {
+    info!("admin_add_series IN {:?}", req);
+    let title = match req.title.trim() {
+        "" => return Err(ApiError::validation("series title is empty")),
+        title => title,
+    };
+    db.execute("INSERT INTO lms_series (title, info) VALUES (?1, ?2)", [title, &req.info])?;
+    let sid = db.last_insert_rowid() as u64;
+    info!("admin_add_series OUT {sid}");
+    Ok(ResponseSeriesAdd {
+        success: true,
+        message: "success".to_string(),
+        sid,
+    })
+}
+
+#[inline]
+pub fn admin_set_series(db: &mut Connection, req: RequestBookSeries) -> ApiResult<ResponseBookSeries> {
+    info!("admin_set_series IN {:?}", req);
+    let tx = db.savepoint()?;
+    tx.query_row("SELECT 1 FROM lms_book WHERE bid = ?1", [req.bid], |_| Ok(()))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("book does not exist"))?;
+    if req.sid == 0 {
+        tx.execute("DELETE FROM lms_book_series WHERE bid = ?1", [req.bid])?;
+    } else {
+        if req.volume == 0 {
+            return Err(ApiError::validation("volume numbers start at 1"));
+        }
+        tx.query_row("SELECT 1 FROM lms_series WHERE sid = ?1", [req.sid], |_| Ok(()))
+            .optional()?
+            .ok_or_else(|| ApiError::not_found("series does not exist"))?;
+        let taken = tx.query_row(
+            "SELECT bid FROM lms_book_series WHERE sid = ?1 AND volume = ?2 AND bid != ?3",
+            [req.sid, req.volume, req.bid],
+            |row| row.get::<_, u64>(0),
+        ).optional()?;
+        if let Some(other) = taken {
+            return Err(ApiError::conflict(&format!("volume {} is already book {other}", req.volume)));
+        }
+        tx.execute(
+            "INSERT INTO lms_book_series (bid, sid, volume) VALUES (?1, ?2, ?3) \
+            ON CONFLICT (bid) DO UPDATE SET sid = excluded.sid, volume = excluded.volume",
+            [req.bid, req.sid, req.volume],
+        )?;
+    }
+    tx.commit()?;
+    info!("admin_set_series OUT {:?}", req);
+    Ok(ResponseBookSeries {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_add_instance(db: &mut Connection, req: RequestBookAddInstance) -> ApiResult<ResponseBookAddInstance> {
+    info!("admin_add_instance IN {:?}", req);
+    db.execute(
+        "INSERT INTO lms_instance (bid, status, lid) VALUES (?1, ?2, ?3)",
+        [&req.bid.to_string(), &req.status.to_string(), &req.lid.to_string()],
+    )?;
+    let iid = db.last_insert_rowid() as u64;
+    info!("admin_add_instance OUT {iid}");
+    Ok(ResponseBookAddInstance {
+        success: true,
+        message: "success".to_string(),
+        iid,
+    })
+}
+
+#[inline]
+pub fn admin_remove_instance(db: &mut Connection, req: RequestBookRemoveInstance) -> ApiResult<ResponseBookRemoveInstance> {
+    info!("admin_remove_instance IN {:?}", req);
+    let tx = db.savepoint()?;
+    tx.query_row("SELECT 1 FROM lms_instance WHERE iid = ?1", [req.iid], |_| Ok(()))
+        .optional()?
+        .ok_or_else(|| ApiError::invariant(ErrorCode::InstanceNotFound, "instance does not exist"))?;
+    if let Some((_, kind)) = occupation_of(&tx, req.iid)? {
+        return Err(occupied(kind));
+    }
+    refuse_referenced(&tx, "lms_instance", "iid", req.iid, &INSTANCE_REFERENCES, "instance does not exist")?;
+    tx.execute("DELETE FROM lms_instance WHERE iid = ?1", [req.iid])?;
+    tx.commit()?;
+    info!("admin_remove_instance OUT {:?}", req);
+    Ok(ResponseBookRemoveInstance {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_occupy_instance(db: &mut Connection, actor: u64, req: RequestInstanceOccupy) -> ApiResult<ResponseInstanceOccupy> {
+    info!("admin_occupy_instance IN {:?}", req);
+    let remark = Remark { actor, note: "" };
+    match req.status {
+        2 => send_to_repair(db, req.iid, None, &remark)?,
+        // A borrowed instance reported lost stays charged to its borrower.
+        3 => {
+            let borrowed = matches!(occupation_of(db, req.iid)?, Some((_, 0)));
+            mark_lost(db, req.iid, borrowed, &remark)?;
+        }
+        _ => return Err(ApiError::validation("status must be 2(maintenance) or 3(lost)")),
+    }
+    info!("admin_occupy_instance OUT {:?}", req);
+    Ok(ResponseInstanceOccupy {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_release_instance(db: &mut Connection, actor: u64, req: RequestInstanceRelease) -> ApiResult<ResponseInstanceRelease> {
+    info!("admin_release_instance IN {:?}", req);
+    instance_book(db, req.iid)?;
+    let remark = Remark { actor, note: "" };
+    let held_for = match occupation_of(db, req.iid)? {
+        None => return Err(ApiError::invariant(ErrorCode::InstanceNotOccupied, "instance is not occupied")),
+        // Releasing a loan would skip the return and its fine.
+        Some((_, 0)) => return Err(ApiError::invariant(ErrorCode::InstanceOccupied, "instance is borrowed")),
+        Some((_, 2)) => finish_repair(db, req.iid, true, &remark)?,
+        Some((_, 3)) => mark_found(db, req.iid, &remark)?.held_for,
+        // Transfers end by being received or cancelled.
+        Some((_, 4)) => return Err(occupied(4)),
+        Some(_) => {
+            let tx = db.savepoint()?;
+            tx.execute("DELETE FROM lms_occupation WHERE iid = ?1", [req.iid])?;
+            tx.execute("UPDATE lms_hold SET status = 3 WHERE iid = ?1 AND status = 1", [req.iid])?;
+            let held_for = assign_hold(&tx, req.iid)?.unwrap_or(0);
+            tx.commit()?;
+            held_for
+        }
+    };
+    info!("admin_release_instance OUT {:?} {}", req, held_for);
+    Ok(ResponseInstanceRelease {
+        success: true,
+        message: "success".to_string(),
+        held_for,
+    })
+}
+
+#[inline]
+pub fn admin_send_repair(db: &mut Connection, actor: u64, req: RequestInstanceRepair) -> ApiResult<ResponseInstanceRepair> {
+    info!("admin_send_repair IN {:?}", req);
+    let expected_date = parse_date(db, &req.expected_date, "expected_date")?;
+    send_to_repair(db, req.iid, expected_date.as_deref(), &Remark { actor, note: &req.note })?;
+    info!("admin_send_repair OUT {:?}", req);
+    Ok(ResponseInstanceRepair {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_finish_repair(db: &mut Connection, actor: u64, req: RequestInstanceRepaired) -> ApiResult<ResponseInstanceRepaired> {
+    info!("admin_finish_repair IN {:?}", req);
+    let held_for = finish_repair(db, req.iid, req.repaired, &Remark { actor, note: &req.note })?;
+    info!("admin_finish_repair OUT {:?} {}", req, held_for);
+    Ok(ResponseInstanceRepaired {
+        success: true,
+        message: "success".to_string(),
+        held_for,
+    })
+}
+
+#[inline]
+pub fn admin_mark_lost(db: &mut Connection, actor: u64, req: RequestInstanceLost) -> ApiResult<ResponseInstanceLost> {
+    info!("admin_mark_lost IN {:?}", req);
+    let (billed, fid) = mark_lost(db, req.iid, req.bill, &Remark { actor, note: &req.note })?;
+    info!("admin_mark_lost OUT {:?} {} {}", req, billed, fid);
+    Ok(ResponseInstanceLost {
+        success: true,
+        message: "success".to_string(),
+        billed,
+        fid,
+    })
+}
+
+#[inline]
+pub fn admin_mark_found(db: &mut Connection, actor: u64, req: RequestInstanceFound) -> ApiResult<ResponseInstanceFound> {
+    info!("admin_mark_found IN {:?}", req);
+    let found = mark_found(db, req.iid, &Remark { actor, note: &req.note })?;
+    info!("admin_mark_found OUT {:?} {} {} {}", req, found.reversed, found.refunded, found.held_for);
+    Ok(ResponseInstanceFound {
+        success: true,
+        message: "success".to_string(),
+        reversed: found.reversed,
+        refunded: found.refunded,
+        held_for: found.held_for,
+    })
+}
+
+#[inline]
+pub fn admin_withdraw_instance(db: &mut Connection, actor: u64, req: RequestInstanceWithdraw) -> ApiResult<ResponseInstanceWithdraw> {
+    info!("admin_withdraw_instance IN {:?}", req);
+    let tx = db.savepoint()?;
+    instance_book(&tx, req.iid)?;
+    match occupation_of(&tx, req.iid)? {
+        None | Some((_, 2)) | Some((_, 3)) => {}
+        Some((_, kind)) => return Err(occupied(kind)),
+    }
+    tx.execute("DELETE FROM lms_occupation WHERE iid = ?1", [req.iid])?;
+    withdraw(&tx, req.iid)?;
+    instance_event(&tx, req.iid, 5, None, None, None, &Remark { actor, note: &req.note })?;
+    tx.commit()?;
+    info!("admin_withdraw_instance OUT {:?}", req);
+    Ok(ResponseInstanceWithdraw {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+fn send_to_repair(db: &mut Connection, iid: u64, expected_date: Option<&str>, remark: &Remark) -> ApiResult<()> {
+    let tx = db.savepoint()?;
+    instance_book(&tx, iid)?;
+    if let Some((_, kind)) = occupation_of(&tx, iid)? {
+        return Err(occupied(kind));
+    }
+    tx.execute(
+        "INSERT INTO lms_occupation (uid, iid, date, due_date, kind) VALUES (null, ?1, date('now'), ?2, 2)",
+        rusqlite::params![iid, expected_date],
+    )?;
+    instance_event(&tx, iid, 0, expected_date, None, None, remark)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Puts a repaired instance back in circulation, or withdraws one beyond repair.
+fn finish_repair(db: &mut Connection, iid: u64, repaired: bool, remark: &Remark) -> ApiResult<u64> {
+    let tx = db.savepoint()?;
+    instance_book(&tx, iid)?;
+    if !matches!(occupation_of(&tx, iid)?, Some((_, 2))) {
+        return Err(ApiError::invariant(ErrorCode::InstanceNotInRepair, "instance is not under maintenance"));
+    }
+    tx.execute("DELETE FROM lms_occupation WHERE iid = ?1", [iid])?;
+    let held_for = if repaired {
+        instance_event(&tx, iid, 1, None, None, None, remark)?;
+        assign_hold(&tx, iid)?.unwrap_or(0)
+    } else {
+        withdraw(&tx, iid)?;
+        instance_event(&tx, iid, 2, None, None, None, remark)?;
+        0
+    };
+    tx.commit()?;
+    Ok(held_for)
+}
+
+fn mark_lost(db: &mut Connection, iid: u64, bill: bool, remark: &Remark) -> ApiResult<(u64, u64)> {
+    let tx = db.savepoint()?;
+    let lost = lose_instance(&tx, iid, bill, remark)?;
+    tx.commit()?;
+    Ok(lost)
+}
+
+/// Marks an instance lost, billing its borrower, or its last one, when `bill` is set.
+fn lose_instance(db: &Connection, iid: u64, bill: bool, remark: &Remark) -> ApiResult<(u64, u64)> {
+    instance_book(db, iid)?;
+    let billed = match occupation_of(db, iid)? {
+        None => {
+            db.execute(
+                "INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (null, ?1, date('now'), 3)",
+                [iid],
+            )?;
+            match bill {
+                true => db.query_row(
+                    "SELECT uid FROM lms_history WHERE iid = ?1 ORDER BY return_date DESC, rowid DESC LIMIT 1",
+                    [iid],
+                    |row| row.get::<_, u64>(0),
+                ).optional()?,
+                false => None,
+            }
+        }
+        // The loan ends here; the borrower stays on the lost record.
+        Some((Some(uid), 0)) => {
+            db.execute(
+                "INSERT INTO lms_history (uid, iid, date, return_date, lost) \
+                SELECT uid, iid, date, date('now'), 1 FROM lms_occupation WHERE iid = ?1",
+                [iid],
+            )?;
+            db.execute("UPDATE lms_occupation SET kind = 3, date = date('now') WHERE iid = ?1", [iid])?;
+            bill.then_some(uid)
+        }
+        Some((_, kind)) => return Err(occupied(kind)),
+    };
+    let fid = match billed {
+        Some(uid) => {
+            let amount = policy_u64(db, "fine_replacement", DEFAULT_FINE_REPLACEMENT);
+            db.execute(
+                "INSERT INTO lms_fine (uid, iid, kind, amount, date, note) \
+                VALUES (?1, ?2, 1, ?3, date('now'), 'replacement charge')",
+                [uid, iid, amount],
+            )?;
+            Some(db.last_insert_rowid() as u64)
+        }
+        None => None,
+    };
+    instance_event(db, iid, 3, None, billed, fid, remark)?;
+    Ok((billed.unwrap_or(0), fid.unwrap_or(0)))
+}
+
+/// Returns a lost instance to circulation and waives its unpaid replacement charges.
+/// What returning a lost instance to circulation settled.
+struct Found {
+    reversed: u64,
+    refunded: u64,
+    held_for: u64,
+}
+
+fn mark_found(db: &mut Connection, iid: u64, remark: &Remark) -> ApiResult<Found> {
+    let tx = db.savepoint()?;
+    instance_book(&tx, iid)?;
+    let uid = match occupation_of(&tx, iid)? {
+        Some((uid, 3)) => uid,
+        _ => return Err(ApiError::invariant(ErrorCode::InstanceNotLost, "instance is not lost")),
+    };
+    tx.execute("DELETE FROM lms_occupation WHERE iid = ?1", [iid])?;
+    let reversed = tx.prepare(
+        "UPDATE lms_fine SET status = 2, settle_date = date('now') \
+        WHERE iid = ?1 AND kind = 1 AND status = 0 RETURNING fid, uid, amount",
+    )?.query_map([iid], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?, row.get::<_, u64>(2)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    // Replacement charges already paid are owed back rather than rewritten.
+    let refunds = tx.prepare(
+        "INSERT INTO lms_credit (uid, fid, amount, date) \
+        SELECT uid, fid, amount, date('now') FROM lms_fine \
+        WHERE iid = ?1 AND kind = 1 AND status = 1 AND fid NOT IN (SELECT fid FROM lms_credit) \
+        RETURNING fid, uid, amount",
+    )?.query_map([iid], |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?, row.get::<_, u64>(2)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (fid, credited) = match reversed.iter().chain(&refunds).max_by_key(|(fid, _, _)| *fid) {
+        Some((fid, credited, _)) => (Some(*fid), Some(*credited)),
+        None => (None, uid),
+    };
+    instance_event(&tx, iid, 4, None, credited, fid, remark)?;
+    let held_for = assign_hold(&tx, iid)?.unwrap_or(0);
+    tx.commit()?;
+    let total = |charges: &[(u64, u64, u64)]| charges.iter().map(|(_, _, amount)| amount).sum();
+    Ok(Found { reversed: total(&reversed), refunded: total(&refunds), held_for })
+}
+
+fn withdraw(db: &Connection, iid: u64) -> rusqlite::Result<()> {
+    db.execute("UPDATE lms_instance SET withdrawn_date = date('now') WHERE iid = ?1", [iid])?;
+    Ok(())
+}
+
+/// The staff member recording an instance event, and what they noted.
+struct Remark<'a> {
+    actor: u64,
+    note: &'a str,
+}
+
+fn instance_event(
+    db: &Connection,
+    iid: u64,
+    kind: u64,
+    due_date: Option<&str>,
+    uid: Option<u64>,
+    fid: Option<u64>,
+    remark: &Remark,
+) -> rusqlite::Result<()> {
+    db.execute(
+        "INSERT INTO lms_instance_event (iid, kind, date, due_date, uid, fid, note, actor) \
+        VALUES (?1, ?2, date('now'), ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![iid, kind, due_date, uid, fid, remark.note, remark.actor],
+    )?;
+    Ok(())
+}
+
+#[inline]
+pub fn admin_add_fine(db: &mut Connection, req: RequestFineAdd) -> ApiResult<ResponseFineAdd> {
+    info!("admin_add_fine IN {:?}", req);
+    if req.kind > 2 {
+        return Err(ApiError::validation("kind must be 0(overdue), 1(lost) or 2(damaged)"));
+    }
+    let iid = if req.iid == 0 { None } else { Some(req.iid) };
+    db.execute(
+        "INSERT INTO lms_fine (uid, iid, kind, amount, date, note) \
+        VALUES (?1, ?2, ?3, ?4, date('now'), ?5)",
+        rusqlite::params![req.uid, iid, req.kind, req.amount, req.note],
+    )?;
+    let fid = db.last_insert_rowid() as u64;
+    info!("admin_add_fine OUT {}", fid);
+    Ok(ResponseFineAdd {
+        success: true,
+        message: "success".to_string(),
+        fid,
+    })
+}
+
+#[inline]
+pub fn admin_pay_fine(db: &mut Connection, req: RequestFineSettle) -> ApiResult<ResponseFineSettle> {
+    info!("admin_pay_fine IN {:?}", req);
+    settle_fine(db, req.fid, 1, "admin_pay_fine")
+}
+
+#[inline]
+pub fn admin_waive_fine(db: &mut Connection, req: RequestFineSettle) -> ApiResult<ResponseFineSettle> {
+    info!("admin_waive_fine IN {:?}", req);
+    settle_fine(db, req.fid, 2, "admin_waive_fine")
+}
+
+fn settle_fine(db: &Connection, fid: u64, status: u64, name: &str) -> ApiResult<ResponseFineSettle> {
+    let rows = db.execute(
+        "UPDATE lms_fine SET status = ?2, settle_date = date('now') WHERE fid = ?1 AND status = 0",
+        [fid, status],
+    )?;
+    affected(rows, "fine does not exist or is already settled")?;
+    info!("{name} OUT {}", fid);
+    Ok(ResponseFineSettle {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_add_category(db: &mut Connection, req: RequestCategoryAdd) -> ApiResult<ResponseCategoryAdd> {
+    info!("admin_add_category IN {:?}", req);
+    db.execute(
+        "INSERT INTO lms_category (name, info) VALUES (?1, ?2)",
+        [&req.name, &req.info],
+    )?;
+    let cid = db.last_insert_rowid() as u64;
+    info!("admin_add_category OUT {}", cid);
+    Ok(ResponseCategoryAdd {
+        success: true,
+        message: "success".to_string(),
+        cid,
+    })
+}
+
+#[inline]
+pub fn admin_add_item_type(db: &mut Connection, req: RequestItemTypeAdd) -> ApiResult<ResponseItemTypeAdd> {
+    info!("admin_add_item_type IN {:?}", req);
+    db.execute(
+        "INSERT INTO lms_item_type (name, info) VALUES (?1, ?2)",
+        [&req.name, &req.info],
+    )?;
+    let tid = db.last_insert_rowid() as u64;
+    info!("admin_add_item_type OUT {}", tid);
+    Ok(ResponseItemTypeAdd {
+        success: true,
+        message: "success".to_string(),
+        tid,
+    })
+}
+
+#[inline]
+pub fn admin_set_category(db: &mut Connection, req: RequestUserCategory) -> ApiResult<ResponseUserCategory> {
+    info!("admin_set_category IN {:?}", req);
+    let rows = db.execute(
+        "UPDATE lms_user SET cid = ?2 WHERE uid = ?1 \
+        AND EXISTS (SELECT 1 FROM lms_category WHERE cid = ?2)",
+        [req.uid, req.cid],
+    )?;
+    affected(rows, "user or category does not exist")?;
+    info!("admin_set_category OUT {:?}", req);
+    Ok(ResponseUserCategory {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_set_active(db: &mut Connection, req: RequestUserActive) -> ApiResult<ResponseUserActive> {
+    info!("admin_set_active IN {:?}", req);
+    let tx = db.savepoint()?;
+    let rows = tx.execute(
+        "UPDATE lms_user SET active = ?2 WHERE uid = ?1",
+        rusqlite::params![req.uid, req.active],
+    )?;
+    affected(rows, "user does not exist")?;
+    if !req.active {
+        tx.execute("DELETE FROM lms_session WHERE uid = ?1", [req.uid])?;
+    }
+    tx.commit()?;
+    info!("admin_set_active OUT {:?}", req);
+    Ok(ResponseUserActive {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_grant_role(db: &mut Connection, req: RequestRoleGrant) -> ApiResult<ResponseRoleGrant> {
+    info!("admin_grant_role IN {:?}", req);
+    if Role::from_u64(req.role).is_none() {
+        return Err(ApiError::validation("role does not exist"));
+    }
+    let current = db.query_row(
+        "SELECT role FROM lms_user WHERE uid = ?1",
+        [req.uid],
+        |row| row.get::<_, u64>(0),
+    ).optional()?.ok_or_else(|| ApiError::not_found("user does not exist"))?;
+    // Never demote the last administrator, or nobody could grant roles again.
+    let administrators = db.query_row(
+        "SELECT COUNT(*) FROM lms_user WHERE role = 3",
+        [],
+        |row| row.get::<_, u64>(0),
+    )?;
+    if current == 3 && req.role != 3 && administrators <= 1 {
+        return Err(ApiError::conflict("user is the last administrator"));
+    }
+    db.execute("UPDATE lms_user SET role = ?2 WHERE uid = ?1", [req.uid, req.role])?;
+    info!("admin_grant_role OUT {:?}", req);
+    Ok(ResponseRoleGrant {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_set_password(db: &mut Connection, req: RequestUserPassword) -> ApiResult<ResponseUserPassword> {
+    info!("admin_set_password IN {:?}", req);
+    if !is_password_legit(&req.password) {
+        return Err(ApiError::validation("password is not legit"));
+    }
+    let tx = db.savepoint()?;
+    tx.query_row("SELECT 1 FROM lms_user WHERE uid = ?1", [req.uid], |_| Ok(()))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("user does not exist"))?;
+    set_password(&tx, req.uid, &req.password)?;
+    // Whoever held the old password is signed out.
+    tx.execute("DELETE FROM lms_session WHERE uid = ?1", [req.uid])?;
+    tx.commit()?;
+    info!("admin_set_password OUT {:?}", req);
+    Ok(ResponseUserPassword {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_set_policy(db: &mut Connection, req: RequestPolicySet) -> ApiResult<ResponsePolicySet> {
+    info!("admin_set_policy IN {:?}", req);
+    db.execute(
+        "INSERT OR REPLACE INTO lms_policy \
+        (cid, tid, max_loans, loan_period, renewal_limit, max_holds, fine_daily_rate) \
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        [req.cid, req.tid, req.max_loans, req.loan_period,
+            req.renewal_limit, req.max_holds, req.fine_daily_rate],
+    )?;
+    info!("admin_set_policy OUT {:?}", req);
+    Ok(ResponsePolicySet {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+fn location_kind(db: &Connection, lid: u64) -> ApiResult<u64> {
+    db.query_row("SELECT kind FROM lms_location WHERE lid = ?1", [lid], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("location does not exist"))
+}
+
+/// The branch at the root of the tree `lid` sits in.
+fn branch_of(db: &Connection, lid: u64) -> rusqlite::Result<Option<u64>> {
+    db.query_row(
+        "WITH RECURSIVE up (lid, parent) AS ( \
+            SELECT lid, parent FROM lms_location WHERE lid = ?1 \
+            UNION ALL SELECT l.lid, l.parent FROM lms_location l JOIN up u ON l.lid = u.parent) \
+        SELECT lid FROM up WHERE parent IS NULL",
+        [lid],
+        |row| row.get(0),
+    ).optional()
+}
+
+#[inline]
+pub fn admin_add_location(db: &mut Connection, req: RequestLocationAdd) -> ApiResult<ResponseLocationAdd> {
+    info!("admin_add_location IN {:?}", req);
+    if req.kind > 3 {
+        return Err(ApiError::validation("location kind is not valid"));
+    }
+    let tx = db.savepoint()?;
+    let parent = match req.parent {
+        0 if req.kind != 0 => return Err(ApiError::validation("only a branch can stand on its own")),
+        0 => None,
+        parent if location_kind(&tx, parent)? >= req.kind =>
+            return Err(ApiError::validation("location must be of a lower level than its parent")),
+        parent => Some(parent),
+    };
+    tx.execute(
+        "INSERT INTO lms_location (name, info, parent, kind) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![req.name, req.info, parent, req.kind],
+    )?;
+    let lid = tx.last_insert_rowid() as u64;
+    tx.commit()?;
+    info!("admin_add_location OUT {:?}", req);
+    Ok(ResponseLocationAdd {
+        success: true,
+        message: "success".to_string(),
+        lid,
+    })
+}
+
+/// Columns, besides an instance's own, that record where something happened.
+/// Rewriting them would misstate the past, so a location they refer to stays.
+const LOCATION_HISTORY: [(&str, &str); 6] = [
+    ("lms_transfer", "origin"),
+    ("lms_transfer", "destination"),
+    ("lms_stocktake", "lid"),
+    ("lms_stocktake_scan", "lid"),
+    ("lms_stocktake_finding", "lid"),
+    ("lms_stocktake_finding", "found"),
+];
+
+#[inline]
+pub fn admin_remove_location(db: &mut Connection, req: RequestLocationRemove) -> ApiResult<ResponseLocationRemove> {
+    info!("admin_remove_location IN {:?}", req);
+    let tx = db.savepoint()?;
+    location_kind(&tx, req.lid)?;
+    let children = tx.query_row(
+        "SELECT COUNT(*) FROM lms_location WHERE parent = ?1",
+        [req.lid],
+        |row| row.get::<_, u64>(0),
+    )?;
+    if children > 0 {
+        return Err(ApiError::conflict("location still contains other locations"));
+    }
+    for (table, column) in LOCATION_HISTORY {
+        let references = tx.query_row(
+            &format!("SELECT COUNT(*) FROM {table} WHERE {column} = ?1"),
+            [req.lid],
+            |row| row.get::<_, u64>(0),
+        )?;
+        if references > 0 {
+            return Err(ApiError::conflict("location is referenced by transfers or stocktakes"));
+        }
+    }
+    let holds = tx.query_row(
+        "SELECT COUNT(*) FROM lms_hold WHERE pickup = ?1 AND status IN (0, 1)",
+        [req.lid],
+        |row| row.get::<_, u64>(0),
+    )?;
+    if holds > 0 {
+        return Err(ApiError::conflict(&format!("location is the pickup branch of {holds} active holds")));
+    }
+    // Holds that are over no longer need to know where they would have been collected.
+    tx.execute("UPDATE lms_hold SET pickup = NULL WHERE pickup = ?1", [req.lid])?;
+    // Withdrawn copies keep their last location, so they move along too.
+    let instances = tx.query_row(
+        "SELECT COUNT(*) FROM lms_instance WHERE lid = ?1",
+        [req.lid],
+        |row| row.get::<_, u64>(0),
+    )?;
+    if instances > 0 {
+        match req.target {
+            0 => return Err(ApiError::conflict(&format!("location still holds {instances} instances"))),
+            target if target == req.lid => return Err(ApiError::validation("cannot relocate instances to the removed location")),
+            target => location_kind(&tx, target)?,
+        };
+        tx.execute("UPDATE lms_instance SET lid = ?1 WHERE lid = ?2", [req.target, req.lid])?;
+    }
+    tx.execute("DELETE FROM lms_location WHERE lid = ?1", [req.lid])?;
+    tx.commit()?;
+    info!("admin_remove_location OUT {:?}", req);
+    Ok(ResponseLocationRemove {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_alter_location(db: &mut Connection, req: RequestLocationAlter) -> ApiResult<ResponseLocationAlter> {
+    info!("admin_alters_location IN {:?}", req);
+    let rows = db.execute(
+        "UPDATE lms_location SET name = ?1, info = ?2 WHERE lid = ?3",
+        [&req.name, &req.info, &req.lid.to_string()],
+    )?;
+    affected(rows, "location does not exist")?;
+    info!("admin_alters_location OUT {:?}", req);
+    Ok(ResponseLocationAlter {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn location_tree(db: &mut Connection, req: RequestLocationTree) -> ApiResult<ResponseLocationTree> {
+    info!("location_tree IN {:?}", req);
+    if req.lid != 0 {
+        location_kind(db, req.lid)?;
+    }
+    // Zero-padded paths sort every location right after its parent.
+    let locations = db.prepare(
+        "WITH RECURSIVE tree (lid, depth, path) AS ( \
+            SELECT lid, 0, printf('%020d', lid) FROM lms_location \
+            WHERE (?1 = 0 AND parent IS NULL) OR lid = ?1 \
+            UNION ALL \
+            SELECT l.lid, t.depth + 1, t.path || printf('/%020d', l.lid) \
+            FROM lms_location l JOIN tree t ON l.parent = t.lid) \
+        SELECT l.lid, l.parent, l.kind, t.depth, l.name, l.info, \
+            (SELECT COUNT(*) FROM lms_instance i WHERE i.lid = l.lid AND i.withdrawn_date IS NULL) \
+        FROM tree t JOIN lms_location l ON l.lid = t.lid ORDER BY t.path",
+    )?
+        .query_map([req.lid], |row| Ok(LocationNode {
+            lid: row.get(0)?,
+            parent: row.get::<_, Option<u64>>(1)?.unwrap_or(0),
+            kind: row.get(2)?,
+            depth: row.get(3)?,
+            name: row.get(4)?,
+            info: row.get(5)?,
+            instances: row.get(6)?,
+        }))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    info!("location_tree OUT {:?}", locations);
+    Ok(ResponseLocationTree {
+        success: true,
+        message: "success".to_string(),
+        locations,
+    })
+}
+
+#[inline]
+pub fn location_contents(db: &mut Connection, req: RequestLocationContents) -> ApiResult<ResponseLocationContents> {
+    info!("location_contents IN {:?}", req);
+    location_kind(db, req.lid)?;
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(
+        "WITH RECURSIVE subtree (lid) AS ( \
+            SELECT ?1 UNION ALL SELECT l.lid FROM lms_location l JOIN subtree s ON l.parent = s.lid) \
+        SELECT i.iid, i.bid, i.lid, l.name, i.status, o.kind, o.date, o.due_date, i.withdrawn_date \
+        FROM lms_instance i \
+        LEFT JOIN lms_location l ON l.lid = i.lid \
+        LEFT JOIN lms_occupation o ON o.iid = i.iid \
+        WHERE i.lid IN (SELECT lid FROM subtree) AND i.withdrawn_date IS NULL AND i.iid > ?2 \
+        ORDER BY i.iid LIMIT ?3",
+    )?;
+    let instances = stmt.query_map([req.lid, req.cursor, limit + 1], instance_record)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (instances, next_cursor) = next_page(instances, limit, |instance| instance.iid);
+    info!("location_contents OUT {:?} {}", instances, next_cursor);
+    Ok(ResponseLocationContents {
+        success: true,
+        message: "success".to_string(),
+        instances,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn admin_request_transfer(db: &mut Connection, req: RequestTransferRequest) -> ApiResult<ResponseTransferRequest> {
+    info!("admin_request_transfer IN {:?}", req);
+    let tx = db.savepoint()?;
+    instance_book(&tx, req.iid)?;
+    location_kind(&tx, req.destination)?;
+    let lid = tx.query_row("SELECT lid FROM lms_instance WHERE iid = ?1", [req.iid], |row| row.get::<_, u64>(0))?;
+    if lid == req.destination {
+        return Err(ApiError::validation("instance is already at the destination"));
+    }
+    if let Some((_, kind)) = occupation_of(&tx, req.iid)? {
+        return Err(occupied(kind));
+    }
+    let xid = start_transfer(&tx, req.iid, req.destination, None, &req.note)?;
+    tx.commit()?;
+    info!("admin_request_transfer OUT {xid}");
+    Ok(ResponseTransferRequest {
+        success: true,
+        message: "success".to_string(),
+        xid,
+    })
+}
+
+#[inline]
+pub fn admin_dispatch_transfer(db: &mut Connection, req: RequestTransferDispatch) -> ApiResult<ResponseTransferDispatch> {
+    info!("admin_dispatch_transfer IN {:?}", req);
+    let transfer = open_transfer(db, req.xid)?;
+    if transfer.status != 0 {
+        return Err(ApiError::conflict("transfer was already dispatched"));
+    }
+    db.execute(
+        "UPDATE lms_transfer SET status = 1, dispatch_date = datetime('now') WHERE xid = ?1",
+        [req.xid],
+    )?;
+    info!("admin_dispatch_transfer OUT {:?}", req);
+    Ok(ResponseTransferDispatch {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_receive_transfer(db: &mut Connection, req: RequestTransferReceive) -> ApiResult<ResponseTransferReceive> {
+    info!("admin_receive_transfer IN {:?}", req);
+    let tx = db.savepoint()?;
+    let transfer = open_transfer(&tx, req.xid)?;
+    if transfer.status != 1 {
+        return Err(ApiError::conflict("transfer was not dispatched"));
+    }
+    tx.execute(
+        "UPDATE lms_transfer SET status = 2, receive_date = datetime('now') WHERE xid = ?1",
+        [req.xid],
+    )?;
+    tx.execute("UPDATE lms_instance SET lid = ?1 WHERE iid = ?2", [transfer.destination, transfer.iid])?;
+    tx.execute("DELETE FROM lms_occupation WHERE iid = ?1 AND kind = 4", [transfer.iid])?;
+    let held_for = match waiting_hold(&tx, &transfer)? {
+        Some((hid, uid)) => {
+            shelve_hold(&tx, hid, uid, transfer.iid)?;
+            uid
+        }
+        None => assign_hold(&tx, transfer.iid)?.unwrap_or(0),
+    };
+    tx.commit()?;
+    info!("admin_receive_transfer OUT {:?} {}", req, held_for);
+    Ok(ResponseTransferReceive {
+        success: true,
+        message: "success".to_string(),
+        held_for,
+    })
+}
+
+#[inline]
+pub fn admin_cancel_transfer(db: &mut Connection, req: RequestTransferCancel) -> ApiResult<ResponseTransferCancel> {
+    info!("admin_cancel_transfer IN {:?}", req);
+    let tx = db.savepoint()?;
+    let transfer = open_transfer(&tx, req.xid)?;
+    if waiting_hold(&tx, &transfer)?.is_some() {
+        return Err(ApiError::conflict("transfer is for a hold, cancel the hold instead"));
+    }
+    // The copy stays at, or is back at, its origin.
+    tx.execute("UPDATE lms_transfer SET status = 3 WHERE xid = ?1", [req.xid])?;
+    tx.execute("DELETE FROM lms_occupation WHERE iid = ?1 AND kind = 4", [transfer.iid])?;
+    assign_hold(&tx, transfer.iid)?;
+    tx.commit()?;
+    info!("admin_cancel_transfer OUT {:?}", req);
+    Ok(ResponseTransferCancel {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn admin_transfers(db: &mut Connection, req: RequestTransferList) -> ApiResult<ResponseTransferList> {
+    info!("admin_transfers IN {:?}", req);
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(&format!(
+        "WITH RECURSIVE subtree (lid) AS ( \
+            SELECT ?1 UNION ALL SELECT l.lid FROM lms_location l JOIN subtree s ON l.parent = s.lid) \
+        SELECT {TRANSFER_COLUMNS} FROM lms_transfer \
+        WHERE (?1 = 0 OR origin IN (SELECT lid FROM subtree) OR destination IN (SELECT lid FROM subtree)) \
+        AND (?2 = 0 OR status IN (0, 1)) AND xid > ?3 ORDER BY xid LIMIT ?4",
+    ))?;
+    let transfers = stmt.query_map(rusqlite::params![req.lid, req.open, req.cursor, limit + 1], transfer_record)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (transfers, next_cursor) = next_page(transfers, limit, |transfer| transfer.xid);
+    info!("admin_transfers OUT {:?} {}", transfers, next_cursor);
+    Ok(ResponseTransferList {
+        success: true,
+        message: "success".to_string(),
+        transfers,
+        next_cursor,
+    })
+}
+
+/// Occupies `iid` as in transit and records its move from where it is now to
+/// `destination`, returning the new transfer.
+fn start_transfer(db: &Connection, iid: u64, destination: u64, hid: Option<u64>, note: &str) -> rusqlite::Result<u64> {
+    db.execute(
+        "INSERT INTO lms_transfer (iid, origin, destination, hid, request_date, note) \
+        SELECT iid, lid, ?2, ?3, datetime('now'), ?4 FROM lms_instance WHERE iid = ?1",
+        rusqlite::params![iid, destination, hid, note],
+    )?;
+    let xid = db.last_insert_rowid() as u64;
+    db.execute(
+        "INSERT INTO lms_occupation (uid, iid, date, kind) VALUES (null, ?1, date('now'), 4)",
+        [iid],
+    )?;
+    Ok(xid)
+}
+
+const TRANSFER_COLUMNS: &str = "xid, iid, origin, destination, hid, status, \
+    request_date, dispatch_date, receive_date, note";
+
+fn transfer_record(row: &rusqlite::Row) -> rusqlite::Result<Transfer> {
+    Ok(Transfer {
+        xid: row.get(0)?,
+        iid: row.get(1)?,
+        origin: row.get(2)?,
+        destination: row.get(3)?,
+        hid: row.get::<_, Option<u64>>(4)?.unwrap_or(0),
+        status: row.get(5)?,
+        request_date: row.get(6)?,
+        dispatch_date: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+        receive_date: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+        note: row.get(9)?,
+    })
+}
+
+/// Transfer `xid`, failing unless it is still requested or dispatched.
+fn open_transfer(db: &Connection, xid: u64) -> ApiResult<Transfer> {
+    let transfer = db.query_row(
+        &format!("SELECT {TRANSFER_COLUMNS} FROM lms_transfer WHERE xid = ?1"),
+        [xid],
+        transfer_record,
+    ).optional()?.ok_or_else(|| ApiError::not_found("transfer does not exist"))?;
+    if transfer.status > 1 {
+        return Err(ApiError::conflict("transfer is already closed"));
+    }
+    Ok(transfer)
+}
+
+/// The hold `transfer` was made for, as `(hid, uid)`, if it still waits for the copy.
+fn waiting_hold(db: &Connection, transfer: &Transfer) -> rusqlite::Result<Option<(u64, u64)>> {
+    db.query_row(
+        "SELECT hid, uid FROM lms_hold WHERE hid = ?1 AND iid = ?2 AND status = 0",
+        [transfer.hid, transfer.iid],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()
+}
+
+#[inline]
+pub fn admin_open_stocktake(db: &mut Connection, req: RequestStocktakeOpen) -> ApiResult<ResponseStocktakeOpen> {
+    info!("admin_open_stocktake IN {:?}", req);
+    location_kind(db, req.lid)?;
+    db.execute(
+        "INSERT INTO lms_stocktake (lid, open_date, note) VALUES (?1, datetime('now'), ?2)",
+        rusqlite::params![req.lid, req.note],
+    )?;
+    let vid = db.last_insert_rowid() as u64;
+    info!("admin_open_stocktake OUT {vid}");
+    Ok(ResponseStocktakeOpen {
+        success: true,
+        message: "success".to_string(),
+        vid,
+    })
+}
+
+#[inline]
+pub fn admin_scan_stocktake(db: &mut Connection, req: RequestStocktakeScan) -> ApiResult<ResponseStocktakeScan> {
+    info!("admin_scan_stocktake IN {:?}", req.vid);
+    let tx = db.savepoint()?;
+    let stocktake = open_stocktake(&tx, req.vid)?;
+    let lid = match req.lid {
+        0 => stocktake.lid,
+        lid => lid,
+    };
+    let inside = tx.query_row(
+        "WITH RECURSIVE subtree (lid) AS ( \
+            SELECT ?1 UNION ALL SELECT l.lid FROM lms_location l JOIN subtree s ON l.parent = s.lid) \
+        SELECT EXISTS (SELECT 1 FROM subtree WHERE lid = ?2)",
+        [stocktake.lid, lid],
+        |row| row.get::<_, bool>(0),
+    )?;
+    if !inside {
+        return Err(ApiError::validation("location is not covered by the stocktake"));
+    }
+    let mut scanned = 0;
+    let mut unknown = Vec::new();
+    {
+        let mut scan = tx.prepare(
+            "INSERT INTO lms_stocktake_scan (vid, iid, lid, date) \
+            SELECT ?1, iid, ?3, datetime('now') FROM lms_instance WHERE iid = ?2 \
+            ON CONFLICT (vid, iid) DO UPDATE SET lid = excluded.lid, date = excluded.date",
+        )?;
+        for &iid in &req.iids {
+            match scan.execute([req.vid, iid, lid])? {
+                0 => unknown.push(iid),
+                _ => scanned += 1,
+            }
+        }
+    }
+    tx.commit()?;
+    info!("admin_scan_stocktake OUT {} {:?}", scanned, unknown);
+    Ok(ResponseStocktakeScan {
+        success: true,
+        message: "success".to_string(),
+        scanned,
+        unknown,
+    })
+}
+
+#[inline]
+pub fn admin_close_stocktake(db: &mut Connection, actor: u64, req: RequestStocktakeClose) -> ApiResult<ResponseStocktakeClose> {
+    info!("admin_close_stocktake IN {:?}", req);
+    let tx = db.savepoint()?;
+    let stocktake = open_stocktake(&tx, req.vid)?;
+    let mut findings = stocktake_findings(&tx, &stocktake)?;
+    let note = format!("stocktake {}", req.vid);
+    for (kind, finding) in &mut findings {
+        // Copies on the hold shelf stay there; only free copies go lost.
+        if req.mark_lost && *kind == 0 && occupation_of(&tx, finding.iid)?.is_none() {
+            lose_instance(&tx, finding.iid, false, &Remark { actor, note: &note })?;
+            finding.lost = true;
+        }
+        tx.execute(
+            "INSERT INTO lms_stocktake_finding (vid, iid, kind, lid, found, lost) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![req.vid, finding.iid, *kind, finding.lid, (finding.found != 0).then_some(finding.found), finding.lost],
+        )?;
+    }
+    tx.execute(
+        "UPDATE lms_stocktake SET status = 1, close_date = datetime('now') WHERE vid = ?1",
+        [req.vid],
+    )?;
+    tx.commit()?;
+    let report = stocktake_report(findings);
+    info!("admin_close_stocktake OUT {:?}", report);
+    Ok(ResponseStocktakeClose {
+        success: true,
+        message: "success".to_string(),
+        report,
+    })
+}
+
+#[inline]
+pub fn admin_stocktake(db: &mut Connection, req: RequestStocktakeReport) -> ApiResult<ResponseStocktakeReport> {
+    info!("admin_stocktake IN {:?}", req);
+    let stocktake = db.query_row(
+        &format!("SELECT {STOCKTAKE_COLUMNS} FROM lms_stocktake s WHERE vid = ?1"),
+        [req.vid],
+        stocktake_record,
+    ).optional()?.ok_or_else(|| ApiError::not_found("stocktake does not exist"))?;
+    let findings = match stocktake.status {
+        0 => stocktake_findings(db, &stocktake)?,
+        _ => db.prepare(
+            "SELECT f.kind, f.iid, i.bid, f.lid, f.found, f.lost FROM lms_stocktake_finding f \
+            JOIN lms_instance i ON i.iid = f.iid WHERE f.vid = ?1 ORDER BY f.kind, f.iid",
+        )?
+            .query_map([req.vid], finding_record)?
+            .collect::<rusqlite::Result<Vec<_>>>()?,
+    };
+    let report = stocktake_report(findings);
+    info!("admin_stocktake OUT {:?} {:?}", stocktake, report);
+    Ok(ResponseStocktakeReport {
+        success: true,
+        message: "success".to_string(),
+        stocktake,
+        report,
+    })
+}
+
+#[inline]
+pub fn admin_stocktakes(db: &mut Connection, req: RequestStocktakeList) -> ApiResult<ResponseStocktakeList> {
+    info!("admin_stocktakes IN {:?}", req);
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(&format!(
+        "WITH RECURSIVE subtree (lid) AS ( \
+            SELECT ?1 UNION ALL SELECT l.lid FROM lms_location l JOIN subtree s ON l.parent = s.lid) \
+        SELECT {STOCKTAKE_COLUMNS} FROM lms_stocktake s \
+        WHERE (?1 = 0 OR lid IN (SELECT lid FROM subtree)) \
+        AND (?2 = 0 OR status = 0) AND vid > ?3 ORDER BY vid LIMIT ?4",
+    ))?;
+    let stocktakes = stmt.query_map(rusqlite::params![req.lid, req.open, req.cursor, limit + 1], stocktake_record)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (stocktakes, next_cursor) = next_page(stocktakes, limit, |stocktake| stocktake.vid);
+    info!("admin_stocktakes OUT {:?} {}", stocktakes, next_cursor);
+    Ok(ResponseStocktakeList {
+        success: true,
+        message: "success".to_string(),
+        stocktakes,
+        next_cursor,
+    })
+}
+
+const STOCKTAKE_COLUMNS: &str = "vid, lid, status, open_date, close_date, note, \
+    (SELECT COUNT(*) FROM lms_stocktake_scan c WHERE c.vid = s.vid)";
+
+fn stocktake_record(row: &rusqlite::Row) -> rusqlite::Result<Stocktake> {
+    Ok(Stocktake {
+        vid: row.get(0)?,
+        lid: row.get(1)?,
+        status: row.get(2)?,
+        open_date: row.get(3)?,
+        close_date: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+        note: row.get(5)?,
+        scanned: row.get(6)?,
+    })
+}
+
+/// Stocktake `vid`, failing unless it is still open.
+fn open_stocktake(db: &Connection, vid: u64) -> ApiResult<Stocktake> {
+    let stocktake = db.query_row(
+        &format!("SELECT {STOCKTAKE_COLUMNS} FROM lms_stocktake s WHERE vid = ?1"),
+        [vid],
+        stocktake_record,
+    ).optional()?.ok_or_else(|| ApiError::not_found("stocktake does not exist"))?;
+    if stocktake.status != 0 {
+        return Err(ApiError::conflict("stocktake is already closed"));
+    }
+    Ok(stocktake)
+}
+
+/// Compares the scans of an open stocktake with the records, as `(kind,
+/// finding)` pairs ordered by kind. Copies are expected on the shelves
+/// unless they are borrowed, under maintenance, lost or in transit. A copy
+/// is misplaced when it was scanned at a location that neither contains nor
+/// lies within the one it is recorded at, so scanning a whole room at once
+/// does not flag every copy on its shelves.
+fn stocktake_findings(db: &Connection, stocktake: &Stocktake) -> rusqlite::Result<Vec<(u64, StocktakeFinding)>> {
+    db.prepare(
+        "WITH RECURSIVE subtree (lid) AS ( \
+            SELECT ?2 UNION ALL SELECT l.lid FROM lms_location l JOIN subtree s ON l.parent = s.lid), \
+        lineage (lid, ancestor) AS ( \
+            SELECT lid, lid FROM lms_location \
+            UNION ALL SELECT g.lid, l.parent FROM lineage g \
+            JOIN lms_location l ON l.lid = g.ancestor WHERE l.parent IS NOT NULL) \
+        SELECT 0, i.iid, i.bid, i.lid, NULL, 0 FROM lms_instance i \
+        LEFT JOIN lms_occupation o ON o.iid = i.iid \
+        WHERE i.lid IN (SELECT lid FROM subtree) AND i.withdrawn_date IS NULL AND (o.kind IS NULL OR o.kind = 1) \
+        AND NOT EXISTS (SELECT 1 FROM lms_stocktake_scan c WHERE c.vid = ?1 AND c.iid = i.iid) \
+        UNION ALL \
+        SELECT 1, i.iid, i.bid, i.lid, c.lid, 0 FROM lms_stocktake_scan c \
+        JOIN lms_instance i ON i.iid = c.iid WHERE c.vid = ?1 AND NOT EXISTS ( \
+            SELECT 1 FROM lineage g WHERE (g.lid = i.lid AND g.ancestor = c.lid) \
+            OR (g.lid = c.lid AND g.ancestor = i.lid)) \
+        UNION ALL \
+        SELECT 2, i.iid, i.bid, i.lid, c.lid, 0 FROM lms_stocktake_scan c \
+        JOIN lms_instance i ON i.iid = c.iid \
+        JOIN lms_occupation o ON o.iid = c.iid AND o.kind = 0 WHERE c.vid = ?1 \
+        ORDER BY 1, 2",
+    )?
+        .query_map([stocktake.vid, stocktake.lid], finding_record)?
+        .collect()
+}
+
+fn finding_record(row: &rusqlite::Row) -> rusqlite::Result<(u64, StocktakeFinding)> {
+    Ok((row.get(0)?, StocktakeFinding {
+        iid: row.get(1)?,
+        bid: row.get(2)?,
+        lid: row.get(3)?,
+        found: row.get::<_, Option<u64>>(4)?.unwrap_or(0),
+        lost: row.get(5)?,
+    }))
+}
+
+fn stocktake_report(findings: Vec<(u64, StocktakeFinding)>) -> StocktakeReport {
+    let mut report = StocktakeReport {
+        missing: Vec::new(),
+        misplaced: Vec::new(),
+        borrowed: Vec::new(),
+    };
+    for (kind, finding) in findings {
+        match kind {
+            0 => report.missing.push(finding),
+            1 => report.misplaced.push(finding),
+            _ => report.borrowed.push(finding),
+        }
+    }
+    report
+}
+
+#[inline]
+pub fn admin_overdue(db: &mut Connection, req: RequestAdminOverdue) -> ApiResult<ResponseAdminOverdue> {
+    info!("admin_overdue IN {:?}", req);
+    let overdue = overdue_loans(db, None)?;
+    info!("admin_overdue OUT {:?}", overdue);
+    Ok(ResponseAdminOverdue {
+        success: true,
+        message: "success".to_string(),
+        overdue,
+    })
+}
+
+#[inline]
+pub fn admin_audit(db: &mut Connection, req: RequestAdminAudit) -> ApiResult<ResponseAdminAudit> {
+    info!("admin_audit IN {:?}", req);
+    if !req.entity.is_empty() && Entity::from_str(&req.entity).is_none() {
+        return Err(ApiError::validation("entity does not exist"));
+    }
+    let since = audit_time(db, &req.since, "since")?;
+    let until = audit_time(db, &req.until, "until")?;
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(
+        "SELECT aid, actor, endpoint, entity, target, before, after, date FROM lms_audit \
+        WHERE (?1 = 0 OR actor = ?1) AND (?2 = '' OR entity = ?2) AND (?3 = 0 OR target = ?3) \
+        AND (?4 IS NULL OR date >= ?4) AND (?5 IS NULL OR date < ?5) AND (?6 = 0 OR aid < ?6) \
+        ORDER BY aid DESC LIMIT ?7",
+    )?;
+    let params = rusqlite::params![req.actor, req.entity, req.target, since, until, req.cursor, limit + 1];
+    let entries = stmt.query_map(params, |row| Ok(AuditEntry {
+        aid: row.get(0)?,
+        actor: row.get::<_, Option<u64>>(1)?.unwrap_or(0),
+        endpoint: row.get(2)?,
+        entity: row.get(3)?,
+        target: row.get::<_, Option<u64>>(4)?.unwrap_or(0),
+        before: audit_json(row.get(5)?),
+        after: audit_json(row.get(6)?),
+        date: row.get(7)?,
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let (entries, next_cursor) = next_page(entries, limit, |entry| entry.aid);
+    info!("admin_audit OUT {} {}", entries.len(), next_cursor);
+    Ok(ResponseAdminAudit {
+        success: true,
+        message: "success".to_string(),
+        entries,
+        next_cursor,
+    })
+}
+
+/// Normalizes a date or date and time to the `datetime('now')` format the log uses.
+fn audit_time(db: &Connection, value: &str, name: &str) -> ApiResult<Option<String>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    db.query_row("SELECT datetime(?1)", [value], |row| row.get::<_, Option<String>>(0))?
+        .map(Some)
+        .ok_or_else(|| ApiError::validation(&format!("{name} is not a date or time")))
+}
+
+/// Normalizes a date to `YYYY-MM-DD`; empty means no date.
+fn parse_date(db: &Connection, value: &str, name: &str) -> ApiResult<Option<String>> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    db.query_row("SELECT date(?1)", [value], |row| row.get::<_, Option<String>>(0))?
+        .map(Some)
+        .ok_or_else(|| ApiError::validation(&format!("{name} is not a date")))
+}
+
+fn audit_json(snapshot: Option<String>) -> serde_json::Value {
+    snapshot.and_then(|snapshot| serde_json::from_str(&snapshot).ok()).unwrap_or_default()
+}
+
+fn overdue_loans(db: &Connection, uid: Option<u64>) -> rusqlite::Result<Vec<OverdueLoan>> {
+    let mut stmt = db.prepare(
+        "SELECT o.uid, o.iid, i.bid, o.date, o.due_date, \
+        CAST(julianday(date('now')) - julianday(o.due_date) AS INTEGER) \
+        FROM lms_occupation o JOIN lms_instance i ON i.iid = o.iid \
+        WHERE o.kind = 0 AND o.due_date < date('now') AND (?1 IS NULL OR o.uid = ?1) \
+        ORDER BY o.due_date",
+    )?;
+    let loans = stmt.query_map([uid], |row| {
+        Ok(OverdueLoan {
+            uid: row.get(0)?,
+            iid: row.get(1)?,
+            bid: row.get(2)?,
+            date: row.get(3)?,
+            due_date: row.get(4)?,
+            days_late: row.get(5)?,
+        })
+    })?;
+    loans.collect()
+}
+
+// Empty filter fields match every book.
+const SEARCH_FILTER: &str = "(:subject = '' OR bid IN \
+    (SELECT bid FROM lms_book_subject WHERE subject = :subject COLLATE NOCASE)) \
+    AND (:tag = '' OR bid IN (SELECT bid FROM lms_book_tag WHERE tag = :tag)) \
+    AND (:class_from = '' AND :class_to = '' OR call_number != '' \
+    AND call_number >= :class_from AND (:class_to = '' OR call_number < :class_to))";
+
+/// What a search is narrowed to besides its phrase.
+struct SearchFilter<'a> {
+    subject: &'a str,
+    tag: &'a str,
+    class_from: &'a str,
+    class_to: &'a str,
+}
+
+// A negative `limit` returns every match.
+fn search_books(
+    db: &Connection,
+    query: &str,
+    filter: &SearchFilter,
+    limit: i64,
+    offset: u64,
+) -> rusqlite::Result<Vec<SearchResult>> {
+    let (subject, class_from, class_to) = (filter.subject.trim(), filter.class_from.trim(), filter.class_to.trim());
+    let tag = filter.tag.trim().to_lowercase();
+    let mut params = rusqlite::named_params! {
+        ":subject": subject,
+        ":tag": tag,
+        ":class_from": class_from,
+        ":class_to": class_to,
+        ":limit": limit,
+        ":offset": offset,
+    }.to_vec();
+    if query.trim().is_empty() {
+        let mut stmt = db.prepare(&format!(
+            "SELECT bid, title, author FROM lms_book WHERE {SEARCH_FILTER} \
+            ORDER BY bid LIMIT :limit OFFSET :offset",
+        ))?;
+        let results = stmt.query_map(params.as_slice(), |row| Ok(SearchResult {
+            bid: row.get(0)?,
+            score: 0.0,
+            title: row.get(1)?,
+            author: row.get(2)?,
+            snippet: String::new(),
+        }))?;
+        return results.collect();
+    }
+    // Title matches weigh most, then author, then the free-form info.
+    let mut stmt = db.prepare(&format!(
+        "SELECT rowid, -bm25(lms_book_fts, 10.0, 5.0, 1.0), \
+        highlight(lms_book_fts, 0, '[', ']'), highlight(lms_book_fts, 1, '[', ']'), \
+        snippet(lms_book_fts, -1, '[', ']', '...', 16) \
+        FROM lms_book_fts WHERE lms_book_fts MATCH :query \
+        AND rowid IN (SELECT bid FROM lms_book WHERE {SEARCH_FILTER}) \
+        ORDER BY bm25(lms_book_fts, 10.0, 5.0, 1.0), rowid LIMIT :limit OFFSET :offset",
+    ))?;
+    params.push((":query", &query));
+    let results = stmt.query_map(params.as_slice(), |row| Ok(SearchResult {
+        bid: row.get(0)?,
+        score: row.get(1)?,
+        title: row.get(2)?,
+        author: row.get(3)?,
+        snippet: row.get(4)?,
+    }))?;
+    results.collect()
+}
+
+/// Whether `err` is FTS5 rejecting the query text itself, the one failure
+/// worth retrying with `plain_query`. FTS5 reports those as plain errors
+/// with one of these messages.
+fn is_fts_query_error(err: &rusqlite::Error) -> bool {
+    match err {
+        rusqlite::Error::SqliteFailure(failure, Some(message)) if failure.code == rusqlite::ErrorCode::Unknown => {
+            message.starts_with("fts5: syntax error")
+                || message.starts_with("no such column: ")
+                || message.starts_with("unknown special query")
+                || message == "unterminated string"
+        }
+        _ => false,
+    }
+}
+
+/// Quotes every word of `phrase`, so text that is not valid FTS5 query
+/// syntax (stray quotes, `c++`, `title:`) still searches as plain words.
+fn plain_query(phrase: &str) -> String {
+    phrase.split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[inline]
+pub fn book_search(db: &mut Connection, req: RequestBookSearch) -> ApiResult<ResponseBookSearch> {
+    info!("book_search IN {:?}", req);
+    let filter = SearchFilter {
+        subject: &req.subject,
+        tag: &req.tag,
+        class_from: &req.class_from,
+        class_to: &req.class_to,
+    };
+    let results = match search_books(db, &req.phrase, &filter, -1, 0) {
+        Err(err) if is_fts_query_error(&err) => search_books(db, &plain_query(&req.phrase), &filter, -1, 0),
+        results => results,
+    }?;
+    let bids = results.iter()
+        .map(|result| result.bid.to_string())
+        .collect::<Vec<String>>();
+    let response = ResponseBookSearch {
+        success: true,
+        message: "success".to_string(),
+        bid_list: bids.join(","),
+        results,
+    };
+    info!("book_search OUT {response:?}");
+    Ok(response)
+}
+
+#[inline]
+pub fn book_info(db: &mut Connection, req: RequestBookInfo) -> ApiResult<ResponseBookInfo> {
+    info!("book_info IN {:?}", req);
+    let res = db.query_row(
+        "SELECT title, author, info, tid FROM lms_book WHERE bid = ?1",
+        [&req.bid.to_string()],
+        |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+            ))
+        }
+    ).optional()?.ok_or_else(|| ApiError::not_found("book does not exist"))?;
+    let response = ResponseBookInfo {
+        success: true,
+        message: "success".to_string(),
+        title: res.0,
+        author: res.1,
+        info: res.2,
+        tid: res.3,
+        details: read_details(db, req.bid)?,
+        contributors: read_contributors(db, req.bid)?,
+        series: read_series(db, req.bid)?,
+    };
+    info!("book_info OUT {response:?}");
+    Ok(response)
+}
+
+fn read_series(db: &Connection, bid: u64) -> rusqlite::Result<Option<SeriesMembership>> {
+    db.query_row(
+        "SELECT s.sid, s.title, bs.volume FROM lms_book_series bs JOIN lms_series s ON s.sid = bs.sid \
+        WHERE bs.bid = ?1",
+        [bid],
+        |row| Ok(SeriesMembership {
+            sid: row.get(0)?,
+            title: row.get(1)?,
+            volume: row.get(2)?,
+        }),
+    ).optional()
+}
+
+// Volumes of a series with the number of their copies that could be handed out now.
+const VOLUME_QUERY: &str = "SELECT bs.volume, b.bid, b.title, b.author, b.info, b.tid, \
+    (SELECT COUNT(*) FROM lms_instance i LEFT JOIN lms_occupation o ON o.iid = i.iid \
+    WHERE i.bid = b.bid AND o.iid IS NULL AND i.withdrawn_date IS NULL) \
+    FROM lms_book_series bs JOIN lms_book b ON b.bid = bs.bid";
+
+fn series_volume(row: &rusqlite::Row) -> rusqlite::Result<SeriesVolume> {
+    Ok(SeriesVolume {
+        volume: row.get(0)?,
+        book: book_record(row, 1)?,
+        available: row.get(6)?,
+    })
+}
+
+#[inline]
+pub fn book_series(db: &mut Connection, req: RequestSeriesVolumes) -> ApiResult<ResponseSeriesVolumes> {
+    info!("book_series IN {:?}", req);
+    let (title, info) = db.query_row(
+        "SELECT title, info FROM lms_series WHERE sid = ?1",
+        [req.sid],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()?.ok_or_else(|| ApiError::not_found("series does not exist"))?;
+    let volumes = db.prepare(&format!("{VOLUME_QUERY} WHERE bs.sid = ?1 ORDER BY bs.volume"))?
+        .query_map([req.sid], series_volume)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    info!("book_series OUT {:?}", volumes);
+    Ok(ResponseSeriesVolumes {
+        success: true,
+        message: "success".to_string(),
+        title,
+        info,
+        volumes,
+    })
+}
+
+#[inline]
+pub fn book_next_volume(db: &mut Connection, req: RequestBookNextVolume) -> ApiResult<ResponseBookNextVolume> {
+    info!("book_next_volume IN {:?}", req);
+    let series = read_series(db, req.bid)?
+        .ok_or_else(|| ApiError::not_found("book is not part of a series"))?;
+    // Gaps in the numbering are skipped, so this is the lowest later volume.
+    let next = db.query_row(
+        &format!("{VOLUME_QUERY} WHERE bs.sid = ?1 AND bs.volume > ?2 ORDER BY bs.volume LIMIT 1"),
+        [series.sid, series.volume],
+        series_volume,
+    ).optional()?;
+    info!("book_next_volume OUT {:?}", next);
+    Ok(ResponseBookNextVolume {
+        success: true,
+        message: "success".to_string(),
+        next,
+    })
+}
+
+#[inline]
+pub fn book_by_author(db: &mut Connection, req: RequestBookByAuthor) -> ApiResult<ResponseBookByAuthor> {
+    info!("book_by_author IN {:?}", req);
+    let name = check_author(db, req.aid)?;
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(
+        "SELECT b.bid, b.title, b.author, b.info, b.tid, group_concat(ba.role) \
+        FROM lms_book_author ba JOIN lms_book b ON b.bid = ba.bid \
+        WHERE ba.aid = ?1 AND b.bid > ?2 GROUP BY b.bid ORDER BY b.bid LIMIT ?3",
+    )?;
+    let books = stmt.query_map([req.aid, req.cursor, limit + 1], |row| Ok(AuthorBook {
+        book: book_record(row, 0)?,
+        roles: row.get::<_, String>(5)?
+            .split(',')
+            .filter_map(|role| role.parse().ok())
+            .collect(),
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let (books, next_cursor) = next_page(books, limit, |book| book.book.bid);
+    info!("book_by_author OUT {:?} {}", books, next_cursor);
+    Ok(ResponseBookByAuthor {
+        success: true,
+        message: "success".to_string(),
+        name,
+        books,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn book_by_subject(db: &mut Connection, req: RequestBookBySubject) -> ApiResult<ResponseBookBySubject> {
+    info!("book_by_subject IN {:?}", req);
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(
+        "SELECT b.bid, b.title, b.author, b.info, b.tid \
+        FROM lms_book_subject s JOIN lms_book b ON b.bid = s.bid \
+        WHERE s.subject = ?1 COLLATE NOCASE AND b.bid > ?2 ORDER BY b.bid LIMIT ?3",
+    )?;
+    let books = stmt.query_map(rusqlite::params![req.subject.trim(), req.cursor, limit + 1], |row| book_record(row, 0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (books, next_cursor) = next_page(books, limit, |book| book.bid);
+    info!("book_by_subject OUT {:?} {}", books, next_cursor);
+    Ok(ResponseBookBySubject {
+        success: true,
+        message: "success".to_string(),
+        books,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn book_by_class(db: &mut Connection, req: RequestBookByClass) -> ApiResult<ResponseBookByClass> {
+    info!("book_by_class IN {:?}", req);
+    let (from, to) = (req.from.trim(), req.to.trim());
+    if !to.is_empty() && to <= from {
+        return Err(ApiError::validation("classification range is empty"));
+    }
+    let limit = page_limit(req.limit);
+    // Shelf order has duplicate keys, so the cursor is an offset.
+    let mut stmt = db.prepare(
+        "SELECT bid, title, author, info, tid, call_number FROM lms_book \
+        WHERE call_number != '' AND call_number >= ?1 AND (?2 = '' OR call_number < ?2) \
+        ORDER BY call_number, bid LIMIT ?3 OFFSET ?4",
+    )?;
+    let mut books = stmt.query_map(rusqlite::params![from, to, limit + 1, req.cursor], |row| Ok(ClassifiedBook {
+        book: book_record(row, 0)?,
+        call_number: row.get(5)?,
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let next_cursor = if books.len() as u64 > limit { req.cursor + limit } else { 0 };
+    books.truncate(limit as usize);
+    info!("book_by_class OUT {:?} {}", books, next_cursor);
+    Ok(ResponseBookByClass {
+        success: true,
+        message: "success".to_string(),
+        books,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn book_by_isbn(db: &mut Connection, req: RequestBookByIsbn) -> ApiResult<ResponseBookByIsbn> {
+    info!("book_by_isbn IN {:?}", req);
+    let isbn = normalize_isbn(&req.isbn).ok_or_else(|| ApiError::validation("isbn is not valid"))?;
+    let books = db.prepare("SELECT bid, title, author, info, tid FROM lms_book WHERE isbn = ?1 ORDER BY bid")?
+        .query_map([isbn], |row| book_record(row, 0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    info!("book_by_isbn OUT {:?}", books);
+    Ok(ResponseBookByIsbn {
+        success: true,
+        message: "success".to_string(),
+        books,
+    })
+}
+
+#[inline]
+pub fn book_instance(db: &mut Connection, req: RequestBookInstance) -> ApiResult<ResponseBookInstance> {
+    info!("book_instance IN {:?}", req);
+    let mut stmt = db.prepare("SELECT iid FROM lms_instance WHERE bid = ?1")?;
+    let iid_list = stmt
+        .query_map([&req.bid.to_string()], |row| row.get::<_, u64>(0))?
+        .map(|iid| iid.map(|iid| iid.to_string()))
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    let response = ResponseBookInstance {
+        success: true,
+        message: "success".to_string(),
+        iid_list: iid_list.join(","),
+    };
+    info!("book_instance OUT {response:?}");
+    Ok(response)
+}
+
+#[inline]
+pub fn book_holds(db: &mut Connection, req: RequestBookHolds) -> ApiResult<ResponseBookHolds> {
+    info!("book_holds IN {:?}", req);
+    let holds = holds_of(db, "h.bid = ?1", req.bid)?;
+    info!("book_holds OUT {:?}", holds);
+    Ok(ResponseBookHolds {
+        success: true,
+        message: "success".to_string(),
+        holds,
+    })
+}
+
+#[inline]
+pub fn book_instance_info(db: &mut Connection, req: RequestBookInstanceInfo) -> ApiResult<ResponseBookInstanceInfo> {
+    info!("book_instance_info IN {:?}", req);
+    let res = db.query_row(
+        "SELECT i.bid, i.status, i.lid, o.due_date FROM lms_instance i \
+        LEFT JOIN lms_occupation o ON o.iid = i.iid AND o.kind = 0 \
+        WHERE i.iid = ?1",
+        [&req.iid.to_string()],
+        |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        }
+    ).optional()?.ok_or_else(|| ApiError::not_found("instance does not exist"))?;
+    let response = ResponseBookInstanceInfo {
         success: true,
         message: "success".to_string(),
         bid: res.0,
         lid: res.2,
         status: res.1,
+        due_date: res.3.unwrap_or_default(),
     };
     info!("book_instance_info OUT {:?}", response);
-    response
-}
\ No newline at end of file
+    Ok(response)
+}
+
+// Open loans are listed with the returned ones, their return date still null.
+const LOANS: &str = "SELECT rowid, uid, iid, date, return_date, lost FROM lms_history \
+    UNION ALL SELECT 0, uid, iid, date, NULL, 0 FROM lms_occupation WHERE kind = 0";
+
+fn history_record(row: &rusqlite::Row, start: usize) -> rusqlite::Result<HistoryRecord> {
+    Ok(HistoryRecord {
+        uid: row.get(start)?,
+        iid: row.get(start + 1)?,
+        date: row.get(start + 2)?,
+        return_date: row.get::<_, Option<String>>(start + 3)?.unwrap_or_default(),
+        book: book_record(row, start + 4)?,
+        lost: row.get(start + 9)?,
+    })
+}
+
+#[inline]
+pub fn user_history(db: &mut Connection, req: RequestUserHistory) -> ApiResult<ResponseUserHistory> {
+    info!("user_history IN {:?}", req);
+    let since = parse_date(db, &req.since, "since")?;
+    let until = parse_date(db, &req.until, "until")?;
+    let limit = page_limit(req.limit);
+    // Loans have no stable key across both tables, so the cursor is an offset.
+    let mut stmt = db.prepare(&format!(
+        "SELECT h.uid, h.iid, h.date, h.return_date, b.bid, b.title, b.author, b.info, b.tid, h.lost \
+        FROM ({LOANS}) h \
+        JOIN lms_instance i ON i.iid = h.iid \
+        JOIN lms_book b ON b.bid = i.bid \
+        WHERE h.uid = ?1 AND (?2 IS NULL OR h.date >= ?2) AND (?3 IS NULL OR h.date < ?3) \
+        ORDER BY h.date, h.return_date IS NULL, h.rowid LIMIT ?4 OFFSET ?5",
+    ))?;
+    let params = rusqlite::params![req.uid, since, until, limit + 1, req.cursor];
+    let mut loans = stmt.query_map(params, |row| history_record(row, 0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let next_cursor = if loans.len() as u64 > limit { req.cursor + limit } else { 0 };
+    loans.truncate(limit as usize);
+    info!("user_history OUT {:?} {}", loans, next_cursor);
+    Ok(ResponseUserHistory {
+        success: true,
+        message: "success".to_string(),
+        loans,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn book_instance_history(db: &mut Connection, req: RequestBookInstanceHistory) -> ApiResult<ResponseBookInstanceHistory> {
+    info!("book_instance_history IN {:?}", req);
+    db.query_row("SELECT 1 FROM lms_instance WHERE iid = ?1", [req.iid], |_| Ok(()))
+        .optional()?
+        .ok_or_else(|| ApiError::not_found("instance does not exist"))?;
+    let since = parse_date(db, &req.since, "since")?;
+    let until = parse_date(db, &req.until, "until")?;
+    let limit = page_limit(req.limit);
+    // Loans and events share no key, so the cursor is an offset into their merged order.
+    let mut stmt = db.prepare(&format!(
+        "SELECT * FROM ( \
+        SELECT 0 AS type, h.rowid AS id, h.date, h.uid, h.iid, h.date, h.return_date, \
+        b.bid, b.title, b.author, b.info, b.tid, h.lost, NULL, NULL, NULL \
+        FROM ({LOANS}) h \
+        JOIN lms_instance i ON i.iid = h.iid \
+        JOIN lms_book b ON b.bid = i.bid \
+        WHERE h.iid = ?1 \
+        UNION ALL \
+        SELECT 1, eid, date, uid, iid, date, due_date, NULL, NULL, NULL, NULL, NULL, kind, fid, note, actor \
+        FROM lms_instance_event WHERE iid = ?1) \
+        WHERE (?2 IS NULL OR date >= ?2) AND (?3 IS NULL OR date < ?3) \
+        ORDER BY date, type, id = 0, id LIMIT ?4 OFFSET ?5",
+    ))?;
+    let params = rusqlite::params![req.iid, since, until, limit + 1, req.cursor];
+    let mut entries = stmt.query_map(params, |row| Ok(match row.get::<_, u64>(0)? {
+        0 => InstanceHistoryEntry::Loan(history_record(row, 3)?),
+        _ => InstanceHistoryEntry::Event(InstanceEvent {
+            eid: row.get(1)?,
+            iid: row.get(4)?,
+            kind: row.get(12)?,
+            date: row.get(5)?,
+            due_date: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+            uid: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+            fid: row.get::<_, Option<u64>>(13)?.unwrap_or(0),
+            note: row.get(14)?,
+            actor: row.get::<_, Option<u64>>(15)?.unwrap_or(0),
+        }),
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let next_cursor = if entries.len() as u64 > limit { req.cursor + limit } else { 0 };
+    entries.truncate(limit as usize);
+    info!("book_instance_history OUT {:?} {}", entries, next_cursor);
+    Ok(ResponseBookInstanceHistory {
+        success: true,
+        message: "success".to_string(),
+        entries,
+        next_cursor,
+    })
+}
+
+fn page_limit(limit: u64) -> u64 {
+    match limit {
+        0 => DEFAULT_PAGE_LIMIT,
+        limit => limit.min(MAX_PAGE_LIMIT),
+    }
+}
+
+// Pages fetch one row past `limit`; its presence means there is a next page.
+fn next_page<T>(mut rows: Vec<T>, limit: u64, cursor: impl Fn(&T) -> u64) -> (Vec<T>, u64) {
+    if rows.len() as u64 <= limit {
+        return (rows, 0);
+    }
+    rows.truncate(limit as usize);
+    let next_cursor = rows.last().map(cursor).unwrap_or(0);
+    (rows, next_cursor)
+}
+
+fn book_record(row: &rusqlite::Row, start: usize) -> rusqlite::Result<BookRecord> {
+    Ok(BookRecord {
+        bid: row.get(start)?,
+        title: row.get(start + 1)?,
+        author: row.get(start + 2)?,
+        info: row.get(start + 3)?,
+        tid: row.get(start + 4)?,
+    })
+}
+
+// Columns: iid, bid, lid, location name, status, occupation kind, date and
+// due date, withdrawn date.
+fn instance_record(row: &rusqlite::Row) -> rusqlite::Result<InstanceRecord> {
+    let occupation = match row.get::<_, Option<u64>>(5)? {
+        Some(kind) => Some(OccupationRecord {
+            kind,
+            date: row.get(6)?,
+            due_date: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+        }),
+        None => None,
+    };
+    Ok(InstanceRecord {
+        iid: row.get(0)?,
+        bid: row.get(1)?,
+        lid: row.get(2)?,
+        location: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+        status: row.get(4)?,
+        occupation,
+        withdrawn_date: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+    })
+}
+
+#[inline]
+pub fn book_search_v2(db: &mut Connection, req: RequestBookSearchV2) -> ApiResult<ResponseBookSearchV2> {
+    info!("book_search_v2 IN {:?}", req);
+    let limit = page_limit(req.limit);
+    let filter = SearchFilter {
+        subject: &req.subject,
+        tag: &req.tag,
+        class_from: &req.class_from,
+        class_to: &req.class_to,
+    };
+    // Relevance order has no stable key, so the cursor is an offset.
+    let mut results = match search_books(db, &req.phrase, &filter, limit as i64 + 1, req.cursor) {
+        Err(err) if is_fts_query_error(&err) => {
+            search_books(db, &plain_query(&req.phrase), &filter, limit as i64 + 1, req.cursor)
+        }
+        results => results,
+    }?;
+    let next_cursor = if results.len() as u64 > limit { req.cursor + limit } else { 0 };
+    results.truncate(limit as usize);
+    info!("book_search_v2 OUT {:?} {}", results, next_cursor);
+    Ok(ResponseBookSearchV2 {
+        success: true,
+        message: "success".to_string(),
+        results,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn book_instance_v2(db: &mut Connection, req: RequestBookInstanceV2) -> ApiResult<ResponseBookInstanceV2> {
+    info!("book_instance_v2 IN {:?}", req);
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(
+        "SELECT i.iid, i.bid, i.lid, l.name, i.status, o.kind, o.date, o.due_date, i.withdrawn_date \
+        FROM lms_instance i \
+        LEFT JOIN lms_location l ON l.lid = i.lid \
+        LEFT JOIN lms_occupation o ON o.iid = i.iid \
+        WHERE i.bid = ?1 AND i.iid > ?2 ORDER BY i.iid LIMIT ?3",
+    )?;
+    let instances = stmt.query_map([req.bid, req.cursor, limit + 1], instance_record)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (instances, next_cursor) = next_page(instances, limit, |instance| instance.iid);
+    info!("book_instance_v2 OUT {:?} {}", instances, next_cursor);
+    Ok(ResponseBookInstanceV2 {
+        success: true,
+        message: "success".to_string(),
+        instances,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn user_borrowed_v2(db: &mut Connection, req: RequestUserBorrowedV2) -> ApiResult<ResponseUserBorrowedV2> {
+    info!("user_borrowed_v2 IN {:?}", req);
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(
+        "SELECT o.iid, o.date, o.due_date, o.renewals, b.bid, b.title, b.author, b.info, b.tid \
+        FROM lms_occupation o \
+        JOIN lms_instance i ON i.iid = o.iid \
+        JOIN lms_book b ON b.bid = i.bid \
+        WHERE o.uid = ?1 AND o.kind = 0 AND o.iid > ?2 ORDER BY o.iid LIMIT ?3",
+    )?;
+    let loans = stmt.query_map([req.uid, req.cursor, limit + 1], |row| Ok(LoanRecord {
+        iid: row.get(0)?,
+        date: row.get(1)?,
+        due_date: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+        renewals: row.get(3)?,
+        book: book_record(row, 4)?,
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let (loans, next_cursor) = next_page(loans, limit, |loan| loan.iid);
+    info!("user_borrowed_v2 OUT {:?} {}", loans, next_cursor);
+    Ok(ResponseUserBorrowedV2 {
+        success: true,
+        message: "success".to_string(),
+        loans,
+        next_cursor,
+    })
+}
+
+#[inline]
+pub fn user_reserved_v2(db: &mut Connection, req: RequestUserReservedV2) -> ApiResult<ResponseUserReservedV2> {
+    info!("user_reserved_v2 IN {:?}", req);
+    let limit = page_limit(req.limit);
+    let mut stmt = db.prepare(
+        "SELECT h.hid, h.uid, h.bid, h.iid, h.status, \
+        CASE h.status WHEN 0 THEN \
+        (SELECT COUNT(*) FROM lms_hold w WHERE w.bid = h.bid AND w.status = 0 AND w.hid <= h.hid) \
+        ELSE 0 END, h.date, h.expiry_date, h.pickup, b.bid, b.title, b.author, b.info, b.tid \
+        FROM lms_hold h JOIN lms_book b ON b.bid = h.bid \
+        WHERE h.uid = ?1 AND h.status IN (0, 1) AND h.hid > ?2 ORDER BY h.hid LIMIT ?3",
+    )?;
+    let holds = stmt.query_map([req.uid, req.cursor, limit + 1], |row| Ok(HoldRecord {
+        hold: Hold {
+            hid: row.get(0)?,
+            uid: row.get(1)?,
+            bid: row.get(2)?,
+            iid: row.get::<_, Option<u64>>(3)?.unwrap_or(0),
+            status: row.get(4)?,
+            position: row.get(5)?,
+            date: row.get(6)?,
+            expiry_date: row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+            pickup: row.get::<_, Option<u64>>(8)?.unwrap_or(0),
+        },
+        book: book_record(row, 9)?,
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let (holds, next_cursor) = next_page(holds, limit, |record| record.hold.hid);
+    info!("user_reserved_v2 OUT {:?} {}", holds, next_cursor);
+    Ok(ResponseUserReservedV2 {
+        success: true,
+        message: "success".to_string(),
+        holds,
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::test_database;
+
+    /// Branch 1 with shelves 2 and 3, and branch 4; copy 1 is recorded on
+    /// shelf 2 and copy 2 on shelf 3.
+    fn library() -> Connection {
+        let db = test_database();
+        db.execute_batch(
+            "INSERT INTO lms_location (name, info, parent, kind) VALUES ('main', '', NULL, 0); \
+            INSERT INTO lms_location (name, info, parent, kind) VALUES ('a', '', 1, 3); \
+            INSERT INTO lms_location (name, info, parent, kind) VALUES ('b', '', 1, 3); \
+            INSERT INTO lms_location (name, info, parent, kind) VALUES ('east', '', NULL, 0); \
+            INSERT INTO lms_book (title, author, info) VALUES ('Dune', 'Frank Herbert', ''); \
+            INSERT INTO lms_instance (bid, lid) VALUES (1, 2); \
+            INSERT INTO lms_instance (bid, lid) VALUES (1, 3);",
+        ).unwrap();
+        db
+    }
+
+    fn scan(db: &mut Connection, vid: u64, lid: u64, iids: &[u64]) {
+        admin_scan_stocktake(db, RequestStocktakeScan { vid, lid, iids: iids.to_vec() }).unwrap();
+    }
+
+    fn report(db: &mut Connection, vid: u64) -> StocktakeReport {
+        admin_stocktake(db, RequestStocktakeReport { vid }).unwrap().report
+    }
+
+    #[test]
+    fn losing_a_borrowed_copy_closes_the_loan() {
+        let mut db = library();
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', ''); \
+            INSERT INTO lms_occupation (uid, iid, date, due_date, kind) VALUES (1, 1, '2026-01-02', '2026-02-01', 0);",
+        ).unwrap();
+        let remark = Remark { actor: 1, note: "" };
+        assert_eq!(mark_lost(&mut db, 1, true, &remark).unwrap().0, 1);
+        mark_found(&mut db, 1, &remark).unwrap();
+        let loans = user_history(&mut db, RequestUserHistory {
+            uid: 1,
+            since: String::new(),
+            until: String::new(),
+            limit: 0,
+            cursor: 0,
+        }).unwrap().loans;
+        assert_eq!(loans.iter().map(|loan| (loan.iid, loan.date.as_str(), loan.lost)).collect::<Vec<_>>(), [(1, "2026-01-02", true)]);
+    }
+
+    #[test]
+    fn lost_and_found_events_name_the_staff_member() {
+        let mut db = library();
+        admin_mark_lost(&mut db, 7, RequestInstanceLost { iid: 1, bill: false, note: String::new() }).unwrap();
+        admin_mark_found(&mut db, 8, RequestInstanceFound { iid: 1, note: String::new() }).unwrap();
+        let entries = book_instance_history(&mut db, RequestBookInstanceHistory {
+            iid: 1,
+            since: String::new(),
+            until: String::new(),
+            limit: 0,
+            cursor: 0,
+        }).unwrap().entries;
+        let actors = entries.iter().filter_map(|entry| match entry {
+            InstanceHistoryEntry::Event(event) => Some((event.kind, event.actor)),
+            InstanceHistoryEntry::Loan(_) => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(actors, [(3, 7), (4, 8)]);
+    }
+
+    /// Alice loses the copy she borrowed and is billed for it.
+    fn lost_loan(db: &mut Connection) -> u64 {
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', ''); \
+            INSERT INTO lms_occupation (uid, iid, date, due_date, kind) VALUES (1, 1, '2026-01-02', '2026-02-01', 0);",
+        ).unwrap();
+        admin_mark_lost(db, 7, RequestInstanceLost { iid: 1, bill: true, note: String::new() }).unwrap().fid
+    }
+
+    fn fines(db: &mut Connection) -> ResponseUserFines {
+        user_fines(db, RequestUserFines { uid: 1 }).unwrap()
+    }
+
+    #[test]
+    fn found_copies_waive_unpaid_replacement_charges() {
+        let mut db = library();
+        lost_loan(&mut db);
+        assert_eq!(fines(&mut db).balance, DEFAULT_FINE_REPLACEMENT);
+        let found = admin_mark_found(&mut db, 8, RequestInstanceFound { iid: 1, note: String::new() }).unwrap();
+        assert_eq!((found.reversed, found.refunded), (DEFAULT_FINE_REPLACEMENT, 0));
+        let fines = fines(&mut db);
+        assert_eq!((fines.balance, fines.credit), (0, 0));
+        assert_eq!(fines.fines.iter().map(|fine| (fine.kind, fine.status)).collect::<Vec<_>>(), [(1, 2)]);
+        assert_eq!(occupation_of(&db, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn found_copies_credit_paid_replacement_charges_once() {
+        let mut db = library();
+        let fid = lost_loan(&mut db);
+        admin_pay_fine(&mut db, RequestFineSettle { fid }).unwrap();
+        let found = admin_mark_found(&mut db, 8, RequestInstanceFound { iid: 1, note: String::new() }).unwrap();
+        assert_eq!((found.reversed, found.refunded), (0, DEFAULT_FINE_REPLACEMENT));
+        let credit = fines(&mut db);
+        assert_eq!((credit.balance, credit.credit), (0, DEFAULT_FINE_REPLACEMENT));
+        assert_eq!(credit.fines.iter().map(|fine| (fine.kind, fine.status)).collect::<Vec<_>>(), [(1, 1)]);
+        admin_mark_lost(&mut db, 7, RequestInstanceLost { iid: 1, bill: false, note: String::new() }).unwrap();
+        let again = admin_mark_found(&mut db, 8, RequestInstanceFound { iid: 1, note: String::new() }).unwrap();
+        assert_eq!((again.reversed, again.refunded), (0, 0));
+        assert_eq!(fines(&mut db).credit, DEFAULT_FINE_REPLACEMENT);
+    }
+
+    fn search(db: &mut Connection, phrase: &str) -> ApiResult<Vec<u64>> {
+        let results = book_search_v2(db, RequestBookSearchV2 {
+            phrase: phrase.to_string(),
+            subject: String::new(),
+            tag: String::new(),
+            class_from: String::new(),
+            class_to: String::new(),
+            limit: 0,
+            cursor: 0,
+        })?;
+        Ok(results.results.iter().map(|result| result.bid).collect())
+    }
+
+    #[test]
+    fn search_retries_malformed_queries_as_plain_words() {
+        let mut db = library();
+        for phrase in ["dune", "dune\"", "(dune", "-dune", "dune:"] {
+            assert_eq!(search(&mut db, phrase).unwrap(), [1], "{phrase}");
+        }
+    }
+
+    #[test]
+    fn search_reports_database_errors() {
+        let mut db = library();
+        db.execute_batch("DROP TABLE lms_book_fts").unwrap();
+        assert!(search(&mut db, "dune").is_err());
+    }
+
+    #[test]
+    fn late_returns_are_fined_at_the_category_rate() {
+        let mut db = library();
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info, cid) VALUES ('alice', 'alice@example.com', '', 1); \
+            INSERT INTO lms_user (username, email, info, cid) VALUES ('bob', 'bob@example.com', '', 2); \
+            UPDATE lms_policy SET fine_daily_rate = 3 WHERE cid = 2 AND tid = 1; \
+            INSERT INTO lms_occupation (uid, iid, date, due_date, kind) VALUES (1, 1, date('now', '-40 days'), date('now', '-4 days'), 0); \
+            INSERT INTO lms_occupation (uid, iid, date, due_date, kind) VALUES (2, 2, date('now', '-40 days'), date('now', '-4 days'), 0);",
+        ).unwrap();
+        let fine = user_return(&mut db, RequestBookReturn { iid: 1, uid: 1 }).unwrap().fine;
+        assert_eq!(fine, 40);
+        let fine = user_return(&mut db, RequestBookReturn { iid: 2, uid: 2 }).unwrap().fine;
+        assert_eq!(fine, 12);
+        let fines = db.query_row("SELECT group_concat(amount) FROM lms_fine ORDER BY fid", [], |row| row.get::<_, String>(0))
+            .unwrap();
+        assert_eq!(fines, "40,12");
+    }
+
+    #[test]
+    fn changing_the_author_moves_the_book_to_the_new_author() {
+        let mut db = test_database();
+        let bid = admin_add(&mut db, RequestBookAdd {
+            title: "Dune".to_string(),
+            author: "Frank Herbert".to_string(),
+            info: String::new(),
+            tid: 1,
+            details: BookDetails::default(),
+        }).unwrap().bid;
+        admin_alter(&mut db, RequestBookAlter {
+            bid,
+            title: "Dune".to_string(),
+            author: "F. Herbert".to_string(),
+            info: String::new(),
+            tid: 1,
+            details: BookDetails::default(),
+        }).unwrap();
+        let by_author = |db: &mut Connection, aid| book_by_author(db, RequestBookByAuthor { aid, limit: 0, cursor: 0 })
+            .unwrap().books.iter().map(|book| book.book.bid).collect::<Vec<u64>>();
+        assert_eq!(by_author(&mut db, 1), Vec::<u64>::new());
+        assert_eq!(by_author(&mut db, 2), [bid]);
+    }
+
+    /// Alice has both copies out; Bob, then Carol, wait for the title.
+    fn queue() -> (Connection, u64, u64) {
+        let mut db = library();
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', ''); \
+            INSERT INTO lms_user (username, email, info) VALUES ('bob', 'bob@example.com', ''); \
+            INSERT INTO lms_user (username, email, info) VALUES ('carol', 'carol@example.com', '');",
+        ).unwrap();
+        user_borrow(&mut db, RequestBookBorrow { uid: 1, iid: 1 }).unwrap();
+        user_borrow(&mut db, RequestBookBorrow { uid: 1, iid: 2 }).unwrap();
+        let reserve = |db: &mut Connection, uid| user_reserve(db, RequestBookReserve { uid, bid: 1, pickup: 0 }).unwrap();
+        let bob = reserve(&mut db, 2);
+        let carol = reserve(&mut db, 3);
+        assert_eq!((bob.position, carol.position), (1, 2));
+        (db, bob.hid, carol.hid)
+    }
+
+    fn holds(db: &mut Connection) -> Vec<(u64, u64, u64, u64)> {
+        book_holds(db, RequestBookHolds { bid: 1 }).unwrap().holds.iter()
+            .map(|hold| (hold.hid, hold.status, hold.iid, hold.position))
+            .collect()
+    }
+
+    #[test]
+    fn returned_copies_go_to_the_first_hold_in_line() {
+        let (mut db, bob, carol) = queue();
+        let returned = user_return(&mut db, RequestBookReturn { iid: 1, uid: 1 }).unwrap();
+        assert_eq!(returned.held_for, 2);
+        assert_eq!(holds(&mut db), [(bob, 1, 1, 0), (carol, 0, 0, 1)]);
+        let taken = user_borrow(&mut db, RequestBookBorrow { uid: 3, iid: 1 });
+        assert!(matches!(taken, Err(ApiError::Invariant(ErrorCode::InstanceOnHold, _))));
+        user_borrow(&mut db, RequestBookBorrow { uid: 2, iid: 1 }).unwrap();
+        assert_eq!(holds(&mut db), [(carol, 0, 0, 1)]);
+    }
+
+    #[test]
+    fn uncollected_holds_expire_to_the_next_in_line() {
+        let (mut db, bob, carol) = queue();
+        user_return(&mut db, RequestBookReturn { iid: 1, uid: 1 }).unwrap();
+        assert_eq!(expired_holds(&db).unwrap(), Vec::<u64>::new());
+        db.execute("UPDATE lms_hold SET expiry_date = date('now', '-1 days') WHERE hid = ?1", [bob]).unwrap();
+        assert_eq!(expired_holds(&db).unwrap(), [bob]);
+        expire_hold(&mut db, RequestHoldCancel { hid: bob }).unwrap();
+        assert_eq!(holds(&mut db), [(carol, 1, 1, 0)]);
+        let status = db.query_row("SELECT status FROM lms_hold WHERE hid = ?1", [bob], |row| row.get::<_, u64>(0)).unwrap();
+        assert_eq!(status, 4);
+        assert!(matches!(expire_hold(&mut db, RequestHoldCancel { hid: carol }), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn cancelling_a_hold_moves_the_queue_up_and_frees_its_copy() {
+        let (mut db, bob, carol) = queue();
+        user_cancel_hold(&mut db, RequestHoldCancel { hid: bob }).unwrap();
+        assert_eq!(holds(&mut db), [(carol, 0, 0, 1)]);
+        user_return(&mut db, RequestBookReturn { iid: 2, uid: 1 }).unwrap();
+        user_cancel_hold(&mut db, RequestHoldCancel { hid: carol }).unwrap();
+        assert_eq!(holds(&mut db), []);
+        assert_eq!(occupation_of(&db, 2).unwrap(), None);
+        let again = user_cancel_hold(&mut db, RequestHoldCancel { hid: carol });
+        assert!(matches!(again, Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn inactive_users_cannot_renew() {
+        let mut db = library();
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info, active) VALUES ('alice', 'alice@example.com', '', 0); \
+            INSERT INTO lms_occupation (uid, iid, date, due_date, kind) VALUES (1, 1, date('now'), date('now', '+7 days'), 0);",
+        ).unwrap();
+        let renewed = user_renew(&mut db, RequestBookRenew { uid: 1, iid: 1 });
+        assert!(matches!(renewed, Err(ApiError::Invariant(ErrorCode::UserInactive, _))));
+    }
+
+    #[test]
+    fn removing_referenced_books_and_users_says_why() {
+        let mut db = library();
+        db.execute_batch(
+            "INSERT INTO lms_book (title, author, info) VALUES ('Emma', 'Jane Austen', ''); \
+            INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', ''); \
+            INSERT INTO lms_user (username, email, info) VALUES ('bob', 'bob@example.com', ''); \
+            INSERT INTO lms_history (uid, iid, date, return_date) VALUES (1, 1, '2026-01-02', '2026-01-09');",
+        ).unwrap();
+        let removed = admin_remove(&mut db, RequestBookRemove { bid: 1 });
+        assert!(matches!(removed, Err(ApiError::Conflict(message)) if message.contains("instances")));
+        admin_remove(&mut db, RequestBookRemove { bid: 2 }).unwrap();
+        let removed = admin_remove(&mut db, RequestBookRemove { bid: 2 });
+        assert!(matches!(removed, Err(ApiError::NotFound(_))));
+        let removed = user_unregister(&mut db, RequestUserUnregister { uid: 1 });
+        assert!(matches!(removed, Err(ApiError::Conflict(message)) if message.contains("loan history")));
+        user_unregister(&mut db, RequestUserUnregister { uid: 2 }).unwrap();
+    }
+
+    #[test]
+    fn removing_instances_in_use_says_why() {
+        let mut db = library();
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', ''); \
+            INSERT INTO lms_occupation (uid, iid, date, due_date, kind) VALUES (1, 1, date('now'), date('now', '+7 days'), 0);",
+        ).unwrap();
+        let remove = |db: &mut Connection, iid| admin_remove_instance(db, RequestBookRemoveInstance { iid });
+        assert!(matches!(remove(&mut db, 1), Err(ApiError::Invariant(ErrorCode::InstanceOccupied, _))));
+        assert!(matches!(remove(&mut db, 9), Err(ApiError::Invariant(ErrorCode::InstanceNotFound, _))));
+        user_return(&mut db, RequestBookReturn { iid: 1, uid: 1 }).unwrap();
+        assert!(matches!(remove(&mut db, 1), Err(ApiError::Conflict(message)) if message.contains("loan history")));
+        let xid = admin_request_transfer(&mut db, RequestTransferRequest { iid: 2, destination: 4, note: String::new() })
+            .unwrap().xid;
+        assert!(matches!(remove(&mut db, 2), Err(ApiError::Invariant(ErrorCode::InstanceInTransit, _))));
+        admin_cancel_transfer(&mut db, RequestTransferCancel { xid }).unwrap();
+        assert!(matches!(remove(&mut db, 2), Err(ApiError::Conflict(message)) if message.contains("transfers")));
+        db.execute("INSERT INTO lms_instance (bid, lid) VALUES (1, 2)", []).unwrap();
+        remove(&mut db, 3).unwrap();
+    }
+
+    #[test]
+    fn removing_a_location_keeps_transfer_history() {
+        let mut db = library();
+        let xid = admin_request_transfer(&mut db, RequestTransferRequest { iid: 1, destination: 4, note: String::new() })
+            .unwrap().xid;
+        admin_cancel_transfer(&mut db, RequestTransferCancel { xid }).unwrap();
+        let removed = admin_remove_location(&mut db, RequestLocationRemove { lid: 4, target: 1 });
+        assert!(matches!(removed, Err(ApiError::Conflict(_))));
+        admin_remove_location(&mut db, RequestLocationRemove { lid: 3, target: 2 }).unwrap();
+        let lid = db.query_row("SELECT lid FROM lms_instance WHERE iid = 2", [], |row| row.get::<_, u64>(0)).unwrap();
+        assert_eq!(lid, 2);
+        let (origin, destination) = db.query_row(
+            "SELECT origin, destination FROM lms_transfer WHERE xid = ?1",
+            [xid],
+            |row| Ok((row.get::<_, u64>(0)?, row.get::<_, u64>(1)?)),
+        ).unwrap();
+        assert_eq!((origin, destination), (2, 4));
+    }
+
+    fn transfer(db: &mut Connection, xid: u64) -> Transfer {
+        db.query_row(&format!("SELECT {TRANSFER_COLUMNS} FROM lms_transfer WHERE xid = ?1"), [xid], transfer_record).unwrap()
+    }
+
+    #[test]
+    fn received_transfers_move_the_copy() {
+        let mut db = library();
+        let xid = admin_request_transfer(&mut db, RequestTransferRequest { iid: 1, destination: 4, note: String::new() })
+            .unwrap().xid;
+        assert_eq!(occupation_of(&db, 1).unwrap().map(|(_, kind)| kind), Some(4));
+        let early = admin_receive_transfer(&mut db, RequestTransferReceive { xid });
+        assert!(matches!(early, Err(ApiError::Conflict(_))));
+        admin_dispatch_transfer(&mut db, RequestTransferDispatch { xid }).unwrap();
+        let again = admin_dispatch_transfer(&mut db, RequestTransferDispatch { xid });
+        assert!(matches!(again, Err(ApiError::Conflict(_))));
+        assert_eq!(admin_receive_transfer(&mut db, RequestTransferReceive { xid }).unwrap().held_for, 0);
+        let moved = transfer(&mut db, xid);
+        assert_eq!((moved.origin, moved.destination, moved.status), (2, 4, 2));
+        let lid = db.query_row("SELECT lid FROM lms_instance WHERE iid = 1", [], |row| row.get::<_, u64>(0)).unwrap();
+        assert_eq!(lid, 4);
+        assert_eq!(occupation_of(&db, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn cancelled_transfers_leave_the_copy_where_it_was() {
+        let mut db = library();
+        let xid = admin_request_transfer(&mut db, RequestTransferRequest { iid: 2, destination: 4, note: String::new() })
+            .unwrap().xid;
+        admin_dispatch_transfer(&mut db, RequestTransferDispatch { xid }).unwrap();
+        admin_cancel_transfer(&mut db, RequestTransferCancel { xid }).unwrap();
+        assert_eq!(transfer(&mut db, xid).status, 3);
+        let lid = db.query_row("SELECT lid FROM lms_instance WHERE iid = 2", [], |row| row.get::<_, u64>(0)).unwrap();
+        assert_eq!(lid, 3);
+        assert_eq!(occupation_of(&db, 2).unwrap(), None);
+        let received = admin_receive_transfer(&mut db, RequestTransferReceive { xid });
+        assert!(matches!(received, Err(ApiError::Conflict(_))));
+    }
+
+    #[test]
+    fn only_live_holds_keep_their_pickup_branch() {
+        let mut db = library();
+        db.execute_batch(
+            "INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', ''); \
+            INSERT INTO lms_hold (uid, bid, date, status, pickup) VALUES (1, 1, '2026-01-02', 0, 4); \
+            INSERT INTO lms_hold (uid, bid, date, status, pickup) VALUES (1, 1, '2026-01-01', 3, 4);",
+        ).unwrap();
+        let removed = admin_remove_location(&mut db, RequestLocationRemove { lid: 4, target: 1 });
+        assert!(matches!(removed, Err(ApiError::Conflict(_))));
+        db.execute("UPDATE lms_hold SET status = 3 WHERE hid = 1", []).unwrap();
+        admin_remove_location(&mut db, RequestLocationRemove { lid: 4, target: 1 }).unwrap();
+        let pickups = db.query_row("SELECT COUNT(*) FROM lms_hold WHERE pickup IS NOT NULL", [], |row| row.get::<_, u64>(0))
+            .unwrap();
+        assert_eq!(pickups, 0);
+    }
+
+    #[test]
+    fn stocktake_accepts_copies_scanned_above_their_shelf() {
+        let mut db = library();
+        let vid = admin_open_stocktake(&mut db, RequestStocktakeOpen { lid: 1, note: String::new() }).unwrap().vid;
+        scan(&mut db, vid, 0, &[1]);
+        scan(&mut db, vid, 3, &[2]);
+        let report = report(&mut db, vid);
+        assert!(report.missing.is_empty());
+        assert!(report.misplaced.is_empty());
+        assert!(report.borrowed.is_empty());
+    }
+
+    #[test]
+    fn stocktake_reports_copies_on_the_wrong_shelf_and_missing_ones() {
+        let mut db = library();
+        let vid = admin_open_stocktake(&mut db, RequestStocktakeOpen { lid: 1, note: String::new() }).unwrap().vid;
+        scan(&mut db, vid, 3, &[1]);
+        let report = report(&mut db, vid);
+        assert_eq!(report.misplaced.iter().map(|finding| (finding.iid, finding.lid, finding.found)).collect::<Vec<_>>(), [(1, 2, 3)]);
+        assert_eq!(report.missing.iter().map(|finding| finding.iid).collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn closing_a_stocktake_can_mark_missing_copies_lost() {
+        let mut db = library();
+        let vid = admin_open_stocktake(&mut db, RequestStocktakeOpen { lid: 1, note: String::new() }).unwrap().vid;
+        scan(&mut db, vid, 2, &[1]);
+        let closed = admin_close_stocktake(&mut db, 1, RequestStocktakeClose { vid, mark_lost: true }).unwrap().report;
+        assert_eq!(closed.missing.iter().map(|finding| (finding.iid, finding.lost)).collect::<Vec<_>>(), [(2, true)]);
+        assert_eq!(occupation_of(&db, 2).unwrap(), Some((None, 3)));
+        assert_eq!(report(&mut db, vid), closed);
+    }
+}
@@ -0,0 +1,392 @@
+use log::info;
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+use serde_json::{Map, Value};
+use crate::model::*;
+use crate::server::error::ApiResult;
+
+/// Kinds of records the audit log tracks, named by the table row they snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    User,
+    Book,
+    Instance,
+    Loan,
+    Hold,
+    Fine,
+    Category,
+    ItemType,
+    Location,
+    Policy,
+    Author,
+    Series,
+    Transfer,
+    Stocktake,
+}
+
+impl Entity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Entity::User => "user",
+            Entity::Book => "book",
+            Entity::Instance => "instance",
+            Entity::Loan => "loan",
+            Entity::Hold => "hold",
+            Entity::Fine => "fine",
+            Entity::Category => "category",
+            Entity::ItemType => "item_type",
+            Entity::Location => "location",
+            Entity::Policy => "policy",
+            Entity::Author => "author",
+            Entity::Series => "series",
+            Entity::Transfer => "transfer",
+            Entity::Stocktake => "stocktake",
+        }
+    }
+
+    pub fn from_str(entity: &str) -> Option<Entity> {
+        [Entity::User, Entity::Book, Entity::Instance, Entity::Loan, Entity::Hold, Entity::Fine,
+            Entity::Category, Entity::ItemType, Entity::Location, Entity::Policy, Entity::Author,
+            Entity::Series, Entity::Transfer, Entity::Stocktake]
+            .into_iter()
+            .find(|candidate| candidate.as_str() == entity)
+    }
+
+    fn select(self) -> &'static str {
+        match self {
+            Entity::User => "SELECT * FROM lms_user WHERE uid = ?1",
+            Entity::Book => "SELECT * FROM lms_book WHERE bid = ?1",
+            Entity::Instance => "SELECT * FROM lms_instance WHERE iid = ?1",
+            Entity::Loan => "SELECT * FROM lms_occupation WHERE iid = ?1",
+            Entity::Hold => "SELECT * FROM lms_hold WHERE hid = ?1",
+            Entity::Fine => "SELECT * FROM lms_fine WHERE fid = ?1",
+            Entity::Category => "SELECT * FROM lms_category WHERE cid = ?1",
+            Entity::ItemType => "SELECT * FROM lms_item_type WHERE tid = ?1",
+            Entity::Location => "SELECT * FROM lms_location WHERE lid = ?1",
+            // A category's policy is every row it has, one per item type.
+            Entity::Policy => "SELECT * FROM lms_policy WHERE cid = ?1 ORDER BY tid",
+            Entity::Author => "SELECT * FROM lms_author WHERE aid = ?1",
+            Entity::Series => "SELECT * FROM lms_series WHERE sid = ?1",
+            Entity::Transfer => "SELECT * FROM lms_transfer WHERE xid = ?1",
+            Entity::Stocktake => "SELECT * FROM lms_stocktake WHERE vid = ?1",
+        }
+    }
+
+    /// Rows of other tables that belong to the record, listed under these keys
+    /// of its snapshot, so edits that only touch them still show a change.
+    fn parts(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Entity::Book => &[
+                ("contributors", "SELECT aid, role, position FROM lms_book_author WHERE bid = ?1 \
+                    ORDER BY position, role, aid"),
+                ("series", "SELECT sid, volume FROM lms_book_series WHERE bid = ?1"),
+                ("subjects", "SELECT subject FROM lms_book_subject WHERE bid = ?1 ORDER BY subject"),
+                ("tags", "SELECT tag FROM lms_book_tag WHERE bid = ?1 ORDER BY tag"),
+            ],
+            Entity::Author => &[
+                ("books", "SELECT bid, role, position FROM lms_book_author WHERE aid = ?1 ORDER BY bid, role"),
+            ],
+            _ => &[],
+        }
+    }
+
+    /// The target's current row as a JSON object, or `null` when it does not exist.
+    fn snapshot(self, db: &Connection, id: u64) -> rusqlite::Result<Value> {
+        let mut rows = rows(db, self.select(), id)?;
+        Ok(match self {
+            Entity::Policy if rows.is_empty() => Value::Null,
+            Entity::Policy => Value::Array(rows),
+            _ => match rows.pop() {
+                Some(Value::Object(mut object)) => {
+                    for (key, select) in self.parts() {
+                        object.insert(key.to_string(), Value::Array(self::rows(db, select, id)?));
+                    }
+                    Value::Object(object)
+                }
+                _ => Value::Null,
+            },
+        })
+    }
+}
+
+fn rows(db: &Connection, select: &str, id: u64) -> rusqlite::Result<Vec<Value>> {
+    let mut stmt = db.prepare(select)?;
+    let columns = stmt.column_names().into_iter().map(String::from).collect::<Vec<_>>();
+    let rows = stmt.query_map([id], |row| {
+        let mut object = Map::new();
+        for (index, column) in columns.iter().enumerate() {
+            let value = match row.get_ref(index)? {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(value) => value.into(),
+                ValueRef::Real(value) => value.into(),
+                ValueRef::Text(value) | ValueRef::Blob(value) => String::from_utf8_lossy(value).into(),
+            };
+            object.insert(column.clone(), value);
+        }
+        Ok(Value::Object(object))
+    })?.collect();
+    rows
+}
+
+/// The record a mutating request acts on. `id` is `None` when the request
+/// creates it; the response then names the new record.
+pub struct Target {
+    pub entity: Entity,
+    pub id: Option<u64>,
+    /// A second record the request folds into `id` and removes. Both are
+    /// snapshotted, as an `[id, merged]` pair.
+    pub merged: Option<u64>,
+}
+
+pub trait Audited {
+    /// `None` for requests that are not worth auditing.
+    fn target(&self) -> Option<Target>;
+}
+
+pub trait Created {
+    fn created(&self) -> Option<u64> {
+        None
+    }
+}
+
+macro_rules! audit_target {
+    ($entity:expr, $id:ident: $($request:ty),*) => {
+        $(impl Audited for $request {
+            fn target(&self) -> Option<Target> {
+                Some(Target { entity: $entity, id: Some(self.$id), merged: None })
+            }
+        })*
+    };
+}
+
+macro_rules! audit_create {
+    ($entity:expr; $($request:ty),*) => {
+        $(impl Audited for $request {
+            fn target(&self) -> Option<Target> {
+                Some(Target { entity: $entity, id: None, merged: None })
+            }
+        })*
+    };
+}
+
+macro_rules! created {
+    ($($response:ty => $id:ident),*) => {
+        $(impl Created for $response {
+            fn created(&self) -> Option<u64> {
+                Some(self.$id)
+            }
+        })*
+    };
+    ($($response:ty),*) => {
+        $(impl Created for $response {})*
+    };
+}
+
+audit_target!(Entity::User, uid: RequestUserUnregister, RequestUserAlter, RequestUserCategory,
+    RequestRoleGrant, RequestUserActive, RequestUserPassword);
+audit_target!(Entity::Book, bid: RequestBookRemove, RequestBookAlter, RequestBookContributors,
+    RequestBookSeries);
+audit_target!(Entity::Instance, iid: RequestBookRemoveInstance, RequestInstanceWithdraw);
+audit_target!(Entity::Loan, iid: RequestBookBorrow, RequestBookReturn, RequestBookRenew,
+    RequestInstanceOccupy, RequestInstanceRelease, RequestInstanceRepair, RequestInstanceRepaired,
+    RequestInstanceLost, RequestInstanceFound);
+audit_target!(Entity::Hold, hid: RequestHoldCancel);
+audit_target!(Entity::Fine, fid: RequestFineSettle);
+audit_target!(Entity::Location, lid: RequestLocationRemove, RequestLocationAlter);
+audit_target!(Entity::Policy, cid: RequestPolicySet);
+audit_target!(Entity::Author, aid: RequestAuthorRename);
+audit_target!(Entity::Transfer, xid: RequestTransferDispatch, RequestTransferReceive, RequestTransferCancel);
+audit_target!(Entity::Stocktake, vid: RequestStocktakeScan, RequestStocktakeClose);
+audit_create!(Entity::User; RequestUserRegister);
+audit_create!(Entity::Book; RequestBookAdd);
+audit_create!(Entity::Instance; RequestBookAddInstance);
+audit_create!(Entity::Hold; RequestBookReserve);
+audit_create!(Entity::Fine; RequestFineAdd);
+audit_create!(Entity::Category; RequestCategoryAdd);
+audit_create!(Entity::ItemType; RequestItemTypeAdd);
+audit_create!(Entity::Location; RequestLocationAdd);
+audit_create!(Entity::Author; RequestAuthorAdd);
+audit_create!(Entity::Series; RequestSeriesAdd);
+audit_create!(Entity::Transfer; RequestTransferRequest);
+audit_create!(Entity::Stocktake; RequestStocktakeOpen);
+
+impl Audited for RequestAuthorMerge {
+    fn target(&self) -> Option<Target> {
+        Some(Target { entity: Entity::Author, id: Some(self.aid), merged: Some(self.from) })
+    }
+}
+
+/// A request about the caller's own account, which does not name the user.
+pub struct OwnAccount<Req>(pub u64, pub Req);
+
+impl<Req> Audited for OwnAccount<Req> {
+    fn target(&self) -> Option<Target> {
+        Some(Target { entity: Entity::User, id: Some(self.0), merged: None })
+    }
+}
+
+impl Audited for RequestAuthLogin {
+    fn target(&self) -> Option<Target> {
+        None
+    }
+}
+
+created!(ResponseUserRegister => uid, ResponseBookAdd => bid, ResponseBookAddInstance => iid,
+    ResponseBookReserve => hid, ResponseFineAdd => fid, ResponseCategoryAdd => cid,
+    ResponseItemTypeAdd => tid, ResponseLocationAdd => lid, ResponseAuthorAdd => aid,
+    ResponseSeriesAdd => sid, ResponseTransferRequest => xid, ResponseStocktakeOpen => vid);
+created!(ResponseUserUnregister, ResponseUserAlter, ResponseUserCategory, ResponseRoleGrant,
+    ResponseUserActive, ResponseUserPassword,
+    ResponseBookRemove, ResponseBookAlter, ResponseBookRemoveInstance, ResponseBookBorrow,
+    ResponseBookReturn, ResponseBookRenew, ResponseInstanceOccupy, ResponseInstanceRelease,
+    ResponseInstanceRepair, ResponseInstanceRepaired, ResponseInstanceLost, ResponseInstanceFound,
+    ResponseInstanceWithdraw, ResponseHoldCancel, ResponseFineSettle, ResponseLocationRemove, ResponseLocationAlter,
+    ResponsePolicySet, ResponseAuthLogin, ResponseAuthLogout, ResponseAuthPassword,
+    ResponseAuthorRename, ResponseAuthorMerge, ResponseBookContributors,
+    ResponseBookSeries, ResponseTransferDispatch, ResponseTransferReceive, ResponseTransferCancel,
+    ResponseStocktakeScan, ResponseStocktakeClose);
+
+/// Runs a mutating `handler` and appends its audit entry in one transaction,
+/// so a change is never committed without its record. `endpoint` is the
+/// handler name, e.g. `admin_remove`, logged as `admin/remove`.
+pub fn audited<Req, Res, F>(
+    db: &mut Connection,
+    actor: Option<u64>,
+    endpoint: &str,
+    req: Req,
+    handler: F,
+) -> ApiResult<Res>
+where
+    Req: Audited,
+    Res: Created,
+    F: FnOnce(&mut Connection, Req) -> ApiResult<Res>,
+{
+    let target = match req.target() {
+        Some(target) => target,
+        None => return handler(db, req),
+    };
+    // A handler that panicked may have left its transaction open.
+    if !db.is_autocommit() {
+        db.execute_batch("ROLLBACK")?;
+    }
+    db.execute_batch("BEGIN IMMEDIATE")?;
+    let res = record(db, actor, endpoint, target, req, handler)
+        .and_then(|res| Ok(db.execute_batch("COMMIT").map(|_| res)?));
+    if res.is_err() && !db.is_autocommit() {
+        db.execute_batch("ROLLBACK")?;
+    }
+    res
+}
+
+fn record<Req, Res, F>(
+    db: &mut Connection,
+    actor: Option<u64>,
+    endpoint: &str,
+    target: Target,
+    req: Req,
+    handler: F,
+) -> ApiResult<Res>
+where
+    Res: Created,
+    F: FnOnce(&mut Connection, Req) -> ApiResult<Res>,
+{
+    let snapshot = |db: &Connection, id: Option<u64>| -> rusqlite::Result<Value> {
+        let value = match id {
+            Some(id) => target.entity.snapshot(db, id)?,
+            None => return Ok(Value::Null),
+        };
+        Ok(match target.merged {
+            Some(merged) => Value::Array(vec![value, target.entity.snapshot(db, merged)?]),
+            None => value,
+        })
+    };
+    let before = snapshot(db, target.id)?;
+    let res = handler(db, req)?;
+    let id = target.id.or_else(|| res.created());
+    let after = snapshot(db, id)?;
+    let json = |value: Value| if value.is_null() { None } else { Some(value.to_string()) };
+    let endpoint = endpoint.replacen('_', "/", 1);
+    db.execute(
+        "INSERT INTO lms_audit (actor, endpoint, entity, target, before, after, date) \
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, datetime('now'))",
+        rusqlite::params![actor, endpoint, target.entity.as_str(), id, json(before), json(after)],
+    )?;
+    info!("audit {} {:?} {} {:?}", endpoint, actor, target.entity.as_str(), id);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::test_database;
+    use crate::server::api::{admin_merge_authors, admin_set_contributors, admin_set_password};
+    use crate::server::auth::{auth_password, Principal};
+    use crate::server::role::Role;
+
+    fn last_entry(db: &Connection) -> (Value, Value) {
+        db.query_row("SELECT before, after FROM lms_audit ORDER BY rowid DESC LIMIT 1", [], |row| {
+            let json = |index| row.get::<_, Option<String>>(index)
+                .map(|value| value.map_or(Value::Null, |value| serde_json::from_str(&value).unwrap()));
+            Ok((json(0)?, json(1)?))
+        }).unwrap()
+    }
+
+    fn library() -> Connection {
+        let db = test_database();
+        db.execute_batch(
+            "INSERT INTO lms_book (title, author, info) VALUES ('Dune', 'Frank Herbert', ''); \
+            INSERT INTO lms_author (name) VALUES ('Frank Herbert'); \
+            INSERT INTO lms_author (name) VALUES ('F. Herbert'); \
+            INSERT INTO lms_book_author (bid, aid) VALUES (1, 2);",
+        ).unwrap();
+        db
+    }
+
+    #[test]
+    fn book_entries_show_contributor_changes() {
+        let mut db = library();
+        let req = RequestBookContributors {
+            bid: 1,
+            contributors: vec![Contributor { aid: 1, name: String::new(), role: 0 }],
+        };
+        audited(&mut db, Some(1), "admin_set_contributors", req, admin_set_contributors).unwrap();
+        let (before, after) = last_entry(&db);
+        assert_eq!(before["title"], after["title"]);
+        assert_eq!(before["contributors"][0]["aid"], 2);
+        assert_eq!(after["contributors"][0]["aid"], 1);
+    }
+
+    #[test]
+    fn merges_keep_the_removed_author() {
+        let mut db = library();
+        audited(&mut db, Some(1), "admin_merge_authors", RequestAuthorMerge { aid: 1, from: 2 }, admin_merge_authors)
+            .unwrap();
+        let (before, after) = last_entry(&db);
+        assert_eq!(before[1]["name"], "F. Herbert");
+        assert_eq!(before[1]["books"][0]["bid"], 1);
+        assert_eq!(after[0]["books"][0]["bid"], 1);
+        assert_eq!(after[1], Value::Null);
+    }
+
+    #[test]
+    fn password_changes_are_logged_against_the_caller() {
+        let mut db = test_database();
+        db.execute("INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', '')", [])
+            .unwrap();
+        admin_set_password(&mut db, RequestUserPassword { uid: 1, password: "correct horse".to_string() }).unwrap();
+        let principal = Principal { uid: 1, role: Role::Patron, token: String::new() };
+        let req = RequestAuthPassword {
+            current_password: "correct horse".to_string(),
+            password: "battery staple".to_string(),
+        };
+        audited(&mut db, Some(1), "auth_password", OwnAccount(1, req),
+            |db, OwnAccount(_, req)| auth_password(db, principal, req)).unwrap();
+        let entry = db.query_row("SELECT actor, endpoint, entity, target FROM lms_audit", [], |row| Ok((
+            row.get::<_, u64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, u64>(3)?,
+        ))).unwrap();
+        assert_eq!(entry, (1, "auth/password".to_string(), "user".to_string(), 1));
+    }
+}
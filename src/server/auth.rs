@@ -0,0 +1,255 @@
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::SaltString;
+use log::{error, info};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rusqlite::{Connection, OptionalExtension};
+use warp::{Filter, Rejection};
+use crate::model::*;
+use crate::server::error::{ApiError, ApiResult};
+use crate::server::policy::policy_u64;
+use crate::server::pool::Pool;
+use crate::server::role::Role;
+use crate::utils::*;
+
+const DEFAULT_SESSION_HOURS: u64 = 24;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub uid: u64,
+    pub role: Role,
+    pub token: String,
+}
+
+fn hash_password(password: &str) -> ApiResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| {
+            error!("password hashing failed: {}", err);
+            ApiError::Internal
+        })
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn set_password(db: &Connection, uid: u64, password: &str) -> ApiResult<()> {
+    let hash = hash_password(password)?;
+    db.execute(
+        "INSERT OR REPLACE INTO lms_credential (uid, hash) VALUES (?1, ?2)",
+        rusqlite::params![uid, hash],
+    )?;
+    Ok(())
+}
+
+fn session_principal(db: &Connection, token: &str) -> ApiResult<Option<Principal>> {
+    let session = db.query_row(
+        "SELECT s.uid, u.role FROM lms_session s JOIN lms_user u ON u.uid = s.uid \
+        WHERE s.token = ?1 AND s.expires > datetime('now') AND u.active = 1",
+        [token],
+        |row| Ok((row.get(0)?, row.get::<_, u64>(1)?)),
+    ).optional()?;
+    Ok(session.and_then(|(uid, role)| Some(Principal {
+        uid,
+        role: Role::from_u64(role)?,
+        token: token.to_string(),
+    })))
+}
+
+/// Extracts the caller from an `Authorization: Bearer` header, rejecting
+/// requests without a live session.
+pub fn authenticated(pool: Pool) -> impl Filter<Extract = (Principal,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let pool = pool.clone();
+            async move {
+                let token = header.as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .map(|token| token.to_string());
+                let principal = match token {
+                    Some(token) => pool.read(move |db| session_principal(db, &token)).await
+                        .map_err(warp::reject::custom)?,
+                    None => None,
+                };
+                principal.ok_or_else(|| warp::reject::custom(ApiError::unauthorized("authentication required")))
+            }
+        })
+}
+
+#[inline]
+pub fn auth_login(db: &mut Connection, req: RequestAuthLogin) -> ApiResult<ResponseAuthLogin> {
+    info!("auth_login IN {:?}", req);
+    let (phrase, query) = if req.username.starts_with(':') {
+        (&req.username[1..], "SELECT c.uid, c.hash, u.role, u.active FROM lms_credential c \
+            JOIN lms_user u ON u.uid = c.uid WHERE u.email = ?1")
+    } else {
+        (req.username.as_str(), "SELECT c.uid, c.hash, u.role, u.active FROM lms_credential c \
+            JOIN lms_user u ON u.uid = c.uid WHERE u.username = ?1")
+    };
+    let candidates = db.prepare(query)?
+        .query_map([phrase], |row| Ok((
+            row.get::<_, u64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, u64>(2)?,
+            row.get::<_, bool>(3)?,
+        )))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (uid, role, active) = candidates.iter()
+        .find(|(_, hash, _, _)| verify_password(&req.password, hash))
+        .map(|(uid, _, role, active)| (*uid, *role, *active))
+        .ok_or_else(|| ApiError::unauthorized("invalid username or password"))?;
+    if !active {
+        return Err(ApiError::invariant(ErrorCode::UserInactive, "user is inactive"));
+    }
+    let hours = policy_u64(db, "session_hours", DEFAULT_SESSION_HOURS);
+    let token = new_token();
+    db.execute("DELETE FROM lms_session WHERE expires <= datetime('now')", [])?;
+    let expires = db.query_row(
+        "INSERT INTO lms_session (token, uid, created, expires) \
+        VALUES (?1, ?2, datetime('now'), datetime('now', ?3)) \
+        RETURNING expires",
+        [&token, &uid.to_string(), &format!("+{hours} hours")],
+        |row| row.get(0),
+    )?;
+    info!("auth_login OUT {} {}", uid, expires);
+    Ok(ResponseAuthLogin {
+        success: true,
+        message: "success".to_string(),
+        uid,
+        role,
+        token,
+        expires,
+    })
+}
+
+#[inline]
+pub fn auth_logout(db: &mut Connection, principal: Principal, req: RequestAuthLogout) -> ApiResult<ResponseAuthLogout> {
+    info!("auth_logout IN {} {:?}", principal.uid, req);
+    db.execute(
+        "DELETE FROM lms_session WHERE token = ?1",
+        [&principal.token],
+    )?;
+    info!("auth_logout OUT {}", principal.uid);
+    Ok(ResponseAuthLogout {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[inline]
+pub fn auth_password(db: &mut Connection, principal: Principal, req: RequestAuthPassword) -> ApiResult<ResponseAuthPassword> {
+    info!("auth_password IN {} {:?}", principal.uid, req);
+    if !is_password_legit(&req.password) {
+        return Err(ApiError::validation("password is not legit"));
+    }
+    let hash = db.query_row(
+        "SELECT hash FROM lms_credential WHERE uid = ?1",
+        [principal.uid],
+        |row| row.get::<_, String>(0),
+    ).optional()?;
+    if !hash.is_some_and(|hash| verify_password(&req.current_password, &hash)) {
+        return Err(ApiError::unauthorized("current password is incorrect"));
+    }
+    set_password(db, principal.uid, &req.password)?;
+    // Changing the password signs out every other session of the user.
+    db.execute(
+        "DELETE FROM lms_session WHERE uid = ?1 AND token != ?2",
+        [&principal.uid.to_string(), &principal.token],
+    )?;
+    info!("auth_password OUT {}", principal.uid);
+    Ok(ResponseAuthPassword {
+        success: true,
+        message: "success".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::test_database;
+    use crate::server::api::{admin_set_active, admin_set_password};
+
+    /// A user with no credential, as migrated from dbv 5, given one by staff.
+    fn account() -> Connection {
+        let mut db = test_database();
+        db.execute("INSERT INTO lms_user (username, email, info) VALUES ('alice', 'alice@example.com', '')", [])
+            .unwrap();
+        admin_set_password(&mut db, RequestUserPassword { uid: 1, password: "correct horse".to_string() }).unwrap();
+        db
+    }
+
+    fn login(db: &mut Connection, username: &str, password: &str) -> ApiResult<Principal> {
+        let response = auth_login(db, RequestAuthLogin {
+            username: username.to_string(),
+            password: password.to_string(),
+        })?;
+        Ok(Principal { uid: response.uid, role: Role::from_u64(response.role).unwrap(), token: response.token })
+    }
+
+    #[test]
+    fn users_sign_in_by_username_or_email() {
+        let mut db = account();
+        let principal = login(&mut db, "alice", "correct horse").unwrap();
+        assert_eq!(session_principal(&db, &principal.token).unwrap(), Some(principal));
+        assert!(login(&mut db, ":alice@example.com", "correct horse").is_ok());
+        assert!(matches!(login(&mut db, "alice", "wrong horse"), Err(ApiError::Unauthorized(_))));
+        assert!(matches!(login(&mut db, "bob", "correct horse"), Err(ApiError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn unknown_expired_and_logged_out_tokens_are_refused() {
+        let mut db = account();
+        assert_eq!(session_principal(&db, "not a token").unwrap(), None);
+        let principal = login(&mut db, "alice", "correct horse").unwrap();
+        db.execute("UPDATE lms_session SET expires = datetime('now', '-1 minutes')", []).unwrap();
+        assert_eq!(session_principal(&db, &principal.token).unwrap(), None);
+        let principal = login(&mut db, "alice", "correct horse").unwrap();
+        auth_logout(&mut db, principal.clone(), RequestAuthLogout {}).unwrap();
+        assert_eq!(session_principal(&db, &principal.token).unwrap(), None);
+    }
+
+    #[test]
+    fn changing_the_password_needs_the_current_one_and_ends_other_sessions() {
+        let mut db = account();
+        let other = login(&mut db, "alice", "correct horse").unwrap();
+        let principal = login(&mut db, "alice", "correct horse").unwrap();
+        let change = |current: &str| RequestAuthPassword {
+            current_password: current.to_string(),
+            password: "battery staple".to_string(),
+        };
+        let changed = auth_password(&mut db, principal.clone(), change("wrong horse"));
+        assert!(matches!(changed, Err(ApiError::Unauthorized(_))));
+        assert!(session_principal(&db, &other.token).unwrap().is_some());
+        auth_password(&mut db, principal.clone(), change("correct horse")).unwrap();
+        assert_eq!(session_principal(&db, &other.token).unwrap(), None);
+        assert!(session_principal(&db, &principal.token).unwrap().is_some());
+        assert!(login(&mut db, "alice", "correct horse").is_err());
+        assert!(login(&mut db, "alice", "battery staple").is_ok());
+    }
+
+    #[test]
+    fn inactive_users_cannot_sign_in_and_lose_their_sessions() {
+        let mut db = account();
+        let principal = login(&mut db, "alice", "correct horse").unwrap();
+        db.execute("UPDATE lms_user SET active = 0", []).unwrap();
+        assert_eq!(session_principal(&db, &principal.token).unwrap(), None);
+        db.execute("UPDATE lms_user SET active = 1", []).unwrap();
+        admin_set_active(&mut db, RequestUserActive { uid: 1, active: false }).unwrap();
+        let sessions = db.query_row("SELECT COUNT(*) FROM lms_session", [], |row| row.get::<_, u64>(0)).unwrap();
+        assert_eq!(sessions, 0);
+        let refused = login(&mut db, "alice", "correct horse");
+        assert!(matches!(refused, Err(ApiError::Invariant(ErrorCode::UserInactive, _))));
+    }
+}
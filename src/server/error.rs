@@ -0,0 +1,158 @@
+use std::convert::Infallible;
+use log::{error, info};
+use rusqlite::ffi;
+use warp::{Rejection, Reply};
+use warp::http::StatusCode;
+use crate::model::{ErrorCode, PolicyViolation, ResponseError};
+use crate::server::policy::violation_message;
+
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    Conflict(String),
+    Policy(PolicyViolation),
+    Invariant(ErrorCode, String),
+    Validation(String),
+    Unauthorized(String),
+    Forbidden,
+    Internal,
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+impl warp::reject::Reject for ApiError {}
+
+impl ApiError {
+    pub fn not_found(message: &str) -> Self {
+        ApiError::NotFound(message.to_string())
+    }
+
+    pub fn conflict(message: &str) -> Self {
+        ApiError::Conflict(message.to_string())
+    }
+
+    pub fn validation(message: &str) -> Self {
+        ApiError::Validation(message.to_string())
+    }
+
+    pub fn unauthorized(message: &str) -> Self {
+        ApiError::Unauthorized(message.to_string())
+    }
+
+    /// A circulation invariant the request would break, named by its own `code`.
+    pub fn invariant(code: ErrorCode, message: &str) -> Self {
+        ApiError::Invariant(code, message.to_string())
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Conflict(_) | ApiError::Policy(_) => ErrorCode::Conflict,
+            ApiError::Invariant(code, _) => *code,
+            ApiError::Validation(_) => ErrorCode::Validation,
+            ApiError::Unauthorized(_) => ErrorCode::Unauthorized,
+            ApiError::Forbidden => ErrorCode::Forbidden,
+            ApiError::Internal => ErrorCode::Internal,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) | ApiError::Policy(_) => StatusCode::CONFLICT,
+            ApiError::Invariant(code, _) => match code {
+                ErrorCode::UserNotFound
+                | ErrorCode::BookNotFound
+                | ErrorCode::InstanceNotFound => StatusCode::NOT_FOUND,
+                _ => StatusCode::CONFLICT,
+            },
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Validation(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Invariant(_, message) => message.clone(),
+            ApiError::Policy(violation) => violation_message(violation),
+            ApiError::Forbidden => "permission denied".to_string(),
+            ApiError::Internal => "internal server error".to_string(),
+        }
+    }
+
+    fn body(&self) -> ResponseError {
+        ResponseError {
+            success: false,
+            code: self.code(),
+            message: self.message(),
+            violation: match self {
+                ApiError::Policy(violation) => Some(violation.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+// Raw SQLite text stays in the server log; clients get a stable code instead.
+impl From<rusqlite::Error> for ApiError {
+    fn from(err: rusqlite::Error) -> Self {
+        match &err {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("record does not exist"),
+            rusqlite::Error::SqliteFailure(failure, _) => match failure.extended_code {
+                ffi::SQLITE_CONSTRAINT_FOREIGNKEY =>
+                    ApiError::conflict("record is referenced elsewhere or refers to a missing record"),
+                ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY =>
+                    ApiError::conflict("record already exists"),
+                ffi::SQLITE_CONSTRAINT_CHECK | ffi::SQLITE_CONSTRAINT_NOTNULL =>
+                    ApiError::validation("value is out of range"),
+                _ => {
+                    error!("database error: {}", err);
+                    ApiError::Internal
+                }
+            },
+            _ => {
+                error!("database error: {}", err);
+                ApiError::Internal
+            }
+        }
+    }
+}
+
+/// Logs a failed handler and turns its error into a rejection for `handle_rejection`.
+pub fn reject(name: &str, err: ApiError) -> Rejection {
+    info!("{} ERR {:?}", name, err);
+    warp::reject::custom(err)
+}
+
+fn error_reply(err: &ApiError) -> impl Reply {
+    warp::reply::with_status(warp::reply::json(&err.body()), err.status())
+}
+
+pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infallible> {
+    if let Some(err) = rejection.find::<ApiError>() {
+        return Ok(error_reply(err));
+    }
+    let err = if rejection.is_not_found() {
+        ApiError::not_found("no such endpoint")
+    } else if let Some(err) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        ApiError::Validation(format!("malformed request body: {}", err))
+    } else if let Some(err) = rejection.find::<warp::reject::InvalidQuery>() {
+        ApiError::Validation(format!("malformed query: {}", err))
+    } else if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        ApiError::validation("request body is too large")
+    } else if rejection.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        ApiError::validation("request body must be json")
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        ApiError::not_found("no such endpoint for this method")
+    } else {
+        error!("unhandled rejection: {:?}", rejection);
+        ApiError::Internal
+    };
+    Ok(error_reply(&err))
+}
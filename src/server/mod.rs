@@ -1,57 +1,257 @@
 mod api;
+mod audit;
+mod auth;
+mod error;
+mod policy;
+mod pool;
+mod role;
+mod shutdown;
 
 use api::*;
+use audit::{audited, OwnAccount};
+use auth::*;
+use error::*;
+use pool::Pool;
+use role::*;
+use shutdown::{InFlight, InFlightGuard};
 
-use log::info;
+pub use auth::set_password;
+
+use log::{info, warn};
+use crate::migrate::{latest_version, migrate, schema_version};
+use crate::model::RequestHoldCancel;
+use crate::settings::settings;
 use rusqlite::Connection;
-use std::sync::{Mutex, MutexGuard};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::watch;
 use warp::Filter;
 
 const SERVER_README: &str = include_str!("../../assets/server_readme.txt");
+const HOLD_EXPIRY_INTERVAL: Duration = Duration::from_secs(300);
 
-macro_rules! endpoint_post_request {
-    ($name:tt, $callback:ident) => {
+macro_rules! endpoint_post_request_own {
+    ($pool:ident, $name:tt, $callback:ident, $permission:expr) => {
+        warp::path($name)
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_pool($pool.clone()))
+            .and(authenticated($pool.clone()))
+            .and(warp::body::content_length_limit(settings().server.body_limit))
+            .and(warp::body::json())
+            .and_then(|pool: Pool, principal: Principal, req| async move {
+                reply(stringify!($callback), pool.write(move |db| {
+                    authorize(db, &principal, $permission, &req)?;
+                    audited(db, Some(principal.uid), stringify!($callback), req, $callback)
+                }).await)
+            })
+    };
+}
+
+// Handlers told `actor` also get the caller's uid, for records of their own
+// that name who acted.
+macro_rules! endpoint_post_request_staff {
+    ($pool:ident, $name:tt, $callback:ident, $permission:expr) => {
+        warp::path($name)
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_pool($pool.clone()))
+            .and(authorized($pool.clone(), $permission))
+            .and(warp::body::content_length_limit(settings().server.body_limit))
+            .and(warp::body::json())
+            .and_then(|pool: Pool, principal: Principal, req| async move {
+                reply(stringify!($callback), pool.write(move |db| {
+                    audited(db, Some(principal.uid), stringify!($callback), req, $callback)
+                }).await)
+            })
+    };
+    ($pool:ident, $name:tt, $callback:ident, $permission:expr, actor) => {
         warp::path($name)
             .and(warp::path::end())
             .and(warp::post())
-            .and(warp::body::content_length_limit(1024 * 16))
+            .and(with_pool($pool.clone()))
+            .and(authorized($pool.clone(), $permission))
+            .and(warp::body::content_length_limit(settings().server.body_limit))
             .and(warp::body::json())
-            .map(|req| warp::reply::json(&$callback(req)))
+            .and_then(|pool: Pool, principal: Principal, req| async move {
+                reply(stringify!($callback), pool.write(move |db| {
+                    audited(db, Some(principal.uid), stringify!($callback), req,
+                        |db, req| $callback(db, principal.uid, req))
+                }).await)
+            })
     };
 }
 
+macro_rules! endpoint_post_request_principal {
+    ($pool:ident, $name:tt, $callback:ident) => {
+        warp::path($name)
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_pool($pool.clone()))
+            .and(authenticated($pool.clone()))
+            .and(warp::body::content_length_limit(settings().server.body_limit))
+            .and(warp::body::json())
+            .and_then(|pool: Pool, principal: Principal, req| async move {
+                reply(stringify!($callback), pool.write(move |db| {
+                    audited(db, Some(principal.uid), stringify!($callback), OwnAccount(principal.uid, req),
+                        |db, OwnAccount(_, req)| $callback(db, principal, req))
+                }).await)
+            })
+    };
+}
+
+macro_rules! endpoint_post_request_public {
+    ($pool:ident, $name:tt, $callback:ident) => {
+        warp::path($name)
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(with_pool($pool.clone()))
+            .and(warp::body::content_length_limit(settings().server.body_limit))
+            .and(warp::body::json())
+            .and_then(|pool: Pool, req| async move {
+                reply(stringify!($callback), pool.write(move |db| {
+                    audited(db, None, stringify!($callback), req, $callback)
+                }).await)
+            })
+    };
+}
+
+// GET endpoints run on a read-only connection unless told to `write`.
 macro_rules! endpoint_get_request {
-    ($name:tt, $callback:ident) => {
+    ($pool:ident, $name:tt, $callback:ident) => {
+        endpoint_get_request!($pool, $name, $callback, read)
+    };
+    ($pool:ident, $name:tt, $callback:ident, $access:ident) => {
+        warp::path($name)
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(with_pool($pool.clone()))
+            .and(authenticated($pool.clone()))
+            .and(warp::query())
+            .and_then(|pool: Pool, _: Principal, req| async move {
+                reply(stringify!($callback), pool.$access(move |db| $callback(db, req)).await)
+            })
+    };
+}
+
+macro_rules! endpoint_get_request_own {
+    ($pool:ident, $name:tt, $callback:ident, $permission:expr) => {
+        endpoint_get_request_own!($pool, $name, $callback, $permission, read)
+    };
+    ($pool:ident, $name:tt, $callback:ident, $permission:expr, $access:ident) => {
+        warp::path($name)
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(with_pool($pool.clone()))
+            .and(authenticated($pool.clone()))
+            .and(warp::query())
+            .and_then(|pool: Pool, principal: Principal, req| async move {
+                reply(stringify!($callback), pool.$access(move |db| {
+                    authorize(db, &principal, $permission, &req)?;
+                    $callback(db, req)
+                }).await)
+            })
+    };
+}
+
+macro_rules! endpoint_get_request_staff {
+    ($pool:ident, $name:tt, $callback:ident, $permission:expr) => {
+        endpoint_get_request_staff!($pool, $name, $callback, $permission, read)
+    };
+    ($pool:ident, $name:tt, $callback:ident, $permission:expr, $access:ident) => {
         warp::path($name)
             .and(warp::path::end())
             .and(warp::get())
+            .and(with_pool($pool.clone()))
+            .and(authorized($pool.clone(), $permission))
             .and(warp::query())
-            .map(|req| warp::reply::json(&$callback(req)))
+            .and_then(|pool: Pool, _: Principal, req| async move {
+                reply(stringify!($callback), pool.$access(move |db| $callback(db, req)).await)
+            })
     };
 }
 
-static mut DATABASE_CONNECTION: Option<Mutex<Connection>> = None;
+fn with_pool(pool: Pool) -> impl Filter<Extract = (Pool,), Error = Infallible> + Clone {
+    warp::any().map(move || pool.clone())
+}
 
-pub fn database() -> MutexGuard<'static, Connection> {
-    unsafe {
-        DATABASE_CONNECTION.as_ref().unwrap().lock().unwrap()
+/// Hides the routes behind it when a feature toggle is off.
+fn feature(enabled: bool) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+}
+
+fn reply<T: Serialize>(name: &str, res: ApiResult<T>) -> Result<warp::reply::Json, warp::Rejection> {
+    res.map(|res| warp::reply::json(&res)).map_err(|err| reject(name, err))
+}
+
+/// Ends shelf holds past their pickup window every `HOLD_EXPIRY_INTERVAL`,
+/// each in its own audited transaction, until the task is aborted.
+async fn expire_holds(pool: Pool) {
+    let mut interval = tokio::time::interval(HOLD_EXPIRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        let expired = pool.write(|db| {
+            let expired = expired_holds(db)?;
+            for &hid in &expired {
+                audited(db, None, "system_expire_hold", RequestHoldCancel { hid }, expire_hold)?;
+            }
+            Ok(expired.len())
+        }).await;
+        match expired {
+            Ok(0) => {}
+            Ok(expired) => info!("Expired {} shelf holds", expired),
+            Err(err) => warn!("Failed to expire shelf holds: {:?}", err),
+        }
     }
 }
 
-pub async fn main_server(port: String) {
-    env_logger::init();
+pub fn metadata(db: &Connection, key: &str) -> Option<String> {
+    db.query_row(
+        "SELECT value FROM lms_metadata WHERE key = ?1",
+        [key],
+        |row| row.get(0),
+    ).ok()
+}
+
+pub async fn main_server() {
+    let settings = settings();
+    settings.init_logger();
     info!("Library Management Service by Midnight233, Version {}", env!("CARGO_PKG_VERSION"));
 
     info!("Connecting to database");
-    unsafe {
-        DATABASE_CONNECTION = Some(Mutex::new(Connection::open("rdb_exp3.db")
-            .expect("Failed to connect to database. Did you run configuration?")));
+    let mut db = Connection::open(&settings.database.path)
+        .expect("Failed to connect to database. Did you run configuration?");
+
+    info!("Checking database schema version");
+    let version = schema_version(&db).expect("Failed to read database schema version");
+    if version == 0 {
+        panic!("Database is not configured. Did you run configuration?");
+    }
+    if version > latest_version() {
+        panic!("Database schema version {} is newer than the latest known version {}. Refusing to start",
+            version, latest_version());
+    }
+    if version < latest_version() {
+        info!("Database schema version {} is behind {}", version, latest_version());
+        migrate(&mut db).expect("Failed to migrate database");
     }
 
     info!("Checking sanity of database");
-    ["lms_user", "lms_book", "lms_instance", "lms_occupation", "lms_history"].iter()
+    ["lms_user", "lms_credential", "lms_session", "lms_book", "lms_instance", "lms_occupation", "lms_history", "lms_hold",
+        "lms_fine", "lms_category", "lms_item_type", "lms_policy", "lms_audit",
+        "lms_instance_event", "lms_book_subject", "lms_author", "lms_book_author", "lms_book_tag", "lms_series", "lms_book_series", "lms_transfer",
+        "lms_stocktake", "lms_stocktake_scan", "lms_stocktake_finding"].iter()
         .for_each(|table| {
-            if database().query_row(
+            if db.query_row(
                 "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
                 [table],
                 |row| row.get::<_, i64>(0),
@@ -60,82 +260,270 @@ pub async fn main_server(port: String) {
             }
         });
 
-    ctrlc::set_handler(move || {
-        info!("Shutting down server");
-        let db = unsafe { DATABASE_CONNECTION.take().unwrap() };
-        db.into_inner().unwrap().close().unwrap();
-        std::process::exit(0);
-    }).expect("Failed to register Ctrl-C handler");
+    let readers = settings.readers();
+    info!("Opening connection pool with {} readers", readers);
+    let pool = Pool::new(&settings.database.path, db, readers).expect("Failed to open connection pool");
 
-    info!("Starting server on port {}", port);
+    info!("Starting server on port {}", settings.server.port);
     let root = warp::path::end()
         .map(move || format!(
             "Library Management Service by Midnight233, Version {}\n\n{}",
             env!("CARGO_PKG_VERSION"), SERVER_README));
 
     let user = {
-        let register = endpoint_post_request!("register", user_register);
-        let borrowed = endpoint_get_request!("borrowed", user_borrowed);
-        let unregister = endpoint_post_request!("unregister", user_unregister);
-        let borrow = endpoint_post_request!("borrow", user_borrow);
-        let return_ = endpoint_post_request!("return", user_return);
-        let lookup = endpoint_get_request!("lookup", user_lookup);
-        let alter = endpoint_post_request!("alter", user_alter);
-        let reserve = endpoint_post_request!("reserve", user_reserve);
-        let reserved = endpoint_get_request!("reserved", user_reserved);
-        let info = endpoint_get_request!("info", user_info);
+        let register = feature(settings.features.registration)
+            .and(endpoint_post_request_public!(pool, "register", user_register));
+        let borrowed = endpoint_get_request_own!(pool, "borrowed", user_borrowed, Permission::ViewPatrons);
+        let unregister = endpoint_post_request_own!(pool, "unregister", user_unregister, Permission::ManagePatrons);
+        let borrow = endpoint_post_request_own!(pool, "borrow", user_borrow, Permission::Circulate);
+        let return_ = endpoint_post_request_own!(pool, "return", user_return, Permission::Circulate);
+        let renew = endpoint_post_request_own!(pool, "renew", user_renew, Permission::Circulate);
+        let lookup = endpoint_get_request_staff!(pool, "lookup", user_lookup, Permission::ViewPatrons);
+        let alter = endpoint_post_request_own!(pool, "alter", user_alter, Permission::ManagePatrons);
+        let reserve = endpoint_post_request_own!(pool, "reserve", user_reserve, Permission::Circulate);
+        let reserved = endpoint_get_request_own!(pool, "reserved", user_reserved, Permission::ViewPatrons);
+        let cancel_hold = endpoint_post_request_own!(pool, "cancel_hold", user_cancel_hold, Permission::Circulate);
+        let info = endpoint_get_request_own!(pool, "info", user_info, Permission::ViewPatrons);
+        let overdue = endpoint_get_request_own!(pool, "overdue", user_overdue, Permission::ViewPatrons);
+        let fines = endpoint_get_request_own!(pool, "fines", user_fines, Permission::ViewPatrons);
+        let policy = endpoint_get_request_own!(pool, "policy", user_policy, Permission::ViewPatrons);
+        let history = endpoint_get_request_own!(pool, "history", user_history, Permission::ViewPatrons);
         warp::path("user").and(register
             .or(borrowed)
             .or(unregister)
             .or(borrow)
             .or(return_)
+            .or(renew)
             .or(lookup)
             .or(alter)
             .or(reserve)
             .or(reserved)
-            .or(info))
+            .or(cancel_hold)
+            .or(info)
+            .or(overdue)
+            .or(fines)
+            .or(policy)
+            .or(history))
     };
 
     let book = {
-        let search = endpoint_get_request!("search", book_search);
-        let info = endpoint_get_request!("info", book_info);
-        let instance = endpoint_get_request!("instance", book_instance);
-        let instance_info = endpoint_get_request!("instance_info", book_instance_info);
+        let search = endpoint_get_request!(pool, "search", book_search);
+        let info = endpoint_get_request!(pool, "info", book_info);
+        let instance = endpoint_get_request!(pool, "instance", book_instance);
+        let instance_info = endpoint_get_request!(pool, "instance_info", book_instance_info);
+        let holds = endpoint_get_request_staff!(pool, "holds", book_holds, Permission::ViewPatrons);
+        let instance_history = endpoint_get_request_staff!(pool, "instance_history", book_instance_history, Permission::ViewPatrons);
+        let by_isbn = endpoint_get_request!(pool, "by_isbn", book_by_isbn);
+        let by_author = endpoint_get_request!(pool, "by_author", book_by_author);
+        let by_subject = endpoint_get_request!(pool, "by_subject", book_by_subject);
+        let by_class = endpoint_get_request!(pool, "by_class", book_by_class);
+        let series = endpoint_get_request!(pool, "series", book_series);
+        let next_volume = endpoint_get_request!(pool, "next_volume", book_next_volume);
         warp::path("book").and(search
             .or(info)
             .or(instance)
-            .or(instance_info))
+            .or(instance_info)
+            .or(holds)
+            .or(instance_history)
+            .or(by_isbn)
+            .or(by_author)
+            .or(by_subject)
+            .or(by_class)
+            .or(series)
+            .or(next_volume))
+    };
+
+    let location = {
+        let tree = endpoint_get_request!(pool, "tree", location_tree);
+        let contents = endpoint_get_request!(pool, "contents", location_contents);
+        warp::path("location").and(tree
+            .or(contents))
     };
 
     let admin = {
-        let add = endpoint_post_request!("add", admin_add);
-        let remove = endpoint_post_request!("remove", admin_remove);
-        let alter = endpoint_post_request!("alter", admin_alter);
-        let add_instance = endpoint_post_request!("add_instance", admin_add_instance);
-        let remove_instance = endpoint_post_request!("remove_instance", admin_remove_instance);
-        let occupy_instance = endpoint_post_request!("occupy_instance", admin_occupy_instance);
-        let release_instance = endpoint_post_request!("release_instance", admin_release_instance);
-        let add_location = endpoint_post_request!("add_location", admin_add_location);
-        let remove_location = endpoint_post_request!("remove_location", admin_remove_location);
-        let alter_location = endpoint_post_request!("alter_location", admin_alter_location);
-        warp::path("admin").and(add
+        let add = endpoint_post_request_staff!(pool, "add", admin_add, Permission::Catalogue);
+        let remove = endpoint_post_request_staff!(pool, "remove", admin_remove, Permission::Catalogue);
+        let alter = endpoint_post_request_staff!(pool, "alter", admin_alter, Permission::Catalogue);
+        let add_author = endpoint_post_request_staff!(pool, "add_author", admin_add_author, Permission::Catalogue);
+        let rename_author = endpoint_post_request_staff!(pool, "rename_author", admin_rename_author, Permission::Catalogue);
+        let merge_authors = endpoint_post_request_staff!(pool, "merge_authors", admin_merge_authors, Permission::Catalogue);
+        let set_contributors = endpoint_post_request_staff!(pool, "set_contributors", admin_set_contributors, Permission::Catalogue);
+        let add_series = endpoint_post_request_staff!(pool, "add_series", admin_add_series, Permission::Catalogue);
+        let set_series = endpoint_post_request_staff!(pool, "set_series", admin_set_series, Permission::Catalogue);
+        let add_instance = endpoint_post_request_staff!(pool, "add_instance", admin_add_instance, Permission::Catalogue);
+        let remove_instance = endpoint_post_request_staff!(pool, "remove_instance", admin_remove_instance, Permission::Catalogue);
+        let occupy_instance = endpoint_post_request_staff!(pool, "occupy_instance", admin_occupy_instance, Permission::Circulate, actor);
+        let release_instance = endpoint_post_request_staff!(pool, "release_instance", admin_release_instance, Permission::Circulate, actor);
+        let send_repair = endpoint_post_request_staff!(pool, "send_repair", admin_send_repair, Permission::Circulate, actor);
+        let finish_repair = endpoint_post_request_staff!(pool, "finish_repair", admin_finish_repair, Permission::Circulate, actor);
+        let mark_lost = endpoint_post_request_staff!(pool, "mark_lost", admin_mark_lost, Permission::Circulate, actor);
+        let mark_found = endpoint_post_request_staff!(pool, "mark_found", admin_mark_found, Permission::Circulate, actor);
+        let withdraw_instance = endpoint_post_request_staff!(pool, "withdraw_instance", admin_withdraw_instance, Permission::Catalogue, actor);
+        let add_location = endpoint_post_request_staff!(pool, "add_location", admin_add_location, Permission::Catalogue);
+        let remove_location = endpoint_post_request_staff!(pool, "remove_location", admin_remove_location, Permission::Catalogue);
+        let alter_location = endpoint_post_request_staff!(pool, "alter_location", admin_alter_location, Permission::Catalogue);
+        let overdue = endpoint_get_request_staff!(pool, "overdue", admin_overdue, Permission::Reports);
+        let add_fine = endpoint_post_request_staff!(pool, "add_fine", admin_add_fine, Permission::AdjustFines);
+        let pay_fine = endpoint_post_request_staff!(pool, "pay_fine", admin_pay_fine, Permission::CollectFines);
+        let waive_fine = endpoint_post_request_staff!(pool, "waive_fine", admin_waive_fine, Permission::AdjustFines);
+        let add_category = endpoint_post_request_staff!(pool, "add_category", admin_add_category, Permission::Configure);
+        let add_item_type = endpoint_post_request_staff!(pool, "add_item_type", admin_add_item_type, Permission::Configure);
+        let set_category = endpoint_post_request_staff!(pool, "set_category", admin_set_category, Permission::ManagePatrons);
+        let set_active = endpoint_post_request_staff!(pool, "set_active", admin_set_active, Permission::ManagePatrons);
+        let set_policy = endpoint_post_request_staff!(pool, "set_policy", admin_set_policy, Permission::Configure);
+        let grant_role = endpoint_post_request_staff!(pool, "grant_role", admin_grant_role, Permission::GrantRole);
+        // Setting a password takes over the account, so it needs as much as granting roles.
+        let set_password = endpoint_post_request_staff!(pool, "set_password", admin_set_password, Permission::GrantRole);
+        let audit = endpoint_get_request_staff!(pool, "audit", admin_audit, Permission::Audit);
+        let request_transfer = endpoint_post_request_staff!(pool, "request_transfer", admin_request_transfer, Permission::Circulate);
+        let dispatch_transfer = endpoint_post_request_staff!(pool, "dispatch_transfer", admin_dispatch_transfer, Permission::Circulate);
+        let receive_transfer = endpoint_post_request_staff!(pool, "receive_transfer", admin_receive_transfer, Permission::Circulate);
+        let cancel_transfer = endpoint_post_request_staff!(pool, "cancel_transfer", admin_cancel_transfer, Permission::Circulate);
+        let transfers = endpoint_get_request_staff!(pool, "transfers", admin_transfers, Permission::Circulate);
+        let open_stocktake = endpoint_post_request_staff!(pool, "open_stocktake", admin_open_stocktake, Permission::Circulate);
+        let scan_stocktake = endpoint_post_request_staff!(pool, "scan_stocktake", admin_scan_stocktake, Permission::Circulate);
+        let close_stocktake = endpoint_post_request_staff!(pool, "close_stocktake", admin_close_stocktake, Permission::Circulate, actor);
+        let stocktake = endpoint_get_request_staff!(pool, "stocktake", admin_stocktake, Permission::Circulate);
+        let stocktakes = endpoint_get_request_staff!(pool, "stocktakes", admin_stocktakes, Permission::Circulate);
+        // Each group is boxed so the route type stays within the compiler's
+        // depth limit; keep new groups to a dozen routes or so.
+        let catalogue = add
             .or(remove)
             .or(alter)
-            .or(add_instance)
+            .or(add_author)
+            .or(rename_author)
+            .or(merge_authors)
+            .or(set_contributors)
+            .or(add_series)
+            .or(set_series)
+            .boxed();
+        let instances = add_instance
             .or(remove_instance)
             .or(occupy_instance)
             .or(release_instance)
-            .or(add_location)
+            .or(send_repair)
+            .or(finish_repair)
+            .or(mark_lost)
+            .or(mark_found)
+            .or(withdraw_instance)
+            .boxed();
+        let locations = add_location
             .or(remove_location)
-            .or(alter_location))
+            .or(alter_location)
+            .or(request_transfer)
+            .or(dispatch_transfer)
+            .or(receive_transfer)
+            .or(cancel_transfer)
+            .or(transfers)
+            .boxed();
+        let inventory = open_stocktake
+            .or(scan_stocktake)
+            .or(close_stocktake)
+            .or(stocktake)
+            .or(stocktakes)
+            .boxed();
+        let fines = overdue
+            .or(add_fine)
+            .or(pay_fine)
+            .or(waive_fine)
+            .boxed();
+        let configuration = add_category
+            .or(add_item_type)
+            .or(set_category)
+            .or(set_active)
+            .or(set_policy)
+            .or(grant_role)
+            .or(set_password)
+            .or(audit)
+            .boxed();
+        warp::path("admin").and(catalogue
+            .or(instances)
+            .or(locations)
+            .or(inventory)
+            .or(fines)
+            .or(configuration))
+    };
+
+    let auth = {
+        let login = endpoint_post_request_public!(pool, "login", auth_login);
+        let logout = endpoint_post_request_principal!(pool, "logout", auth_logout);
+        let password = endpoint_post_request_principal!(pool, "password", auth_password);
+        warp::path("auth").and(login
+            .or(logout)
+            .or(password))
     };
 
-    let api = root
-        .or(user)
-        .or(book)
-        .or(admin);
+    let v2 = {
+        let user = {
+            let borrowed = endpoint_get_request_own!(pool, "borrowed", user_borrowed_v2, Permission::ViewPatrons);
+            let reserved = endpoint_get_request_own!(pool, "reserved", user_reserved_v2, Permission::ViewPatrons);
+            warp::path("user").and(borrowed
+                .or(reserved))
+        };
+        let book = {
+            let search = endpoint_get_request!(pool, "search", book_search_v2);
+            let instance = endpoint_get_request!(pool, "instance", book_instance_v2);
+            warp::path("book").and(search
+                .or(instance))
+        };
+        warp::path("v2")
+            .and(feature(settings.features.v2))
+            .and(user
+                .or(book))
+    };
 
-    warp::serve(api)
-        .run(([127, 0, 0, 1], port.parse::<u16>().unwrap()))
-        .await;
-}
\ No newline at end of file
+    let in_flight = InFlight::default();
+    let api = in_flight.enter()
+        .and(root
+            .or(v2)
+            .or(auth)
+            .or(user)
+            .or(book)
+            .or(location)
+            .or(admin)
+            .recover(handle_rejection))
+        .map(|guard: InFlightGuard, reply| {
+            drop(guard);
+            reply
+        });
+
+    let (stop, stopped) = watch::channel(false);
+    let servers = settings.addresses().into_iter().map(|addr| {
+        let mut stopped = stopped.clone();
+        let (bound, server) = warp::serve(api.clone())
+            .try_bind_with_graceful_shutdown((addr, settings.server.port), async move {
+                let _ = stopped.changed().await;
+            })
+            .unwrap_or_else(|err| panic!("Failed to bind {}: {}", addr, err));
+        info!("Listening on {}", bound);
+        tokio::spawn(server)
+    }).collect::<Vec<_>>();
+
+    let expiry = tokio::spawn(expire_holds(pool.clone()));
+
+    shutdown::signal().await;
+    info!("Shutting down server, draining {} in-flight requests", in_flight.count());
+    stop.send(true).expect("Servers stopped listening early");
+    let drain = Duration::from_secs(settings.server.drain_timeout);
+    let drained = tokio::time::timeout(drain, async {
+        for server in servers {
+            let _ = server.await;
+        }
+    }).await;
+    if drained.is_err() {
+        warn!("Drain timeout of {}s elapsed with {} requests in flight, {} of them waiting for the database and {} using it",
+            settings.server.drain_timeout, in_flight.count(), pool.waiting(), pool.working());
+    }
+
+    expiry.abort();
+    info!("Waiting for outstanding database work");
+    // Requests that held or were queued for a connection before the pool
+    // closed may have committed; only those still waiting are refused.
+    let refused = pool.close().await.expect("Failed to checkpoint database");
+    match in_flight.count() {
+        0 => info!("All in-flight requests completed"),
+        remaining => warn!("Abandoned {} in-flight requests, {} of them refused before reaching the database",
+            remaining, refused),
+    }
+    info!("Server stopped");
+}
@@ -0,0 +1,168 @@
+use rusqlite::{Connection, OptionalExtension};
+use crate::model::PolicyViolation;
+use crate::server::metadata;
+use crate::settings::settings;
+
+pub const DEFAULT_LOAN_PERIOD: u64 = 30;
+pub const DEFAULT_RENEWAL_LIMIT: u64 = 2;
+pub const DEFAULT_FINE_DAILY_RATE: u64 = 10;
+pub const DEFAULT_MAX_LOANS: u64 = 10;
+pub const DEFAULT_MAX_HOLDS: u64 = 5;
+
+pub fn metadata_u64(db: &Connection, key: &str, default: u64) -> u64 {
+    metadata(db, key)
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(default)
+}
+
+/// A library-wide policy value: the configuration file wins over
+/// `lms_metadata`, which wins over the built-in `default`.
+pub fn policy_u64(db: &Connection, key: &str, default: u64) -> u64 {
+    settings().policy.get(key).unwrap_or_else(|| metadata_u64(db, key, default))
+}
+
+/// Circulation rules for one patron category and item type, falling back to
+/// the library-wide defaults in `lms_metadata` when `lms_policy` has no row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy {
+    pub max_loans: u64,
+    pub loan_period: u64,
+    pub renewal_limit: u64,
+    pub max_holds: u64,
+    pub fine_daily_rate: u64,
+}
+
+impl Policy {
+    pub fn lookup(db: &Connection, cid: u64, tid: u64) -> rusqlite::Result<Policy> {
+        let policy = db.query_row(
+            "SELECT max_loans, loan_period, renewal_limit, max_holds, fine_daily_rate \
+            FROM lms_policy WHERE cid = ?1 AND tid = ?2",
+            [cid, tid],
+            |row| Ok(Policy {
+                max_loans: row.get(0)?,
+                loan_period: row.get(1)?,
+                renewal_limit: row.get(2)?,
+                max_holds: row.get(3)?,
+                fine_daily_rate: row.get(4)?,
+            }),
+        ).optional()?;
+        Ok(policy.unwrap_or_else(|| Policy::fallback(db)))
+    }
+
+    pub fn for_book(db: &Connection, uid: u64, bid: u64) -> rusqlite::Result<Policy> {
+        let (cid, tid) = db.query_row(
+            "SELECT u.cid, b.tid FROM lms_user u, lms_book b WHERE u.uid = ?1 AND b.bid = ?2",
+            [uid, bid],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Policy::lookup(db, cid, tid)
+    }
+
+    pub fn for_instance(db: &Connection, uid: u64, iid: u64) -> rusqlite::Result<Policy> {
+        let bid = db.query_row(
+            "SELECT bid FROM lms_instance WHERE iid = ?1",
+            [iid],
+            |row| row.get(0),
+        )?;
+        Policy::for_book(db, uid, bid)
+    }
+
+    fn fallback(db: &Connection) -> Policy {
+        Policy {
+            max_loans: policy_u64(db, "max_loans", DEFAULT_MAX_LOANS),
+            loan_period: policy_u64(db, "loan_period", DEFAULT_LOAN_PERIOD),
+            renewal_limit: policy_u64(db, "renewal_limit", DEFAULT_RENEWAL_LIMIT),
+            max_holds: policy_u64(db, "max_holds", DEFAULT_MAX_HOLDS),
+            fine_daily_rate: policy_u64(db, "fine_daily_rate", DEFAULT_FINE_DAILY_RATE),
+        }
+    }
+
+    pub fn check_borrow(
+        &self,
+        db: &Connection,
+        uid: u64,
+        bid: u64,
+    ) -> rusqlite::Result<Option<PolicyViolation>> {
+        let current = db.query_row(
+            "SELECT COUNT(*) FROM lms_occupation o \
+            JOIN lms_instance i ON i.iid = o.iid \
+            JOIN lms_book b ON b.bid = i.bid \
+            WHERE o.uid = ?1 AND o.kind = 0 \
+            AND b.tid = (SELECT tid FROM lms_book WHERE bid = ?2)",
+            [uid, bid],
+            |row| row.get(0),
+        )?;
+        Ok(violation("max_loans", self.max_loans, current))
+    }
+
+    pub fn check_hold(
+        &self,
+        db: &Connection,
+        uid: u64,
+        bid: u64,
+    ) -> rusqlite::Result<Option<PolicyViolation>> {
+        let current = db.query_row(
+            "SELECT COUNT(*) FROM lms_hold h \
+            JOIN lms_book b ON b.bid = h.bid \
+            WHERE h.uid = ?1 AND h.status IN (0, 1) \
+            AND b.tid = (SELECT tid FROM lms_book WHERE bid = ?2)",
+            [uid, bid],
+            |row| row.get(0),
+        )?;
+        Ok(violation("max_holds", self.max_holds, current))
+    }
+
+    pub fn check_renew(&self, renewals: u64) -> Option<PolicyViolation> {
+        violation("renewal_limit", self.renewal_limit, renewals)
+    }
+}
+
+pub fn violation(rule: &str, limit: u64, current: u64) -> Option<PolicyViolation> {
+    if current < limit {
+        return None;
+    }
+    Some(PolicyViolation {
+        rule: rule.to_string(),
+        limit,
+        current,
+    })
+}
+
+pub fn violation_message(violation: &PolicyViolation) -> String {
+    format!("{} of {} reached ({} in use)", violation.rule, violation.limit, violation.current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrate::test_database;
+
+    #[test]
+    fn policies_fall_back_to_library_defaults() {
+        let db = test_database();
+        db.execute_batch(
+            "UPDATE lms_policy SET fine_daily_rate = 0 WHERE cid = 2 AND tid = 1; \
+            DELETE FROM lms_policy WHERE cid = 1 AND tid = 1; \
+            UPDATE lms_metadata SET value = '25' WHERE key = 'fine_daily_rate';",
+        ).unwrap();
+        let staff = Policy::lookup(&db, 2, 1).unwrap();
+        assert_eq!((staff.loan_period, staff.fine_daily_rate), (90, 0));
+        let student = Policy::lookup(&db, 1, 1).unwrap();
+        assert_eq!(student, Policy {
+            max_loans: DEFAULT_MAX_LOANS,
+            loan_period: DEFAULT_LOAN_PERIOD,
+            renewal_limit: DEFAULT_RENEWAL_LIMIT,
+            max_holds: DEFAULT_MAX_HOLDS,
+            fine_daily_rate: 25,
+        });
+    }
+
+    #[test]
+    fn limits_are_violated_once_reached() {
+        assert_eq!(violation("max_loans", 2, 1), None);
+        let reached = violation("max_loans", 2, 2).unwrap();
+        assert_eq!(violation_message(&reached), "max_loans of 2 reached (2 in use)");
+        let reference = Policy::lookup(&test_database(), 1, 2).unwrap();
+        assert_eq!(reference.check_renew(0).map(|violation| violation.rule).as_deref(), Some("renewal_limit"));
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use log::{error, warn};
+use rusqlite::{Connection, OpenFlags};
+use tokio::sync::{Mutex as AsyncMutex, OwnedSemaphorePermit, Semaphore};
+use crate::server::error::{ApiError, ApiResult};
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SQLite connections shared by the handlers. In WAL mode readers never block
+/// each other or the writer, so reads fan out over `readers` connections while
+/// every write goes through the single writer connection.
+#[derive(Clone)]
+pub struct Pool {
+    /// `None` once the pool is closed.
+    writer: Arc<AsyncMutex<Option<Connection>>>,
+    readers: Arc<Mutex<Vec<Connection>>>,
+    permits: Arc<Semaphore>,
+    size: u32,
+    waiting: Arc<AtomicUsize>,
+    working: Arc<AtomicUsize>,
+}
+
+fn open(path: &str, flags: OpenFlags) -> rusqlite::Result<Connection> {
+    let db = Connection::open_with_flags(path, flags)?;
+    db.busy_timeout(BUSY_TIMEOUT)?;
+    db.execute_batch("PRAGMA foreign_keys = ON; PRAGMA synchronous = NORMAL")?;
+    Ok(db)
+}
+
+/// Puts a checked out reader back even if the caller went away or panicked.
+struct Reader {
+    db: Option<Connection>,
+    readers: Arc<Mutex<Vec<Connection>>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.take() {
+            self.readers.lock().unwrap().push(db);
+        }
+    }
+}
+
+/// Counts one task in `count` until dropped, even if the task is cancelled.
+struct Counted(Arc<AtomicUsize>);
+
+impl Counted {
+    fn new(count: &Arc<AtomicUsize>) -> Counted {
+        count.fetch_add(1, Ordering::SeqCst);
+        Counted(count.clone())
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn join_error(err: tokio::task::JoinError) -> ApiError {
+    error!("database task failed: {}", err);
+    ApiError::Internal
+}
+
+fn closed() -> ApiError {
+    warn!("database request refused, the pool is closed");
+    ApiError::Internal
+}
+
+impl Pool {
+    /// Wraps an already migrated `writer` and opens `readers` more connections to `path`.
+    pub fn new(path: &str, writer: Connection, readers: usize) -> rusqlite::Result<Pool> {
+        writer.busy_timeout(BUSY_TIMEOUT)?;
+        writer.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON; PRAGMA synchronous = NORMAL")?;
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let readers = (0..readers.max(1))
+            .map(|_| open(path, flags))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Pool {
+            writer: Arc::new(AsyncMutex::new(Some(writer))),
+            permits: Arc::new(Semaphore::new(readers.len())),
+            size: readers.len() as u32,
+            readers: Arc::new(Mutex::new(readers)),
+            waiting: Arc::default(),
+            working: Arc::default(),
+        })
+    }
+
+    /// Requests queued for a connection.
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    /// Requests running on a connection right now.
+    pub fn working(&self) -> usize {
+        self.working.load(Ordering::SeqCst)
+    }
+
+    /// Runs `f` on an idle read-only connection on the blocking thread pool.
+    pub async fn read<T, F>(&self, f: F) -> ApiResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> ApiResult<T> + Send + 'static,
+    {
+        // One permit per connection, so holding a permit guarantees an idle one.
+        let waiting = Counted::new(&self.waiting);
+        let permit = self.permits.clone().acquire_owned().await.map_err(|_| closed())?;
+        let working = Counted::new(&self.working);
+        drop(waiting);
+        let readers = self.readers.clone();
+        tokio::task::spawn_blocking(move || {
+            let _working = working;
+            let db = readers.lock().unwrap().pop().ok_or(ApiError::Internal)?;
+            let mut reader = Reader { db: Some(db), readers, _permit: permit };
+            f(reader.db.as_mut().unwrap())
+        }).await.map_err(join_error)?
+    }
+
+    /// Runs `f` on the writer connection on the blocking thread pool.
+    pub async fn write<T, F>(&self, f: F) -> ApiResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> ApiResult<T> + Send + 'static,
+    {
+        let waiting = Counted::new(&self.waiting);
+        let mut db = self.writer.clone().lock_owned().await;
+        if db.is_none() {
+            return Err(closed());
+        }
+        let working = Counted::new(&self.working);
+        drop(waiting);
+        tokio::task::spawn_blocking(move || {
+            let _working = working;
+            f(db.as_mut().unwrap())
+        }).await.map_err(join_error)?
+    }
+
+    /// Lets work already queued for a connection finish, then closes the pool
+    /// and folds the write-ahead log back into the database file. Requests
+    /// still waiting, whose number is returned, are refused without touching
+    /// the database.
+    pub async fn close(&self) -> Result<usize, String> {
+        // The semaphore and the writer lock are both fair, so taking them
+        // queues behind every request that asked for a connection first.
+        if let Ok(idle) = self.permits.acquire_many(self.size).await {
+            idle.forget();
+        }
+        let mut writer = self.writer.lock().await;
+        let refused = self.waiting();
+        self.permits.close();
+        let db = match writer.take() {
+            Some(db) => db,
+            None => return Ok(refused),
+        };
+        drop(writer);
+        tokio::task::spawn_blocking(move || {
+            db.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
+            db.close().map_err(|(_, err)| err)
+        }).await
+            .map_err(|err| format!("{}", err))?
+            .map_err(|err| format!("{}", err))?;
+        Ok(refused)
+    }
+}
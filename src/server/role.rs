@@ -0,0 +1,127 @@
+use rusqlite::{Connection, OptionalExtension};
+use warp::{Filter, Rejection};
+use crate::model::*;
+use crate::server::auth::{authenticated, Principal};
+use crate::server::error::{ApiError, ApiResult};
+use crate::server::pool::Pool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Patron,
+    Circulation,
+    Librarian,
+    Administrator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Circulate,
+    ViewPatrons,
+    ManagePatrons,
+    CollectFines,
+    AdjustFines,
+    Catalogue,
+    Reports,
+    Configure,
+    GrantRole,
+    Audit,
+}
+
+impl Role {
+    pub fn from_u64(role: u64) -> Option<Role> {
+        match role {
+            0 => Some(Role::Patron),
+            1 => Some(Role::Circulation),
+            2 => Some(Role::Librarian),
+            3 => Some(Role::Administrator),
+            _ => None,
+        }
+    }
+
+    /// The permission matrix. Patrons hold no permissions and may only act
+    /// on their own records; every staff role extends the one below it.
+    pub fn permits(self, permission: Permission) -> bool {
+        use Permission::*;
+        match self {
+            Role::Patron => false,
+            Role::Circulation => matches!(permission,
+                Circulate | ViewPatrons | CollectFines | Reports),
+            Role::Librarian => matches!(permission,
+                Circulate | ViewPatrons | CollectFines | Reports
+                | ManagePatrons | AdjustFines | Catalogue),
+            Role::Administrator => true,
+        }
+    }
+}
+
+/// Requests acting on a patron's records name that patron, either directly
+/// or through the loan or hold they refer to.
+pub trait Subject {
+    fn subject(&self, db: &Connection) -> Option<u64>;
+}
+
+macro_rules! subject_uid {
+    ($($request:ty),*) => {
+        $(impl Subject for $request {
+            fn subject(&self, _: &Connection) -> Option<u64> {
+                Some(self.uid)
+            }
+        })*
+    };
+}
+
+subject_uid!(RequestUserInfo, RequestUserAlter, RequestUserBorrowed, RequestUserReserved,
+    RequestUserOverdue, RequestUserUnregister, RequestBookBorrow, RequestBookReserve,
+    RequestBookRenew, RequestUserFines, RequestUserPolicy, RequestUserBorrowedV2,
+    RequestUserReservedV2, RequestUserHistory);
+
+impl Subject for RequestBookReturn {
+    fn subject(&self, db: &Connection) -> Option<u64> {
+        db.query_row(
+            "SELECT uid FROM lms_occupation WHERE iid = ?1 AND kind = 0",
+            [self.iid],
+            |row| row.get(0),
+        ).optional().ok()?
+    }
+}
+
+impl Subject for RequestHoldCancel {
+    fn subject(&self, db: &Connection) -> Option<u64> {
+        db.query_row(
+            "SELECT uid FROM lms_hold WHERE hid = ?1",
+            [self.hid],
+            |row| row.get(0),
+        ).optional().ok()?
+    }
+}
+
+/// Lets the caller through when the request concerns their own records, or
+/// when their role grants `permission` over everyone's.
+pub fn authorize<T: Subject>(
+    db: &Connection,
+    principal: &Principal,
+    permission: Permission,
+    req: &T,
+) -> ApiResult<()> {
+    if principal.role.permits(permission) {
+        return Ok(());
+    }
+    match req.subject(db) {
+        Some(uid) if uid == principal.uid => Ok(()),
+        _ => Err(ApiError::Forbidden),
+    }
+}
+
+/// Extracts the caller, rejecting those whose role lacks `permission`.
+pub fn authorized(
+    pool: Pool,
+    permission: Permission,
+) -> impl Filter<Extract = (Principal,), Error = Rejection> + Clone {
+    authenticated(pool).and_then(move |principal: Principal| async move {
+        if principal.role.permits(permission) {
+            Ok(principal)
+        } else {
+            Err(warp::reject::custom(ApiError::Forbidden))
+        }
+    })
+}
@@ -0,0 +1,61 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use log::info;
+use warp::Filter;
+
+/// Counts requests between being routed and having their reply produced, so
+/// shutdown can tell how many it had to abort.
+#[derive(Clone, Default)]
+pub struct InFlight {
+    count: Arc<AtomicUsize>,
+}
+
+/// Held for the lifetime of one request; dropping it, even when the request
+/// future is cancelled, marks the request as finished.
+pub struct InFlightGuard {
+    count: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// Extracts a guard that counts the request until it is dropped.
+    pub fn enter(&self) -> impl Filter<Extract = (InFlightGuard,), Error = Infallible> + Clone {
+        let count = self.count.clone();
+        warp::any().map(move || {
+            count.fetch_add(1, Ordering::SeqCst);
+            InFlightGuard {
+                count: count.clone(),
+            }
+        })
+    }
+}
+
+/// Resolves on the first SIGINT or, on Unix, SIGTERM.
+pub async fn signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for SIGINT");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
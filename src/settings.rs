@@ -0,0 +1,261 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_CONFIG_PATH: &str = "rdb_exp3.toml";
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Effective configuration: built-in defaults, then the TOML file, then `lms_*`
+/// environment variables.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub database: DatabaseSettings,
+    pub policy: PolicySettings,
+    pub features: FeatureSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: Vec<String>,
+    pub port: u16,
+    pub body_limit: u64,
+    pub log_level: String,
+    /// Read-only database connections; 0 means one per CPU core.
+    pub readers: usize,
+    /// Seconds to let in-flight requests finish after a shutdown signal.
+    pub drain_timeout: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    pub path: String,
+}
+
+/// Library-wide circulation defaults. A value set here overrides the one in
+/// `lms_metadata`; `lms_policy` rows still take precedence for their category.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicySettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_loans: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loan_period: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renewal_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_holds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fine_daily_rate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fine_borrow_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fine_replacement: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold_shelf_days: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_hours: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureSettings {
+    /// Lets anyone create a patron account through `user/register`.
+    pub registration: bool,
+    /// Serves the paginated `v2/` endpoints.
+    pub v2: bool,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            bind: vec!["127.0.0.1".to_string()],
+            port: 9998,
+            body_limit: 1024 * 16,
+            log_level: "error".to_string(),
+            readers: 0,
+            drain_timeout: 10,
+        }
+    }
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        DatabaseSettings {
+            path: "rdb_exp3.db".to_string(),
+        }
+    }
+}
+
+impl PolicySettings {
+    pub fn get(&self, key: &str) -> Option<u64> {
+        match key {
+            "max_loans" => self.max_loans,
+            "loan_period" => self.loan_period,
+            "renewal_limit" => self.renewal_limit,
+            "max_holds" => self.max_holds,
+            "fine_daily_rate" => self.fine_daily_rate,
+            "fine_borrow_limit" => self.fine_borrow_limit,
+            "fine_replacement" => self.fine_replacement,
+            "hold_shelf_days" => self.hold_shelf_days,
+            "session_hours" => self.session_hours,
+            _ => None,
+        }
+    }
+}
+
+impl Default for FeatureSettings {
+    fn default() -> Self {
+        FeatureSettings {
+            registration: true,
+            v2: true,
+        }
+    }
+}
+
+fn env_override<T: FromStr>(value: &mut T, key: &str) -> Result<(), String> {
+    if let Ok(raw) = std::env::var(key) {
+        *value = raw.parse::<T>()
+            .map_err(|_| format!("{} has an invalid value `{}`", key, raw))?;
+    }
+    Ok(())
+}
+
+fn env_override_opt<T: FromStr>(value: &mut Option<T>, key: &str) -> Result<(), String> {
+    if let Ok(raw) = std::env::var(key) {
+        *value = Some(raw.parse::<T>()
+            .map_err(|_| format!("{} has an invalid value `{}`", key, raw))?);
+    }
+    Ok(())
+}
+
+impl Settings {
+    /// Reads `path`, or `rdb_exp3.toml` when it exists, and applies the environment.
+    pub fn load(path: Option<&str>) -> Result<Settings, String> {
+        let mut settings = match path {
+            Some(path) => Settings::read(path)?,
+            None if std::path::Path::new(DEFAULT_CONFIG_PATH).exists() => Settings::read(DEFAULT_CONFIG_PATH)?,
+            None => Settings::default(),
+        };
+        settings.apply_env()?;
+        Ok(settings)
+    }
+
+    fn read(path: &str) -> Result<Settings, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path, err))?;
+        toml::from_str(&text).map_err(|err| format!("failed to parse {}: {}", path, err))
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        if let Ok(bind) = std::env::var("lms_bind") {
+            self.server.bind = bind.split(',').map(|addr| addr.trim().to_string()).collect();
+        }
+        env_override(&mut self.server.port, "lms_port")?;
+        env_override(&mut self.server.body_limit, "lms_body_limit")?;
+        env_override(&mut self.server.log_level, "lms_log_level")?;
+        env_override(&mut self.server.readers, "lms_readers")?;
+        env_override(&mut self.server.drain_timeout, "lms_drain_timeout")?;
+        env_override(&mut self.database.path, "lms_database")?;
+        let policy = &mut self.policy;
+        env_override_opt(&mut policy.max_loans, "lms_max_loans")?;
+        env_override_opt(&mut policy.loan_period, "lms_loan_period")?;
+        env_override_opt(&mut policy.renewal_limit, "lms_renewal_limit")?;
+        env_override_opt(&mut policy.max_holds, "lms_max_holds")?;
+        env_override_opt(&mut policy.fine_daily_rate, "lms_fine_daily_rate")?;
+        env_override_opt(&mut policy.fine_borrow_limit, "lms_fine_borrow_limit")?;
+        env_override_opt(&mut policy.fine_replacement, "lms_fine_replacement")?;
+        env_override_opt(&mut policy.hold_shelf_days, "lms_hold_shelf_days")?;
+        env_override_opt(&mut policy.session_hours, "lms_session_hours")?;
+        env_override(&mut self.features.registration, "lms_feature_registration")?;
+        env_override(&mut self.features.v2, "lms_feature_v2")?;
+        Ok(())
+    }
+
+    /// Every problem with the configuration, so `config check` can report them at once.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.server.bind.is_empty() {
+            problems.push("server.bind must list at least one address".to_string());
+        }
+        for addr in &self.server.bind {
+            if addr.parse::<IpAddr>().is_err() {
+                problems.push(format!("server.bind: `{}` is not an IP address", addr));
+            }
+        }
+        if self.server.port == 0 {
+            problems.push("server.port must not be 0".to_string());
+        }
+        if self.server.body_limit < 1024 {
+            problems.push("server.body_limit must be at least 1024 bytes".to_string());
+        }
+        if self.server.log_level.parse::<LevelFilter>().is_err() {
+            problems.push(format!("server.log_level: `{}` is not a log level", self.server.log_level));
+        }
+        if self.database.path.is_empty() {
+            problems.push("database.path must not be empty".to_string());
+        }
+        let policy = &self.policy;
+        for (key, value) in [
+            ("loan_period", policy.loan_period),
+            ("hold_shelf_days", policy.hold_shelf_days),
+            ("session_hours", policy.session_hours),
+        ] {
+            if value == Some(0) {
+                problems.push(format!("policy.{} must be at least 1", key));
+            }
+        }
+        problems
+    }
+
+    pub fn addresses(&self) -> Vec<IpAddr> {
+        self.server.bind.iter().filter_map(|addr| addr.parse().ok()).collect()
+    }
+
+    pub fn readers(&self) -> usize {
+        match self.server.readers {
+            0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            readers => readers,
+        }
+    }
+
+    /// Starts the logger at `server.log_level` unless `RUST_LOG` says otherwise.
+    pub fn init_logger(&self) {
+        env_logger::Builder::from_env(
+            env_logger::Env::default().default_filter_or(&self.server.log_level),
+        ).init();
+    }
+}
+
+pub fn init(settings: Settings) {
+    SETTINGS.set(settings).expect("Settings are already initialized");
+}
+
+/// Default settings for tests, which run without a configuration file.
+#[cfg(test)]
+pub fn init_for_tests() {
+    SETTINGS.get_or_init(Settings::default);
+}
+
+pub fn settings() -> &'static Settings {
+    SETTINGS.get().expect("Settings are not initialized")
+}
+
+pub fn main_check(settings: &Settings) {
+    println!("{}", toml::to_string_pretty(settings).unwrap());
+    let problems = settings.problems();
+    if problems.is_empty() {
+        println!("# configuration is valid");
+        return;
+    }
+    for problem in &problems {
+        eprintln!("error: {}", problem);
+    }
+    std::process::exit(1);
+}
@@ -15,4 +15,74 @@ pub fn is_username_legit(username: &str) -> bool {
 pub fn is_email_legit(email: &str) -> bool {
     let regex = Regex::new(r"^[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+\.[a-zA-Z0-9-.]+$").unwrap();
     regex.is_match(email)
-}
\ No newline at end of file
+}
+
+#[inline]
+pub fn is_password_legit(password: &str) -> bool {
+    (8..=512).contains(&password.chars().count())
+}
+/// Checks an ISBN-10 or ISBN-13, ignoring hyphens and spaces, and returns it
+/// as ISBN-13 digits. `None` when the length or check digit is wrong.
+#[inline]
+pub fn normalize_isbn(isbn: &str) -> Option<String> {
+    let isbn = isbn.chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<String>();
+    if !isbn.is_ascii() {
+        return None;
+    }
+    let digit = |c: char| c.to_digit(10);
+    let isbn13 = match isbn.len() {
+        10 => {
+            let (body, check) = isbn.split_at(9);
+            let body = body.chars().map(digit).collect::<Option<Vec<u32>>>()?;
+            let check = match check {
+                "X" => 10,
+                check => digit(check.chars().next()?)?,
+            };
+            let sum = body.iter().chain([check].iter())
+                .enumerate()
+                .map(|(i, d)| (10 - i as u32) * d)
+                .sum::<u32>();
+            if sum % 11 != 0 {
+                return None;
+            }
+            format!("978{}", &isbn[..9])
+        }
+        13 => isbn[..12].to_string(),
+        _ => return None,
+    };
+    let digits = isbn13.chars().map(digit).collect::<Option<Vec<u32>>>()?;
+    let sum = digits.iter()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+        .sum::<u32>();
+    let check = (10 - sum % 10) % 10;
+    if isbn.len() == 13 && digit(isbn.chars().last()?)? != check {
+        return None;
+    }
+    Some(format!("{isbn13}{check}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isbns_normalize_to_thirteen_digits() {
+        assert_eq!(normalize_isbn("978-0-441-17271-9").as_deref(), Some("9780441172719"));
+        assert_eq!(normalize_isbn(" 0441172717 ").as_deref(), Some("9780441172719"));
+        assert_eq!(normalize_isbn("0-8044-2957-x").as_deref(), Some("9780804429573"));
+    }
+
+    #[test]
+    fn malformed_isbns_are_rejected() {
+        assert_eq!(normalize_isbn("9780441172718"), None);
+        assert_eq!(normalize_isbn("0441172718"), None);
+        assert_eq!(normalize_isbn("97804411727"), None);
+        assert_eq!(normalize_isbn("978044117271X"), None);
+        assert_eq!(normalize_isbn("０４４１１７２７１７"), None);
+        assert_eq!(normalize_isbn(""), None);
+    }
+}